@@ -0,0 +1,78 @@
+// Times Dense and LSTM training on the CPU backend. Run it once against the
+// default build and once against `--features blas` to compare the two:
+//
+//   deno run bench:netsaur-cpu-training
+
+import {
+  Cost,
+  CPU,
+  DenseLayer,
+  LSTMLayer,
+  ReluLayer,
+  Sequential,
+  setupBackend,
+  tensor,
+} from "../mod.ts";
+
+await setupBackend(CPU);
+
+const samples = 256;
+
+function data(shape: number[]): Float32Array {
+  const size = shape.reduce((a, b) => a * b, samples);
+  return Float32Array.from({ length: size }, (_, i) => ((i * 7) % 5) / 5);
+}
+
+function labels(size: number): Float32Array {
+  return Float32Array.from({ length: samples * size }, (_, i) => i % 2);
+}
+
+const dense = new Sequential({
+  size: [64, 256],
+  silent: true,
+  seed: 1,
+  layers: [
+    DenseLayer({ size: [512] }),
+    ReluLayer(),
+    DenseLayer({ size: [512] }),
+    ReluLayer(),
+    DenseLayer({ size: [10] }),
+  ],
+  cost: Cost.MSE,
+});
+
+let start = performance.now();
+dense.train(
+  [{
+    inputs: tensor(data([256]), [samples, 256]),
+    outputs: tensor(labels(10), [samples, 10]),
+  }],
+  5,
+  4,
+  0.01,
+);
+console.log(`Dense: ${(performance.now() - start).toFixed(0)}ms`);
+
+const lstm = new Sequential({
+  size: [32, 20, 32],
+  silent: true,
+  seed: 1,
+  layers: [
+    LSTMLayer({ size: 128, returnSequences: true }),
+    LSTMLayer({ size: 128 }),
+    DenseLayer({ size: [1] }),
+  ],
+  cost: Cost.MSE,
+});
+
+start = performance.now();
+lstm.train(
+  [{
+    inputs: tensor(data([20, 32]), [samples, 20, 32]),
+    outputs: tensor(labels(1), [samples, 1]),
+  }],
+  5,
+  8,
+  0.01,
+);
+console.log(`LSTM: ${(performance.now() - start).toFixed(0)}ms`);
@@ -1,8 +1,8 @@
 use std::slice::{from_raw_parts, from_raw_parts_mut};
 
 use crate::{
-    decode_array, decode_json, length, Backend, Dataset, Logger, PredictOptions, TrainOptions,
-    RESOURCES,
+    decode_array, decode_json, insert_backend, length, remove_backend, with_backend, Backend,
+    Dataset, Logger, PredictOptions, TrainOptions,
 };
 
 type AllocBufferFn = extern "C" fn(usize) -> *mut u8;
@@ -20,13 +20,7 @@ pub extern "C" fn ffi_backend_create(ptr: *const u8, len: usize, alloc: AllocBuf
     let output_shape = unsafe { from_raw_parts_mut(size_ptr, buf.len()) };
     output_shape.copy_from_slice(buf.as_slice());
 
-    let mut len = 0;
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        len = backend.len();
-        backend.push(net_backend);
-    });
-    len
+    insert_backend(net_backend)
 }
 
 #[no_mangle]
@@ -36,7 +30,7 @@ pub extern "C" fn ffi_backend_train(
     buffer_len: usize,
     options_ptr: *const u8,
     options_len: usize,
-) {
+) -> bool {
     let buffer = unsafe { from_raw_parts(buffer_ptr, buffer_len) };
     let options: TrainOptions = decode_json(options_ptr, options_len);
 
@@ -50,10 +44,10 @@ pub extern "C" fn ffi_backend_train(
         });
     }
 
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        backend[id].train(datasets, options.epochs, options.batches, options.rate)
-    });
+    with_backend(id, |backend| {
+        backend.train(datasets, options.epochs, options.batches, options.rate)
+    })
+    .is_some()
 }
 
 #[no_mangle]
@@ -63,27 +57,32 @@ pub extern "C" fn ffi_backend_predict(
     options_ptr: *const u8,
     options_len: usize,
     output_ptr: *mut f32,
-) {
+) -> bool {
     let options: PredictOptions = decode_json(options_ptr, options_len);
     let inputs = decode_array(buffer_ptr, options.input_shape);
     let outputs = unsafe { from_raw_parts_mut(output_ptr, length(options.output_shape)) };
 
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        let res = backend[id].predict(inputs, options.layers);
+    with_backend(id, |backend| {
+        let res = backend.predict(inputs, options.layers);
         outputs.copy_from_slice(res.as_slice().unwrap());
-    });
+    })
+    .is_some()
 }
 
 #[no_mangle]
-pub extern "C" fn ffi_backend_save(id: usize, alloc: AllocBufferFn) {
-    RESOURCES.with(|cell| {
-        let backend = cell.backend.borrow_mut();
-        let data = backend[id].save();
+pub extern "C" fn ffi_backend_save(id: usize, alloc: AllocBufferFn) -> bool {
+    with_backend(id, |backend| {
+        let data = backend.save();
         let file_ptr = alloc(data.len());
         let file = unsafe { from_raw_parts_mut(file_ptr, data.len()) };
         file.copy_from_slice(data.as_slice());
-    });
+    })
+    .is_some()
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_free(id: usize) -> bool {
+    remove_backend(id)
 }
 
 #[no_mangle]
@@ -99,11 +98,5 @@ pub extern "C" fn ffi_backend_load(
     let output_shape = unsafe { from_raw_parts_mut(size_ptr, buf.len()) };
     output_shape.copy_from_slice(buf.as_slice());
 
-    let mut len = 0;
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        len = backend.len();
-        backend.push(net_backend);
-    });
-    len
+    insert_backend(net_backend)
 }
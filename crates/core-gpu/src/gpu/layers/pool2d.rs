@@ -56,7 +56,7 @@ impl Pool2DGPULayer {
                         let stride_x = (x + 1) * self.strides[1];
                         if self.max {
                             let mut max_index = (0, 0);
-                            let mut max_value = 0.0;
+                            let mut max_value = f32::NEG_INFINITY;
                             self.inputs
                                 .slice(s![b, c, input_y..stride_y, input_x..stride_x])
                                 .indexed_iter()
@@ -0,0 +1,69 @@
+use std::ops::{Add, Div, Mul, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{AdadeltaOptimizer, GPUScheduler};
+
+pub struct GPUAdadeltaOptimizer {
+    pub rho: f32,
+    pub epsilon: f32,
+    pub acc_sg: Vec<Vec<ArrayD<f32>>>,
+    pub acc_delta: Vec<Vec<ArrayD<f32>>>,
+}
+
+impl GPUAdadeltaOptimizer {
+    pub fn new(config: AdadeltaOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut acc_sg = Vec::new();
+        let mut acc_delta = Vec::new();
+        for params in params {
+            acc_sg.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+            acc_delta.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            rho: config.rho,
+            epsilon: config.epsilon,
+            acc_sg,
+            acc_delta,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        scheduler: &GPUScheduler,
+        rate: f32,
+        epoch: usize,
+    ) {
+        for (j, (param, grad)) in params.iter_mut().zip(grads).enumerate() {
+            self.acc_sg[idx][j] = self
+                .rho
+                .mul(&self.acc_sg[idx][j])
+                .add((1.0 - self.rho).mul(&grad.map(|x| x.powi(2))));
+
+            let delta = self.acc_delta[idx][j]
+                .map(|x| (x + self.epsilon).sqrt())
+                .div(self.acc_sg[idx][j].map(|x| (x + self.epsilon).sqrt()))
+                .mul(&grad);
+            self.acc_delta[idx][j] = self
+                .rho
+                .mul(&self.acc_delta[idx][j])
+                .add((1.0 - self.rho).mul(&delta.map(|x| x.powi(2))));
+
+            let rate = scheduler.eta(rate, epoch);
+
+            param.sub_assign(&rate.mul(delta))
+        }
+    }
+}
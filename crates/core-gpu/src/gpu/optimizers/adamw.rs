@@ -0,0 +1,79 @@
+use std::ops::{Add, Div, Mul, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{AdamWOptimizer, GPUScheduler};
+
+pub struct GPUAdamWOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+    pub weight_decay: f32,
+    pub m: Vec<Vec<ArrayD<f32>>>,
+    pub v: Vec<Vec<ArrayD<f32>>>,
+    pub t: f32,
+}
+
+impl GPUAdamWOptimizer {
+    pub fn new(config: AdamWOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut m = Vec::new();
+        let mut v = Vec::new();
+        for params in params {
+            m.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+            v.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            beta1: config.beta1,
+            beta2: config.beta2,
+            epsilon: config.epsilon,
+            weight_decay: config.weight_decay,
+            m,
+            v,
+            t: 0.0,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        scheduler: &GPUScheduler,
+        rate: f32,
+    ) {
+        for (j, (param, grad)) in params.iter_mut().zip(grads).enumerate() {
+            self.m[idx][j] = self
+                .beta1
+                .mul(&self.m[idx][j])
+                .add((1.0 - self.beta1).mul(&grad));
+            self.v[idx][j] = self
+                .beta2
+                .mul(&self.v[idx][j])
+                .add((1.0 - self.beta2).mul(&grad.map(|x| x.powi(2))));
+
+            let m_hat = self.m[idx][j].view().div(1.0 - self.beta1.powf(self.t));
+            let v_hat = self.v[idx][j].view().div(1.0 - self.beta2.powf(self.t));
+            let rate = scheduler.eta(rate, self.t as usize);
+
+            // the decay is applied to the weights directly instead of being
+            // folded into the gradient
+            let decay = param.mul(rate * self.weight_decay);
+            param.sub_assign(
+                &rate
+                    .mul(m_hat)
+                    .div(v_hat.map(|x| x.sqrt()).add(self.epsilon))
+                    .add(&decay),
+            );
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::ops::{Add, Mul, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{GPUScheduler, LionOptimizer};
+
+pub struct GPULionOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub weight_decay: f32,
+    pub m: Vec<Vec<ArrayD<f32>>>,
+}
+
+impl GPULionOptimizer {
+    pub fn new(config: LionOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut m = Vec::new();
+        for params in params {
+            m.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            beta1: config.beta1,
+            beta2: config.beta2,
+            weight_decay: config.weight_decay.unwrap_or(0.0),
+            m,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        scheduler: &GPUScheduler,
+        rate: f32,
+        epoch: usize,
+    ) {
+        for (j, (param, grad)) in params.iter_mut().zip(grads).enumerate() {
+            let update = self
+                .beta1
+                .mul(&self.m[idx][j])
+                .add((1.0 - self.beta1).mul(&grad))
+                .map(|x| if *x == 0.0 { 0.0 } else { x.signum() });
+            self.m[idx][j] = self
+                .beta2
+                .mul(&self.m[idx][j])
+                .add((1.0 - self.beta2).mul(&grad));
+
+            let rate = scheduler.eta(rate, epoch);
+
+            let decay = param.mul(self.weight_decay);
+            param.sub_assign(&rate.mul(update.add(&decay)));
+        }
+    }
+}
@@ -1,8 +1,20 @@
+mod adabelief;
+mod adadelta;
+mod adagrad;
 mod adam;
+mod adamw;
+mod lion;
+mod rmsprop;
 mod sgd;
 
+pub use adabelief::*;
+pub use adadelta::*;
+pub use adagrad::*;
 pub use adam::*;
+pub use adamw::*;
+pub use lion::*;
 use ndarray::{ArrayViewD, ArrayViewMutD};
+pub use rmsprop::*;
 pub use sgd::*;
 
 use crate::{GPULayer, GPUScheduler, Optimizer};
@@ -10,6 +22,12 @@ use crate::{GPULayer, GPUScheduler, Optimizer};
 pub enum GPUOptimizer {
     SGD(GPUSGDOptimizer),
     Adam(GPUAdamOptimizer),
+    RMSProp(GPURMSPropOptimizer),
+    AdamW(GPUAdamWOptimizer),
+    Adagrad(GPUAdagradOptimizer),
+    Adadelta(GPUAdadeltaOptimizer),
+    AdaBelief(GPUAdaBeliefOptimizer),
+    Lion(GPULionOptimizer),
 }
 
 impl GPUOptimizer {
@@ -21,10 +39,28 @@ impl GPUOptimizer {
             }
         }
         match optimizer {
-            Optimizer::SGD => GPUOptimizer::SGD(GPUSGDOptimizer::new()),
+            Optimizer::SGD(config) => GPUOptimizer::SGD(GPUSGDOptimizer::new(config, all_params)),
             Optimizer::Adam(config) => {
                 GPUOptimizer::Adam(GPUAdamOptimizer::new(config, all_params))
             }
+            Optimizer::RMSProp(config) => {
+                GPUOptimizer::RMSProp(GPURMSPropOptimizer::new(config, all_params))
+            }
+            Optimizer::AdamW(config) => {
+                GPUOptimizer::AdamW(GPUAdamWOptimizer::new(config, all_params))
+            }
+            Optimizer::Adagrad(config) => {
+                GPUOptimizer::Adagrad(GPUAdagradOptimizer::new(config, all_params))
+            }
+            Optimizer::Adadelta(config) => {
+                GPUOptimizer::Adadelta(GPUAdadeltaOptimizer::new(config, all_params))
+            }
+            Optimizer::AdaBelief(config) => {
+                GPUOptimizer::AdaBelief(GPUAdaBeliefOptimizer::new(config, all_params))
+            }
+            Optimizer::Lion(config) => {
+                GPUOptimizer::Lion(GPULionOptimizer::new(config, all_params))
+            }
         }
     }
 
@@ -37,6 +73,8 @@ impl GPUOptimizer {
     ) {
         match self {
             GPUOptimizer::Adam(adam) => adam.t += 1.0,
+            GPUOptimizer::AdamW(adamw) => adamw.t += 1.0,
+            GPUOptimizer::AdaBelief(adabelief) => adabelief.t += 1.0,
             _ => {}
         }
         let mut idx = 0;
@@ -44,11 +82,29 @@ impl GPUOptimizer {
             if let Some((params, grads)) = GPUOptimizer::get_params(layer) {
                 match self {
                     GPUOptimizer::SGD(sgd) => {
-                        sgd.update_grads(params, grads, scheduler, rate, epoch)
+                        sgd.update_grads(params, grads, idx, scheduler, rate, epoch)
                     }
                     GPUOptimizer::Adam(adam) => {
                         adam.update_grads(params, grads, idx, scheduler, rate)
                     }
+                    GPUOptimizer::RMSProp(rmsprop) => {
+                        rmsprop.update_grads(params, grads, idx, scheduler, rate, epoch)
+                    }
+                    GPUOptimizer::AdamW(adamw) => {
+                        adamw.update_grads(params, grads, idx, scheduler, rate)
+                    }
+                    GPUOptimizer::Adagrad(adagrad) => {
+                        adagrad.update_grads(params, grads, idx, scheduler, rate, epoch)
+                    }
+                    GPUOptimizer::Adadelta(adadelta) => {
+                        adadelta.update_grads(params, grads, idx, scheduler, rate, epoch)
+                    }
+                    GPUOptimizer::AdaBelief(adabelief) => {
+                        adabelief.update_grads(params, grads, idx, scheduler, rate)
+                    }
+                    GPUOptimizer::Lion(lion) => {
+                        lion.update_grads(params, grads, idx, scheduler, rate, epoch)
+                    }
                 }
                 idx += 1;
             }
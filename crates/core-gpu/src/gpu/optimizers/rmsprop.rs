@@ -0,0 +1,55 @@
+use std::ops::{Add, Div, Mul, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{GPUScheduler, RMSPropOptimizer};
+
+pub struct GPURMSPropOptimizer {
+    pub decay_rate: f32,
+    pub epsilon: f32,
+    pub acc_sg: Vec<Vec<ArrayD<f32>>>,
+}
+
+impl GPURMSPropOptimizer {
+    pub fn new(config: RMSPropOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut acc_sg = Vec::new();
+        for params in params {
+            acc_sg.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            acc_sg,
+            decay_rate: config.decay_rate,
+            epsilon: config.epsilon,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        scheduler: &GPUScheduler,
+        rate: f32,
+        epoch: usize,
+    ) {
+        for (j, (param, grad)) in params.iter_mut().zip(grads).enumerate() {
+            self.acc_sg[idx][j] = self
+                .decay_rate
+                .mul(&self.acc_sg[idx][j])
+                .add((1.0 - self.decay_rate).mul(&grad.map(|x| x.powi(2))));
+
+            let rate = scheduler.eta(rate, epoch);
+
+            param.sub_assign(
+                &rate
+                    .mul(&grad)
+                    .div(self.acc_sg[idx][j].map(|x| x.sqrt()).add(self.epsilon)),
+            )
+        }
+    }
+}
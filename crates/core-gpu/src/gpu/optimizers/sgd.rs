@@ -1,27 +1,66 @@
-use std::ops::{Mul, SubAssign};
+use std::ops::{Add, Mul, SubAssign};
 
-use ndarray::{ArrayViewD, ArrayViewMutD};
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
 
-use crate::GPUScheduler;
+use crate::{GPUScheduler, SGDOptimizer};
 
-pub struct GPUSGDOptimizer {}
+pub struct GPUSGDOptimizer {
+    pub momentum: f32,
+    pub nesterov: bool,
+    pub velocity: Vec<Vec<ArrayD<f32>>>,
+}
 
 impl GPUSGDOptimizer {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: Option<SGDOptimizer>, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let (momentum, nesterov) = match config {
+            Some(config) => (config.momentum, config.nesterov.unwrap_or(false)),
+            None => (0.0, false),
+        };
+        let mut velocity = Vec::new();
+        if momentum != 0.0 {
+            for params in params {
+                velocity.push(
+                    params
+                        .iter()
+                        .map(|param| ArrayD::zeros(param.dim()))
+                        .collect(),
+                );
+            }
+        }
+        Self {
+            momentum,
+            nesterov,
+            velocity,
+        }
     }
 
     pub fn update_grads(
         &mut self,
         mut params: Vec<ArrayViewMutD<f32>>,
         grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
         scheduler: &GPUScheduler,
         rate: f32,
         epoch: usize,
     ) {
         let eta = scheduler.eta(rate, epoch);
-        for (param, grad) in params.iter_mut().zip(grads) {
-            param.sub_assign(&grad.mul(eta));
+        for (j, (param, grad)) in params.iter_mut().zip(grads).enumerate() {
+            if self.momentum == 0.0 {
+                param.sub_assign(&grad.mul(eta));
+                continue;
+            }
+            self.velocity[idx][j] = self.momentum.mul(&self.velocity[idx][j]).add(&grad);
+            if self.nesterov {
+                param.sub_assign(
+                    &self
+                        .momentum
+                        .mul(&self.velocity[idx][j])
+                        .add(&grad)
+                        .mul(eta),
+                );
+            } else {
+                param.sub_assign(&self.velocity[idx][j].view().mul(eta));
+            }
         }
     }
 }
@@ -7,6 +7,7 @@ mod util;
 pub use gpu::*;
 
 pub use ffi::*;
+pub use netsaur_registry::Registry;
 pub use tensor::*;
 pub use types::*;
 pub use util::*;
@@ -14,13 +15,13 @@ pub use util::*;
 use std::cell::RefCell;
 
 pub struct Resources {
-    pub backend: RefCell<Vec<Backend>>,
+    pub backend: RefCell<Registry<Backend>>,
 }
 
 impl Resources {
     pub fn new() -> Self {
         Self {
-            backend: RefCell::new(Vec::new()),
+            backend: RefCell::new(Registry::new()),
         }
     }
 }
@@ -28,3 +29,19 @@ impl Resources {
 thread_local! {
     pub static RESOURCES: Resources = Resources::new();
 }
+
+/// Registers a backend and returns its id.
+pub fn insert_backend(net_backend: Backend) -> usize {
+    RESOURCES.with(|cell| cell.backend.borrow_mut().insert(net_backend))
+}
+
+/// Runs `f` against the backend registered under `id`, returning `None` for
+/// ids that were never issued or have been freed.
+pub fn with_backend<T>(id: usize, f: impl FnOnce(&mut Backend) -> T) -> Option<T> {
+    RESOURCES.with(|cell| cell.backend.borrow_mut().get_mut(id).map(f))
+}
+
+/// Drops the backend registered under `id`, returning whether it existed.
+pub fn remove_backend(id: usize) -> bool {
+    RESOURCES.with(|cell| cell.backend.borrow_mut().remove(id).is_some())
+}
@@ -134,19 +134,66 @@ pub struct AdamOptimizer {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "camelCase")]
 pub struct RMSPropOptimizer {
     pub decay_rate: f32,
     pub epsilon: f32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SGDOptimizer {
+    pub momentum: f32,
+    pub nesterov: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdamWOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+    pub weight_decay: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdagradOptimizer {
+    pub epsilon: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdadeltaOptimizer {
+    pub rho: f32,
+    pub epsilon: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdaBeliefOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LionOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub weight_decay: Option<f32>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "config")]
 #[serde(rename_all = "lowercase")]
 pub enum Optimizer {
-    SGD,
+    SGD(Option<SGDOptimizer>),
     Adam(AdamOptimizer),
     RMSProp(RMSPropOptimizer),
+    AdamW(AdamWOptimizer),
+    Adagrad(AdagradOptimizer),
+    Adadelta(AdadeltaOptimizer),
+    AdaBelief(AdaBeliefOptimizer),
+    Lion(LionOptimizer),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
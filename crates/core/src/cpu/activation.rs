@@ -39,6 +39,15 @@ impl CPUActivation {
         }
     }
 
+    /// Derivative at `x`, where `y` is the activated value. `prime` takes
+    /// the output for sigmoid and the input for the other activations.
+    pub fn derivative(&self, x: &f32, y: &f32) -> f32 {
+        match self.activation {
+            Activation::Sigmoid => (self.prime)(y),
+            _ => (self.prime)(x),
+        }
+    }
+
     pub fn memoize_output(activation: &CPUActivation) -> bool {
         match activation.activation {
             Activation::Sigmoid | Activation::Tanh => true,
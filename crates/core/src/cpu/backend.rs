@@ -1,23 +1,36 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
-use ndarray::{ArrayD, ArrayViewD, IxDyn};
+use ndarray::{concatenate, ArrayD, ArrayViewD, Axis, IxDyn};
+use ndarray_rand::rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use safetensors::{serialize, SafeTensors};
 
 use crate::{
-    to_arr, ActivationCPULayer, BackendConfig, BatchNorm1DCPULayer, BatchNorm2DCPULayer,
-    BatchNormTensors, CPUCost, CPULayer, CPUOptimizer, CPUPostProcessor, CPUScheduler,
-    Conv2DCPULayer, ConvTensors, ConvTranspose2DCPULayer, Dataset, DenseCPULayer, DenseTensors,
-    Dropout1DCPULayer, Dropout2DCPULayer, FlattenCPULayer, GetTensor, Layer, Logger,
-    Pool2DCPULayer, PostProcessor, SoftmaxCPULayer, Tensor, Tensors, Timer,
+    evaluate_metrics, seeded_rng, to_arr, validate, validate_tensors, ActivationCPULayer,
+    BackendConfig, BatchNorm1DCPULayer, BatchNorm2DCPULayer, BatchNormTensors, CPUCost, CPULayer,
+    CPUOptimizer, CPUPostProcessor, CPUScheduler, Conv2DCPULayer, ConvTensors,
+    ConvTranspose2DCPULayer, Dataset, DenseCPULayer, DenseTensors, Dropout1DCPULayer,
+    Dropout2DCPULayer, EmbeddingTensors, FlattenCPULayer, GetTensor, Layer, LayerParam,
+    LayerSummary, Logger, Metric, MetricsReport, Monitor, NetsaurError, NetsaurResult,
+    Pool2DCPULayer, PostProcessor, RecurrentTensors, SoftmaxCPULayer, Summary, Tensor, Tensors,
+    Timer, TrainOptions, TrainingState,
 };
 
-use super::{EmbeddingCPULayer, LSTMCPULayer};
+use super::{EmbeddingCPULayer, GRUCPULayer, LSTMCPULayer};
+
+/// Version of the saved model format, stored under `format_version` in the
+/// safetensors metadata. Files without it key tensors by layer index.
+pub const FORMAT_VERSION: u32 = 2;
 
 pub struct Backend {
     pub silent: bool,
     pub config: BackendConfig,
     pub tolerance: f32,
     pub patience: usize,
+    pub monitor: Monitor,
+    pub state: TrainingState,
+    /// The model of the best epoch so far, as written by `save`, which early
+    /// stopping restores. Checkpoints carry it so a resumed run keeps it.
+    pub best_net: Option<Vec<u8>>,
     pub layers: Vec<CPULayer>,
     pub size: Vec<usize>,
     pub cost: CPUCost,
@@ -25,6 +38,7 @@ pub struct Backend {
     pub scheduler: CPUScheduler,
     pub logger: Logger,
     pub timer: Timer,
+    pub rng: StdRng,
 }
 
 impl Backend {
@@ -34,21 +48,23 @@ impl Backend {
         timer: Timer,
         mut tensors: Option<Vec<Tensors>>,
     ) -> Self {
+        let mut rng = seeded_rng(config.seed);
         let mut layers = Vec::new();
         let mut size = config.size.clone();
-        for layer in config.layers.iter() {
-            match layer.clone() {
+        for entry in config.layers.iter() {
+            match entry.layer.clone() {
                 Layer::Activation(config) => {
                     let layer = ActivationCPULayer::new(config, IxDyn(&size));
                     layers.push(CPULayer::Activation(layer));
                 }
                 Layer::Conv2D(config) => {
-                    let layer = Conv2DCPULayer::new(config, IxDyn(&size), tensors.get());
+                    let layer = Conv2DCPULayer::new(config, IxDyn(&size), tensors.get(), &mut rng);
                     size = layer.output_size().to_vec();
                     layers.push(CPULayer::Conv2D(layer));
                 }
                 Layer::ConvTranspose2D(config) => {
-                    let layer = ConvTranspose2DCPULayer::new(config, IxDyn(&size), tensors.get());
+                    let layer =
+                        ConvTranspose2DCPULayer::new(config, IxDyn(&size), tensors.get(), &mut rng);
                     size = layer.output_size().to_vec();
                     layers.push(CPULayer::ConvTranspose2D(layer));
                 }
@@ -69,12 +85,13 @@ impl Backend {
                     layers.push(CPULayer::Dropout2D(layer));
                 }
                 Layer::Dense(config) => {
-                    let layer = DenseCPULayer::new(config, IxDyn(&size), tensors.get());
+                    let layer = DenseCPULayer::new(config, IxDyn(&size), tensors.get(), &mut rng);
                     size = layer.output_size().to_vec();
                     layers.push(CPULayer::Dense(layer));
                 }
                 Layer::Embedding(config) => {
-                    let layer = EmbeddingCPULayer::new(config, IxDyn(&size));
+                    let layer =
+                        EmbeddingCPULayer::new(config, IxDyn(&size), tensors.get(), &mut rng);
                     size = layer.output_size().to_vec();
                     layers.push(CPULayer::Embedding(layer));
                 }
@@ -84,10 +101,15 @@ impl Backend {
                     layers.push(CPULayer::Flatten(layer));
                 }
                 Layer::LSTM(config) => {
-                    let layer = LSTMCPULayer::new(config, IxDyn(&size), None);
+                    let layer = LSTMCPULayer::new(config, IxDyn(&size), tensors.get(), &mut rng);
                     size = layer.output_size().to_vec();
                     layers.push(CPULayer::LSTM(layer));
                 }
+                Layer::GRU(config) => {
+                    let layer = GRUCPULayer::new(config, IxDyn(&size), tensors.get(), &mut rng);
+                    size = layer.output_size().to_vec();
+                    layers.push(CPULayer::GRU(layer));
+                }
                 Layer::Pool2D(config) => {
                     let layer = Pool2DCPULayer::new(config, IxDyn(&size));
                     size = layer.output_size().to_vec();
@@ -105,18 +127,23 @@ impl Backend {
         let silent = config.silent.is_some_and(|x| x == true);
         let tolerance = config.tolerance.unwrap_or(0.0);
         let patience = config.patience.unwrap_or(0);
+        let monitor = config.monitor.clone().unwrap_or(Monitor::Loss);
         Self {
             logger,
             silent,
             config,
             tolerance,
             patience,
+            monitor,
+            state: TrainingState::default(),
+            best_net: None,
             layers,
             cost,
             optimizer,
             scheduler,
             size,
             timer,
+            rng,
         }
     }
 
@@ -133,15 +160,16 @@ impl Backend {
                         .layers
                         .get_mut(layer_index)
                         .expect(&format!("Layer #{} does not exist.", layer_index));
-                    inputs = layer.forward_propagate(inputs, training);
+                    let training = training && self.config.layers[layer_index].trainable();
+                    inputs = layer.forward_propagate(inputs, training, &mut self.rng);
                 }
             }
             None => {
-      //          let mut i = 0;
-                for layer in &mut self.layers {
-       //             i += 1;
-       //             println!("\n\nLayer +{}: {:?}", i, &inputs);
-                    inputs = layer.forward_propagate(inputs, training);
+                // Frozen layers run in inference mode, so a frozen BatchNorm
+                // normalizes with its running statistics and keeps them.
+                for (layer, entry) in self.layers.iter_mut().zip(&self.config.layers) {
+                    let training = training && entry.trainable();
+                    inputs = layer.forward_propagate(inputs, training, &mut self.rng);
                 }
             }
         }
@@ -153,47 +181,298 @@ impl Backend {
         outputs: ArrayViewD<'b, f32>,
         data: ArrayViewD<'b, f32>,
     ) -> ArrayD<f32> {
- //       println!("\n\nOutput: {:?}", &outputs);
         let mut d_outputs = (self.cost.prime)(outputs, data);
-   //     println!("\n\nD Output: {:?}", &d_outputs);
-   //     let mut i = 0;
-        for layer in self.layers.iter_mut().rev() {
-  //          i += 1;
-            d_outputs = layer.backward_propagate(d_outputs);
-  //          println!("\n\nLayer -{}: {:?}", i, &d_outputs);
+        // The frozen layers in front of the first trainable one need no
+        // gradients, so backpropagation stops there.
+        let first = self
+            .config
+            .layers
+            .iter()
+            .position(|entry| entry.trainable())
+            .unwrap_or(self.layers.len());
+        // Frozen layers behind it only pass the gradient on.
+        let layers = self.layers.iter_mut().zip(&self.config.layers);
+        for (layer, entry) in layers.skip(first).rev() {
+            d_outputs = layer.backward_propagate(d_outputs, entry.trainable());
         }
         d_outputs
     }
 
-    pub fn train(&mut self, datasets: Vec<Dataset>, epochs: usize, batches: usize, rate: f32) {
-        let mut epoch = 0;
-        let mut best_cost = -1f32;
-        let mut disappointments = 0;
-        let mut best_net = self.save();
-        let mut cost = 0f32;
+    /// Checks that the trailing dimensions of a tensor match the expected shape.
+    fn check_shape(msg: &'static str, expected: &[usize], got: &[usize]) -> NetsaurResult<()> {
+        if expected.len() != got.len() || expected[1..] != got[1..] {
+            return Err(NetsaurError::ShapeMismatch {
+                msg,
+                expected: expected.to_vec(),
+                got: got.to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that every dataset has samples that fit the model.
+    fn check_datasets(&self, datasets: &[Dataset]) -> NetsaurResult<()> {
+        for dataset in datasets {
+            Self::check_shape(
+                "invalid input shape",
+                &self.config.size,
+                dataset.inputs.shape(),
+            )?;
+            Self::check_shape("invalid output shape", &self.size, dataset.outputs.shape())?;
+            if dataset.inputs.shape()[0] == 0 || dataset.outputs.shape()[0] == 0 {
+                return Err(NetsaurError::InvalidOption(
+                    "datasets must contain at least one sample".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits off the trailing `fraction` of samples to use for validation.
+    pub fn split_validation(
+        mut datasets: Vec<Dataset>,
+        fraction: f32,
+    ) -> NetsaurResult<(Vec<Dataset>, Vec<Dataset>)> {
+        if !(0.0..1.0).contains(&fraction) {
+            return Err(NetsaurError::InvalidOption(format!(
+                "validation split must be in [0, 1), got {}",
+                fraction
+            )));
+        }
+        let samples: usize = datasets.iter().map(|x| x.inputs.shape()[0]).sum();
+        let mut remaining = (samples as f32 * fraction).round() as usize;
+        let mut validation = Vec::new();
+        while remaining > 0 {
+            let dataset = datasets.pop().unwrap();
+            let batches = dataset.inputs.shape()[0];
+            if batches <= remaining {
+                remaining -= batches;
+                validation.insert(0, dataset);
+            } else {
+                let split = batches - remaining;
+                let (inputs, held_inputs) = dataset.inputs.view().split_at(Axis(0), split);
+                let (outputs, held_outputs) = dataset.outputs.view().split_at(Axis(0), split);
+                validation.insert(
+                    0,
+                    Dataset {
+                        inputs: held_inputs.to_owned(),
+                        outputs: held_outputs.to_owned(),
+                    },
+                );
+                datasets.push(Dataset {
+                    inputs: inputs.to_owned(),
+                    outputs: outputs.to_owned(),
+                });
+                remaining = 0;
+            }
+        }
+        if datasets.is_empty() {
+            return Err(NetsaurError::InvalidOption(
+                "validation split leaves no training data".to_string(),
+            ));
+        }
+        Ok((datasets, validation))
+    }
+
+    /// Joins the datasets into a single dataset along the batch axis.
+    fn concatenate(datasets: &[Dataset]) -> NetsaurResult<Dataset> {
+        let inputs: Vec<_> = datasets.iter().map(|x| x.inputs.view()).collect();
+        let outputs: Vec<_> = datasets.iter().map(|x| x.outputs.view()).collect();
+        Ok(Dataset {
+            inputs: concatenate(Axis(0), &inputs)?,
+            outputs: concatenate(Axis(0), &outputs)?,
+        })
+    }
+
+    /// Mixes the seed with the epoch, so neighbouring seeds do not replay
+    /// each other's epochs one epoch apart.
+    fn epoch_seed(seed: u64, epoch: usize) -> u64 {
+        seed ^ (epoch as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+
+    /// Returns the order in which to visit `len` items, shuffled with `rng`
+    /// when requested.
+    fn permutation(len: usize, shuffle: bool, rng: &mut StdRng) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..len).collect();
+        if shuffle {
+            order.shuffle(rng);
+        }
+        order
+    }
+
+    /// Slices the samples of `dataset` into batches of `batch_size` in the
+    /// given order, the final batch holds whatever remains.
+    fn minibatches(dataset: &Dataset, batch_size: usize, order: &[usize]) -> Vec<Dataset> {
+        order
+            .chunks(batch_size)
+            .map(|indices| Dataset {
+                inputs: dataset.inputs.select(Axis(0), indices),
+                outputs: dataset.outputs.select(Axis(0), indices),
+            })
+            .collect()
+    }
+
+    /// Computes the cost and the requested metrics over the datasets in
+    /// inference mode.
+    pub fn evaluate(
+        &mut self,
+        datasets: &[Dataset],
+        metrics: &[Metric],
+    ) -> NetsaurResult<MetricsReport> {
+        if datasets.is_empty() {
+            return Err(NetsaurError::InvalidOption(
+                "evaluate requires at least one dataset".to_string(),
+            ));
+        }
+        self.check_datasets(datasets)?;
+        let mut total = 0.0;
+        let mut samples = 0;
+        let mut outputs = Vec::new();
+        for dataset in datasets {
+            let batches = dataset.inputs.shape()[0];
+            let output = self.forward_propagate(dataset.inputs.clone(), false, None);
+            total += (self.cost.cost)(output.view(), dataset.outputs.view()) * batches as f32;
+            samples += batches;
+            outputs.push(output);
+        }
+        let loss = total / samples as f32;
+        if metrics.is_empty() {
+            return Ok(MetricsReport {
+                loss,
+                ..Default::default()
+            });
+        }
+        let y_hat = concatenate(
+            Axis(0),
+            &outputs.iter().map(|x| x.view()).collect::<Vec<_>>(),
+        )?;
+        let y = concatenate(
+            Axis(0),
+            &datasets
+                .iter()
+                .map(|x| x.outputs.view())
+                .collect::<Vec<_>>(),
+        )?;
+        Ok(evaluate_metrics(metrics, y_hat.view(), y.view(), loss))
+    }
+
+    pub fn train(
+        &mut self,
+        datasets: Vec<Dataset>,
+        validation: Vec<Dataset>,
+        options: &TrainOptions,
+    ) -> NetsaurResult<()> {
+        let (datasets, validation) = match options.validation_split {
+            Some(fraction) if validation.is_empty() => Self::split_validation(datasets, fraction)?,
+            _ => (datasets, validation),
+        };
+        if datasets.is_empty() {
+            return Err(NetsaurError::InvalidOption(
+                "train requires at least one dataset".to_string(),
+            ));
+        }
+        self.check_datasets(&datasets)?;
+        self.check_datasets(&validation)?;
+        if self.monitor == Monitor::ValLoss && validation.is_empty() {
+            return Err(NetsaurError::InvalidOption(
+                "monitoring val_loss requires validation data".to_string(),
+            ));
+        }
+        if options.batches == 0 {
+            return Err(NetsaurError::InvalidOption(
+                "batches must be positive".to_string(),
+            ));
+        }
+        let shuffle = options.shuffle.unwrap_or(false);
+        let (samples, mut datasets) = match options.batch_size {
+            Some(0) => {
+                return Err(NetsaurError::InvalidOption(
+                    "batch size must be positive".to_string(),
+                ))
+            }
+            Some(batch_size) => (
+                Some((Self::concatenate(&datasets)?, batch_size)),
+                Vec::new(),
+            ),
+            None => (None, datasets),
+        };
+        let (epochs, batches, rate) = (options.epochs, options.batches, options.rate);
+        let metrics = options.metrics.clone().unwrap_or_default();
+        if !options.resume.unwrap_or(false) {
+            self.state = TrainingState::default();
+            self.scheduler = CPUScheduler::from(&self.config.scheduler);
+            self.best_net = None;
+        }
+        let mut epoch = self.state.epoch;
+        let mut best_cost = self.state.best_cost;
+        let mut disappointments = self.state.disappointments;
+        let mut best_net = self.best_net.take().unwrap_or_else(|| self.save());
         let mut time: u128;
         let mut total_time = 0u128;
         let start = (self.timer.now)();
-        let total_iter = epochs * datasets.len();
+        let first_epoch = epoch;
+        let steps_per_epoch = match &samples {
+            Some((samples, batch_size)) => samples.inputs.shape()[0].div_ceil(*batch_size),
+            None => datasets.len(),
+        };
+        let total_iter = (epochs.max(first_epoch) - first_epoch) * steps_per_epoch;
+        let trainable: Vec<bool> = self.config.layers.iter().map(|x| x.trainable()).collect();
         while epoch < epochs {
             let mut total = 0.0;
-            for (i, dataset) in datasets.iter().enumerate() {
+            let mut total_norm = 0.0;
+            let mut steps = 0;
+            let mut window = 0;
+            let mut seen = 0;
+            let mut epoch_total = 0.0;
+            // A seeded run reseeds every epoch, so shuffling and dropout only
+            // depend on the seed and the epoch and a resumed run matches an
+            // uninterrupted one.
+            if let Some(seed) = options.seed.or(self.config.seed) {
+                self.rng = StdRng::seed_from_u64(Self::epoch_seed(seed, epoch));
+            }
+            let order = match &samples {
+                Some((samples, batch_size)) => {
+                    let len = samples.inputs.shape()[0];
+                    let order = Self::permutation(len, shuffle, &mut self.rng);
+                    datasets = Self::minibatches(samples, *batch_size, &order);
+                    (0..datasets.len()).collect()
+                }
+                None => Self::permutation(datasets.len(), shuffle, &mut self.rng),
+            };
+            for (i, dataset) in order.iter().map(|i| &datasets[*i]).enumerate() {
                 let outputs = self.forward_propagate(dataset.inputs.clone(), true, None);
                 self.backward_propagate(outputs.view(), dataset.outputs.view());
-                self.optimizer
-                    .update_grads(&mut self.layers, &self.scheduler, rate, epoch);
-                total += (self.cost.cost)(outputs.view(), dataset.outputs.view());
+                total_norm += self.optimizer.update_grads(
+                    &mut self.layers,
+                    &trainable,
+                    &self.scheduler,
+                    rate,
+                    epoch,
+                    self.config.clipping.as_ref(),
+                );
+                // Costs are weighted by the batch size, so a smaller final
+                // batch counts for its samples only.
                 let minibatch = outputs.dim()[0];
-                if !self.silent && ((i + 1) * minibatch) % batches == 0 {
-                    cost = total / (batches) as f32;
+                let batch_cost = (self.cost.cost)(outputs.view(), dataset.outputs.view());
+                total += batch_cost * minibatch as f32;
+                epoch_total += batch_cost * minibatch as f32;
+                steps += 1;
+                window += minibatch;
+                seen += minibatch;
+                // Logs whenever the sample count crosses a multiple of
+                // `batches`, whatever the size of the batches.
+                if !self.silent && seen / batches > (seen - minibatch) / batches {
+                    let cost = total / window as f32;
                     time = ((self.timer.now)() - start) - total_time;
                     total_time += time;
-                    let current_iter = epoch * datasets.len() + i;
+                    let current_iter = (epoch - first_epoch) * steps_per_epoch + i;
                     let msg = format!(
-                        "Epoch={}, Dataset={}, Cost={}, Time={:.3}s, ETA={:.3}s",
+                        "Epoch={}, Dataset={}, Cost={}, GradNorm={}, Rate={}, Time={:.3}s, ETA={:.3}s",
                         epoch,
-                        i * minibatch,
+                        seen,
                         cost,
+                        total_norm / steps as f32,
+                        self.scheduler.eta(rate, epoch),
                         (time as f32) / 1000.0,
                         (((total_time as f32) / current_iter as f32)
                             * (total_iter - current_iter) as f32)
@@ -201,9 +480,32 @@ impl Backend {
                     );
                     (self.logger.log)(msg);
                     total = 0.0;
+                    total_norm = 0.0;
+                    steps = 0;
+                    window = 0;
+                }
+            }
+            let mut epoch_cost = epoch_total / seen as f32;
+            let mut cost = epoch_cost;
+            if !validation.is_empty() {
+                let report = self.evaluate(&validation, &metrics)?;
+                epoch_cost = report.loss;
+                if !self.silent {
+                    let mut msg = format!("Epoch={}, Validation Cost={}", epoch, report.loss);
+                    if !metrics.is_empty() {
+                        msg = format!("{}, {}", msg, report.log());
+                    }
+                    (self.logger.log)(msg);
+                }
+                if self.monitor == Monitor::ValLoss {
+                    cost = report.loss;
                 }
+            } else if !metrics.is_empty() && !self.silent {
+                let report = self.evaluate(&datasets, &metrics)?;
+                (self.logger.log)(format!("Epoch={}, {}", epoch, report.log()));
             }
-            if self.patience != 0 && cost != 0.0 {
+            self.scheduler.observe(epoch_cost);
+            if self.patience != 0 {
                 if best_cost < 0.0 {
                     best_cost = cost;
                 }
@@ -227,111 +529,918 @@ impl Backend {
                             disappointments, best_cost
                         ));
                     }
-                    let net = Self::load(&best_net, self.logger.clone(), self.timer.clone());
+                    let net = Self::load(&best_net, self.logger.clone(), self.timer.clone())?;
                     self.layers = net.layers;
-                    break;
+                    self.best_net = Some(best_net);
+                    return Ok(());
                 }
             }
-            epoch += 1
+            epoch += 1;
+            self.state = TrainingState {
+                epoch,
+                step: self.optimizer.step(),
+                best_cost,
+                disappointments,
+                plateau: self.scheduler.plateau().cloned(),
+            };
         }
+        self.best_net = Some(best_net);
+        Ok(())
     }
 
     pub fn predict(
-        &mut self,
+        &self,
         data: ArrayD<f32>,
         postprocess: PostProcessor,
         layers: Option<Vec<usize>>,
-    ) -> ArrayD<f32> {
+    ) -> NetsaurResult<ArrayD<f32>> {
+        match &layers {
+            Some(indices) => {
+                if let Some(index) = indices.iter().find(|i| **i >= self.layers.len()) {
+                    return Err(NetsaurError::UnknownLayer(*index));
+                }
+                // every selected layer has to accept what the previous one
+                // returns, not only the first
+                let shapes = validate(&self.config)?;
+                let mut shape = data.shape();
+                for index in indices {
+                    let expected = match index {
+                        0 => &self.config.size,
+                        _ => &shapes[index - 1],
+                    };
+                    Self::check_shape("invalid input shape", expected, shape)?;
+                    shape = &shapes[*index];
+                }
+            }
+            None => Self::check_shape("invalid input shape", &self.config.size, data.shape())?,
+        }
         let processor = CPUPostProcessor::from(&postprocess);
-        for layer in &mut self.layers {
-            layer.reset(1);
+        let indices = layers.unwrap_or_else(|| (0..self.layers.len()).collect());
+        let res = indices
+            .into_iter()
+            .fold(data, |inputs, index| self.layers[index].infer(inputs));
+        Ok(processor.process(res))
+    }
+
+    pub fn summary(&self) -> NetsaurResult<Summary> {
+        let shapes = validate(&self.config)?;
+        let mut input_shape = self.config.size.clone();
+        let mut layers = Vec::new();
+        let (mut trainable_params, mut non_trainable_params) = (0, 0);
+        let names = self.config.layer_names();
+        for (((entry, layer), output_shape), name) in self
+            .config
+            .layers
+            .iter()
+            .zip(&self.layers)
+            .zip(shapes)
+            .zip(names)
+        {
+            let (trainable, non_trainable) = match layer.params() {
+                (trainable, non_trainable) if !entry.trainable() => (0, trainable + non_trainable),
+                params => params,
+            };
+            trainable_params += trainable;
+            non_trainable_params += non_trainable;
+            layers.push(LayerSummary {
+                layer: entry.layer.name().to_string(),
+                name,
+                input_shape,
+                output_shape: output_shape.clone(),
+                trainable_params: trainable,
+                non_trainable_params: non_trainable,
+            });
+            input_shape = output_shape;
         }
-        let res = self.forward_propagate(data, false, layers);
-        processor.process(res)
+        let bytes = std::mem::size_of::<f32>();
+        Ok(Summary {
+            layers,
+            trainable_params,
+            non_trainable_params,
+            total_params: trainable_params + non_trainable_params,
+            parameter_bytes: (trainable_params + non_trainable_params) * bytes,
+            gradient_bytes: trainable_params * bytes,
+            optimizer_bytes: self.optimizer.state_len() * bytes,
+        })
     }
 
-    pub fn save(&self) -> Vec<u8> {
+    /// Freezes the layers in `range`, so training keeps their parameters
+    /// and BatchNorm statistics fixed. Saved models remember frozen layers.
+    pub fn freeze(&mut self, range: Range<usize>) -> NetsaurResult<()> {
+        self.set_trainable(range, false)
+    }
+
+    /// Makes the layers in `range` trainable again.
+    pub fn unfreeze(&mut self, range: Range<usize>) -> NetsaurResult<()> {
+        self.set_trainable(range, true)
+    }
+
+    fn set_trainable(&mut self, range: Range<usize>, trainable: bool) -> NetsaurResult<()> {
+        if range.start > range.end || range.end > self.layers.len() {
+            return Err(NetsaurError::InvalidConfig(format!(
+                "layer range {:?} does not fit a model of {} layers",
+                range,
+                self.layers.len()
+            )));
+        }
+        for entry in &mut self.config.layers[range] {
+            entry.trainable = (!trainable).then_some(false);
+        }
+        Ok(())
+    }
+
+    /// Collects the weights of every layer under their save keys.
+    pub(crate) fn tensors(&self) -> Vec<(String, Tensor<'_>)> {
         let mut tensors = Vec::new();
-        for (i, layer) in self.layers.iter().enumerate() {
+        for (name, layer) in self.config.layer_names().iter().zip(&self.layers) {
             match layer {
                 CPULayer::BatchNorm1D(layer) => {
                     let gamma = Tensor::new(layer.gamma.view().into_dyn());
                     let beta = Tensor::new(layer.beta.view().into_dyn());
                     let running_mean = Tensor::new(layer.running_mean.view().into_dyn());
                     let running_var = Tensor::new(layer.running_var.view().into_dyn());
-                    tensors.push((format!("{}g", i), gamma));
-                    tensors.push((format!("{}b", i), beta));
-                    tensors.push((format!("{}m", i), running_mean));
-                    tensors.push((format!("{}v", i), running_var));
+                    tensors.push((LayerParam::Gamma.key(name), gamma));
+                    tensors.push((LayerParam::Beta.key(name), beta));
+                    tensors.push((LayerParam::RunningMean.key(name), running_mean));
+                    tensors.push((LayerParam::RunningVar.key(name), running_var));
                 }
                 CPULayer::BatchNorm2D(layer) => {
                     let gamma = Tensor::new(layer.gamma.view().into_dyn());
                     let beta = Tensor::new(layer.beta.view().into_dyn());
                     let running_mean = Tensor::new(layer.running_mean.view().into_dyn());
                     let running_var = Tensor::new(layer.running_var.view().into_dyn());
-                    tensors.push((format!("{}g", i), gamma));
-                    tensors.push((format!("{}b", i), beta));
-                    tensors.push((format!("{}m", i), running_mean));
-                    tensors.push((format!("{}v", i), running_var));
+                    tensors.push((LayerParam::Gamma.key(name), gamma));
+                    tensors.push((LayerParam::Beta.key(name), beta));
+                    tensors.push((LayerParam::RunningMean.key(name), running_mean));
+                    tensors.push((LayerParam::RunningVar.key(name), running_var));
                 }
                 CPULayer::ConvTranspose2D(layer) => {
                     let weights = Tensor::new(layer.weights.view().into_dyn());
                     let biases = Tensor::new(layer.biases.view().into_dyn());
-                    tensors.push((format!("{}w", i), weights));
-                    tensors.push((format!("{}b", i), biases));
+                    tensors.push((LayerParam::Weight.key(name), weights));
+                    tensors.push((LayerParam::Bias.key(name), biases));
                 }
                 CPULayer::Conv2D(layer) => {
                     let weights = Tensor::new(layer.weights.view().into_dyn());
                     let biases = Tensor::new(layer.biases.view().into_dyn());
-                    tensors.push((format!("{}w", i), weights));
-                    tensors.push((format!("{}b", i), biases));
+                    tensors.push((LayerParam::Weight.key(name), weights));
+                    tensors.push((LayerParam::Bias.key(name), biases));
                 }
                 CPULayer::Dense(layer) => {
                     let weights = Tensor::new(layer.weights.view().into_dyn());
                     let biases = Tensor::new(layer.biases.view().into_dyn());
-                    tensors.push((format!("{}w", i), weights));
-                    tensors.push((format!("{}b", i), biases));
+                    tensors.push((LayerParam::Weight.key(name), weights));
+                    tensors.push((LayerParam::Bias.key(name), biases));
+                }
+                CPULayer::Embedding(layer) => {
+                    let embeddings = Tensor::new(layer.embeddings.view().into_dyn());
+                    tensors.push((LayerParam::Embeddings.key(name), embeddings));
+                }
+                CPULayer::LSTM(layer) => {
+                    let w_ih = Tensor::new(layer.w_ih.view().into_dyn());
+                    let w_hh = Tensor::new(layer.w_hh.view().into_dyn());
+                    let biases = Tensor::new(layer.biases.view().into_dyn());
+                    tensors.push((LayerParam::WIh.key(name), w_ih));
+                    tensors.push((LayerParam::WHh.key(name), w_hh));
+                    tensors.push((LayerParam::Bias.key(name), biases));
+                }
+                CPULayer::GRU(layer) => {
+                    let w_ih = Tensor::new(layer.w_ih.view().into_dyn());
+                    let w_hh = Tensor::new(layer.w_hh.view().into_dyn());
+                    let biases = Tensor::new(layer.biases.view().into_dyn());
+                    tensors.push((LayerParam::WIh.key(name), w_ih));
+                    tensors.push((LayerParam::WHh.key(name), w_hh));
+                    tensors.push((LayerParam::Bias.key(name), biases));
                 }
                 _ => {}
             }
         }
-        let config = serde_json::to_string(&self.config).unwrap();
-        let metadata = HashMap::from([("metadata".to_string(), config)]);
+        tensors
+    }
+
+    /// Metadata shared by saved models and checkpoints.
+    fn metadata(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "metadata".to_string(),
+                serde_json::to_string(&self.config).unwrap(),
+            ),
+            ("format_version".to_string(), FORMAT_VERSION.to_string()),
+        ])
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        serialize(self.tensors(), &Some(self.metadata())).unwrap()
+    }
+
+    /// Saves the weights together with the optimizer state and the training
+    /// progress so that training can be resumed with `Backend::resume`.
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let best = match &self.best_net {
+            Some(buffer) => SafeTensors::deserialize(buffer)
+                .unwrap()
+                .tensors()
+                .into_iter()
+                .map(|(key, view)| (format!("best.{}", key), to_arr(view).unwrap()))
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut tensors = self.tensors();
+        for (key, tensor) in &best {
+            tensors.push((key.clone(), Tensor::new(tensor.view())));
+        }
+        for (name, state) in self.optimizer.state() {
+            for (i, params) in state.iter().enumerate() {
+                for (j, param) in params.iter().enumerate() {
+                    let key = format!("optimizer.{}.{}.{}", name, i, j);
+                    tensors.push((key, Tensor::new(param.view())));
+                }
+            }
+        }
+        let state = TrainingState {
+            step: self.optimizer.step(),
+            ..self.state.clone()
+        };
+        let mut metadata = self.metadata();
+        metadata.insert(
+            "checkpoint".to_string(),
+            serde_json::to_string(&state).unwrap(),
+        );
         serialize(tensors, &Some(metadata)).unwrap()
     }
 
-    pub fn load(buffer: &[u8], logger: Logger, timer: Timer) -> Self {
-        let tensors = SafeTensors::deserialize(buffer).unwrap();
-        let (_, metadata) = SafeTensors::read_metadata(buffer).unwrap();
-        let data = metadata.metadata().as_ref().unwrap();
-        let json = data.get("metadata").unwrap();
-        let config: BackendConfig = serde_json::from_str(json).unwrap();
-        let mut layers = Vec::new();
+    /// Loads a checkpoint written by `Backend::checkpoint`, restoring the
+    /// optimizer state and the training progress.
+    pub fn resume(buffer: &[u8], logger: Logger, timer: Timer) -> NetsaurResult<Self> {
+        let mut backend = Self::load(buffer, logger, timer)?;
+        let tensors = SafeTensors::deserialize(buffer)?;
+        let json = Self::read_metadata(buffer, "checkpoint")?;
+        let state: TrainingState = serde_json::from_str(&json)?;
+        for (name, params) in backend.optimizer.state_mut() {
+            for (i, params) in params.iter_mut().enumerate() {
+                for (j, param) in params.iter_mut().enumerate() {
+                    let key = format!("optimizer.{}.{}.{}", name, i, j);
+                    let value = to_arr(tensors.tensor(&key)?)?;
+                    if value.shape() != param.shape() {
+                        return Err(NetsaurError::ShapeMismatch {
+                            msg: "invalid optimizer state shape",
+                            expected: param.shape().to_vec(),
+                            got: value.shape().to_vec(),
+                        });
+                    }
+                    *param = value;
+                }
+            }
+        }
+        backend.optimizer.set_step(state.step);
+        let names = backend.config.layer_names();
+        let first = backend
+            .tensors()
+            .first()
+            .map(|(key, _)| format!("best.{}", key));
+        if first.is_some_and(|key| tensors.names().contains(&&key)) {
+            let layers = Self::layer_tensors(&backend.config, |i, param| {
+                to_arr(tensors.tensor(&format!("best.{}", param.key(&names[i])))?)
+            })?;
+            let (logger, timer) = (backend.logger.clone(), backend.timer.clone());
+            let best = Backend::new(backend.config.clone(), logger, timer, Some(layers));
+            backend.best_net = Some(best.save());
+        }
+        if let Some(plateau) = &state.plateau {
+            backend.scheduler.set_plateau(plateau.clone());
+        }
+        backend.state = state;
+        Ok(backend)
+    }
 
-        for (i, layer) in config.layers.iter().enumerate() {
-            match layer {
+    fn read_metadata(buffer: &[u8], key: &str) -> NetsaurResult<String> {
+        let (_, metadata) = SafeTensors::read_metadata(buffer)?;
+        metadata
+            .metadata()
+            .as_ref()
+            .and_then(|data| data.get(key))
+            .cloned()
+            .ok_or(NetsaurError::Corrupt(format!("missing {} metadata", key)))
+    }
+
+    pub fn load(buffer: &[u8], logger: Logger, timer: Timer) -> NetsaurResult<Self> {
+        let tensors = SafeTensors::deserialize(buffer)?;
+        let json = Self::read_metadata(buffer, "metadata")?;
+        let config: BackendConfig = serde_json::from_str(&json)?;
+        validate(&config)?;
+        let legacy = match Self::read_metadata(buffer, "format_version") {
+            Ok(version) if version == FORMAT_VERSION.to_string() => false,
+            Ok(version) => {
+                return Err(NetsaurError::Corrupt(format!(
+                    "unsupported format version {}",
+                    version
+                )))
+            }
+            Err(_) => true,
+        };
+        let names = config.layer_names();
+        let layers = Self::layer_tensors(&config, |i, param| {
+            let key = if legacy {
+                param.legacy_key(i)
+            } else {
+                param.key(&names[i])
+            };
+            to_arr(tensors.tensor(&key)?)
+        })?;
+        Ok(Backend::new(config, logger, timer, Some(layers)))
+    }
+
+    /// Groups the tensors that `get` returns for each layer index and
+    /// parameter into the per-layer tensors `Backend::new` expects, failing
+    /// on tensors whose shape does not fit their layer.
+    pub(crate) fn layer_tensors(
+        config: &BackendConfig,
+        mut get: impl FnMut(usize, LayerParam) -> NetsaurResult<ArrayD<f32>>,
+    ) -> NetsaurResult<Vec<Tensors>> {
+        let mut layers = Vec::new();
+        for (i, entry) in config.layers.iter().enumerate() {
+            match entry.layer {
                 Layer::BatchNorm1D(_) | Layer::BatchNorm2D(_) => {
                     layers.push(Tensors::BatchNorm(BatchNormTensors {
-                        gamma: to_arr(tensors.tensor(&format!("{}g", i)).unwrap()),
-                        beta: to_arr(tensors.tensor(&format!("{}b", i)).unwrap()),
-                        running_mean: to_arr(tensors.tensor(&format!("{}m", i)).unwrap()),
-                        running_var: to_arr(tensors.tensor(&format!("{}v", i)).unwrap()),
+                        gamma: get(i, LayerParam::Gamma)?,
+                        beta: get(i, LayerParam::Beta)?,
+                        running_mean: get(i, LayerParam::RunningMean)?,
+                        running_var: get(i, LayerParam::RunningVar)?,
                     }))
                 }
                 Layer::Dense(_) => layers.push(Tensors::Dense(DenseTensors {
-                    weights: to_arr(tensors.tensor(&format!("{}w", i)).unwrap()),
-                    biases: to_arr(tensors.tensor(&format!("{}b", i)).unwrap()),
+                    weights: get(i, LayerParam::Weight)?,
+                    biases: get(i, LayerParam::Bias)?,
                 })),
                 Layer::Conv2D(_) | Layer::ConvTranspose2D(_) => {
                     layers.push(Tensors::Conv(ConvTensors {
-                        weights: to_arr(tensors.tensor(&format!("{}w", i)).unwrap()),
-                        biases: to_arr(tensors.tensor(&format!("{}b", i)).unwrap()),
+                        weights: get(i, LayerParam::Weight)?,
+                        biases: get(i, LayerParam::Bias)?,
+                    }))
+                }
+                Layer::Embedding(_) => layers.push(Tensors::Embedding(EmbeddingTensors {
+                    embeddings: get(i, LayerParam::Embeddings)?,
+                })),
+                Layer::LSTM(_) | Layer::GRU(_) => {
+                    layers.push(Tensors::Recurrent(RecurrentTensors {
+                        w_ih: get(i, LayerParam::WIh)?,
+                        w_hh: get(i, LayerParam::WHh)?,
+                        biases: get(i, LayerParam::Bias)?,
                     }))
                 }
                 _ => {}
             };
         }
+        validate_tensors(config, &layers)?;
+        Ok(layers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use ndarray::ArrayD;
+
+    use super::*;
+
+    const RECURRENT: &str = r#"{
+        "size": [2, 3],
+        "layers": [
+            { "type": "embedding", "config": { "vocabSize": 10, "embeddingSize": 4 } },
+            { "type": "lstm", "config": { "size": 5 } },
+            { "type": "dense", "config": { "size": [1] } }
+        ],
+        "cost": "mse",
+        "optimizer": { "type": "sgd" },
+        "scheduler": { "type": "none" }
+    }"#;
+
+    fn logger() -> Logger {
+        Logger { log: |_| {} }
+    }
+
+    fn timer() -> Timer {
+        Timer { now: || 0 }
+    }
+
+    fn backend(config: &str) -> Backend {
+        Backend::new(
+            serde_json::from_str(config).unwrap(),
+            logger(),
+            timer(),
+            None,
+        )
+    }
+
+    fn weights(backend: &Backend) -> Vec<(String, ArrayD<f32>)> {
+        backend
+            .tensors()
+            .into_iter()
+            .map(|(key, tensor)| (key, tensor.data.to_owned()))
+            .collect()
+    }
+
+    fn tokens() -> ArrayD<f32> {
+        ArrayD::from_shape_vec(vec![2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap()
+    }
+
+    #[test]
+    fn load_restores_embedding_and_lstm_weights() {
+        let model = backend(RECURRENT);
+        let loaded = Backend::load(&model.save(), logger(), timer()).unwrap();
+        assert_eq!(weights(&loaded), weights(&model));
+        assert_eq!(
+            loaded.predict(tokens(), PostProcessor::None, None).unwrap(),
+            model.predict(tokens(), PostProcessor::None, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn load_reads_index_keyed_files() {
+        let model = backend(RECURRENT);
+        let names = model.config.layer_names();
+        let saved: HashMap<_, _> = weights(&model).into_iter().collect();
+        let mut legacy = Vec::new();
+        for (i, entry) in model.config.layers.iter().enumerate() {
+            for param in entry.layer.params() {
+                legacy.push((param.legacy_key(i), saved[&param.key(&names[i])].clone()));
+            }
+        }
+        let keys: Vec<_> = legacy.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["0e", "1w_ih", "1w_hh", "1b", "2w", "2b"]);
+
+        let metadata = HashMap::from([(
+            "metadata".to_string(),
+            serde_json::to_string(&model.config).unwrap(),
+        )]);
+        let tensors = legacy
+            .iter()
+            .map(|(key, tensor)| (key.clone(), Tensor::new(tensor.view())));
+        let file = serialize(tensors, &Some(metadata)).unwrap();
+        let loaded = Backend::load(&file, logger(), timer()).unwrap();
+        assert_eq!(weights(&loaded), weights(&model));
+    }
+
+    #[test]
+    fn save_keys_tensors_by_layer_name() {
+        let named = RECURRENT.replace(
+            r#"{ "type": "lstm","#,
+            r#"{ "type": "activation", "config": { "activation": "relu" } },
+            { "type": "lstm", "name": "encoder.lstm","#,
+        );
+        let model = backend(&named);
+        assert_eq!(
+            model.config.layer_names(),
+            ["embedding0", "activation0", "encoder.lstm", "dense0"]
+        );
+        let file = model.save();
+        let (_, metadata) = SafeTensors::read_metadata(&file).unwrap();
+        let metadata = metadata.metadata().clone().unwrap();
+        assert_eq!(metadata["format_version"], FORMAT_VERSION.to_string());
+        let keys: Vec<_> = weights(&model).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(
+            keys,
+            [
+                "embedding0.embeddings",
+                "encoder.lstm.w_ih",
+                "encoder.lstm.w_hh",
+                "encoder.lstm.bias",
+                "dense0.weight",
+                "dense0.bias"
+            ]
+        );
+        let loaded = Backend::load(&file, logger(), timer()).unwrap();
+        assert_eq!(weights(&loaded), weights(&model));
+
+        let duplicate = named.replace("encoder.lstm", "dense0");
+        let error = validate(&serde_json::from_str(&duplicate).unwrap()).unwrap_err();
+        assert!(error.to_string().contains("dense0"), "{}", error);
+    }
+
+    #[test]
+    fn load_rejects_unknown_format_versions() {
+        let model = backend(RECURRENT);
+        let file = model.save();
+        let tensors = SafeTensors::deserialize(&file).unwrap();
+        let arrays: Vec<_> = tensors
+            .tensors()
+            .into_iter()
+            .map(|(name, view)| (name, to_arr(view).unwrap()))
+            .collect();
+        let metadata = HashMap::from([
+            (
+                "metadata".to_string(),
+                serde_json::to_string(&model.config).unwrap(),
+            ),
+            ("format_version".to_string(), "9".to_string()),
+        ]);
+        let views = arrays
+            .iter()
+            .map(|(name, array)| (name.clone(), Tensor::new(array.view())));
+        let file = serialize(views, &Some(metadata)).unwrap();
+        let error = Backend::load(&file, logger(), timer()).err().unwrap();
+        assert!(matches!(error, NetsaurError::Corrupt(_)), "{}", error);
+    }
+
+    fn replace_tensor(file: &[u8], key: &str, tensor: ArrayD<f32>) -> Vec<u8> {
+        let tensors = SafeTensors::deserialize(file).unwrap();
+        let (_, metadata) = SafeTensors::read_metadata(file).unwrap();
+        let mut arrays: Vec<_> = tensors
+            .tensors()
+            .into_iter()
+            .map(|(name, view)| (name, to_arr(view).unwrap()))
+            .collect();
+        arrays.iter_mut().find(|(name, _)| name == key).unwrap().1 = tensor;
+        let views = arrays
+            .iter()
+            .map(|(name, array)| (name.clone(), Tensor::new(array.view())));
+        serialize(views, metadata.metadata()).unwrap()
+    }
+
+    #[test]
+    fn load_rejects_tensors_that_do_not_fit_their_layer() {
+        let file = backend(RECURRENT).save();
+        for tensor in [ArrayD::zeros(vec![4, 3, 5]), ArrayD::zeros(vec![4, 20])] {
+            let file = replace_tensor(&file, "lstm0.w_ih", tensor);
+            let error = Backend::load(&file, logger(), timer()).err().unwrap();
+            assert!(matches!(error, NetsaurError::Corrupt(_)), "{}", error);
+            assert!(error.to_string().contains("lstm0.w_ih"));
+        }
+    }
+
+    #[test]
+    fn predict_checks_the_input_of_selected_layers() {
+        let model = backend(RECURRENT);
+        let embedded = ArrayD::zeros(vec![2, 3, 4]);
+        assert_eq!(
+            model
+                .predict(embedded.clone(), PostProcessor::None, Some(vec![1, 2]))
+                .unwrap()
+                .shape(),
+            [2, 1]
+        );
+        let error = model
+            .predict(tokens(), PostProcessor::None, Some(vec![1, 2]))
+            .err()
+            .unwrap();
+        assert!(matches!(error, NetsaurError::ShapeMismatch { .. }));
+        let error = model
+            .predict(embedded, PostProcessor::None, Some(vec![1, 1]))
+            .err()
+            .unwrap();
+        assert!(matches!(error, NetsaurError::ShapeMismatch { .. }));
+    }
+
+    #[test]
+    fn summary_reports_shapes_and_parameter_counts() {
+        let summary = backend(RECURRENT).summary().unwrap();
+        let shapes: Vec<_> = summary
+            .layers
+            .iter()
+            .map(|layer| (layer.input_shape.clone(), layer.output_shape.clone()))
+            .collect();
+        assert_eq!(
+            shapes,
+            [
+                (vec![2, 3], vec![2, 3, 4]),
+                (vec![2, 3, 4], vec![2, 5]),
+                (vec![2, 5], vec![2, 1])
+            ]
+        );
+        let params: Vec<_> = summary
+            .layers
+            .iter()
+            .map(|layer| (layer.trainable_params, layer.non_trainable_params))
+            .collect();
+        assert_eq!(params, [(40, 0), (200, 0), (6, 0)]);
+        assert_eq!(summary.total_params, 246);
+        assert_eq!(summary.parameter_bytes, 246 * 4);
+        assert_eq!(summary.gradient_bytes, 246 * 4);
+    }
+
+    fn samples(len: usize, offset: usize) -> Dataset {
+        let values: Vec<f32> = (offset..offset + len).map(|x| x as f32).collect();
+        Dataset {
+            inputs: ArrayD::from_shape_vec(vec![len, 1], values.clone()).unwrap(),
+            outputs: ArrayD::from_shape_vec(vec![len, 1], values).unwrap(),
+        }
+    }
+
+    #[test]
+    fn split_validation_holds_out_the_trailing_samples() {
+        let (train, validation) =
+            Backend::split_validation(vec![samples(4, 0), samples(4, 4)], 0.25).unwrap();
+        let inputs = |datasets: &[Dataset]| -> Vec<Vec<f32>> {
+            datasets
+                .iter()
+                .map(|dataset| dataset.inputs.iter().copied().collect())
+                .collect()
+        };
+        assert_eq!(inputs(&train), [vec![0.0, 1.0, 2.0, 3.0], vec![4.0, 5.0]]);
+        assert_eq!(inputs(&validation), [vec![6.0, 7.0]]);
+
+        for fraction in [1.0, -0.5] {
+            let error = Backend::split_validation(vec![samples(4, 0)], fraction)
+                .err()
+                .unwrap();
+            assert!(matches!(error, NetsaurError::InvalidOption(_)));
+        }
+    }
+
+    const LINEAR: &str = r#"{
+        "size": [4, 1],
+        "layers": [{ "type": "dense", "config": { "size": [1] } }],
+        "cost": "mse",
+        "optimizer": { "type": "sgd" },
+        "scheduler": { "type": "none" },
+        "monitor": "val_loss",
+        "patience": 2
+    }"#;
+
+    fn options(epochs: usize) -> TrainOptions {
+        TrainOptions {
+            datasets: 1,
+            input_shape: vec![4, 1],
+            output_shape: vec![4, 1],
+            epochs,
+            batches: 1,
+            rate: 0.01,
+            validation_datasets: None,
+            validation_input_shape: None,
+            validation_output_shape: None,
+            validation_split: None,
+            metrics: None,
+            resume: None,
+            batch_size: None,
+            shuffle: None,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn monitoring_val_loss_requires_validation_data() {
+        let mut model = backend(LINEAR);
+        let error = model
+            .train(vec![samples(4, 0)], vec![], &options(1))
+            .err()
+            .unwrap();
+        assert!(matches!(error, NetsaurError::InvalidOption(_)));
+        model
+            .train(vec![samples(4, 0)], vec![samples(4, 4)], &options(1))
+            .unwrap();
+    }
+
+    #[test]
+    fn empty_datasets_are_rejected() {
+        let mut model = backend(LINEAR);
+        let error = model.evaluate(&[samples(0, 0)], &[]).err().unwrap();
+        assert!(matches!(error, NetsaurError::InvalidOption(_)), "{}", error);
+
+        for (datasets, validation) in [
+            (vec![], vec![samples(4, 4)]),
+            (vec![samples(0, 0)], vec![samples(4, 4)]),
+            (vec![samples(4, 0)], vec![samples(0, 4)]),
+        ] {
+            let error = model
+                .train(datasets, validation, &options(1))
+                .err()
+                .unwrap();
+            assert!(matches!(error, NetsaurError::InvalidOption(_)), "{}", error);
+        }
+    }
+
+    #[test]
+    fn the_epoch_cost_is_weighted_by_batch_size() {
+        let mut model = backend(&LINEAR.replace(r#""monitor": "val_loss","#, ""));
+        let expected = model
+            .evaluate(&[samples(3, 0), samples(1, 3)], &[])
+            .unwrap()
+            .loss;
+        // A zero rate keeps the weights, so the epoch sees the same model.
+        let frozen = TrainOptions {
+            rate: 0.0,
+            ..options(1)
+        };
+        model
+            .train(vec![samples(3, 0), samples(1, 3)], vec![], &frozen)
+            .unwrap();
+        assert!((model.state.best_cost - expected).abs() < 1e-5);
+
+        let error = model
+            .train(
+                vec![samples(4, 0)],
+                vec![],
+                &TrainOptions {
+                    batches: 0,
+                    ..options(1)
+                },
+            )
+            .err()
+            .unwrap();
+        assert!(matches!(error, NetsaurError::InvalidOption(_)), "{}", error);
+    }
+
+    const SEEDED: &str = r#"{
+        "size": [4, 1],
+        "layers": [
+            { "type": "dense", "config": { "size": [3] } },
+            { "type": "dropout1d", "config": { "probability": 0.2 } },
+            { "type": "dense", "config": { "size": [1] } }
+        ],
+        "cost": "mse",
+        "optimizer": {
+            "type": "adam",
+            "config": { "beta1": 0.9, "beta2": 0.999, "epsilon": 1e-8 }
+        },
+        "scheduler": { "type": "none" },
+        "patience": 2,
+        "seed": 7,
+        "silent": true
+    }"#;
+
+    #[test]
+    fn frozen_layers_keep_their_weights() {
+        let config = SEEDED
+            .replace(
+                r#"{ "type": "dropout1d", "#,
+                r#"{ "type": "dense", "config": { "size": [3] } },
+            { "type": "dropout1d", "#,
+            )
+            .replace(r#""patience": 2,"#, "");
+        let mut model = backend(&config);
+        // the middle dense and dropout layers, with batches of 3 instead of 4
+        model.freeze(1..3).unwrap();
+        let initial = weights(&model);
+        let options = TrainOptions {
+            batch_size: Some(3),
+            ..shuffled(3)
+        };
+        model.train(vec![samples(8, 0)], vec![], &options).unwrap();
+
+        let trained = weights(&model);
+        for ((key, initial), (_, trained)) in initial.iter().zip(&trained) {
+            assert_eq!(key.starts_with("dense1."), initial == trained, "{}", key);
+        }
+        match &model.layers[1] {
+            CPULayer::Dense(layer) => assert!(layer.d_weights.iter().all(|x| *x == 0.0)),
+            _ => unreachable!(),
+        }
+        assert!(!model.config.layers[2].trainable());
+    }
+
+    #[test]
+    fn freeze_rejects_ranges_outside_the_model() {
+        let mut model = backend(SEEDED);
+        for range in [Range { start: 2, end: 1 }, 0..4, 3..4] {
+            let error = model.freeze(range.clone()).unwrap_err();
+            assert!(
+                matches!(error, NetsaurError::InvalidConfig(_)),
+                "{:?}",
+                range
+            );
+        }
+        model.freeze(3..3).unwrap();
+        assert!(model.config.layers.iter().all(|entry| entry.trainable()));
+    }
+
+    fn resumed(epochs: usize) -> TrainOptions {
+        TrainOptions {
+            resume: Some(true),
+            ..shuffled(epochs)
+        }
+    }
+
+    fn shuffled(epochs: usize) -> TrainOptions {
+        TrainOptions {
+            batch_size: Some(4),
+            shuffle: Some(true),
+            ..options(epochs)
+        }
+    }
+
+    fn best_weights(backend: &Backend) -> Vec<(String, ArrayD<f32>)> {
+        let best = backend.best_net.as_ref().unwrap();
+        weights(&Backend::load(best, logger(), timer()).unwrap())
+    }
+
+    #[test]
+    fn resuming_a_checkpoint_matches_an_uninterrupted_run() {
+        let mut uninterrupted = backend(SEEDED);
+        uninterrupted
+            .train(vec![samples(8, 0)], vec![], &shuffled(4))
+            .unwrap();
+
+        let mut first = backend(SEEDED);
+        first
+            .train(vec![samples(8, 0)], vec![], &shuffled(1))
+            .unwrap();
+        // Nothing improves on the first epoch, so the best weights are still
+        // the initial ones and differ from the checkpointed weights.
+        assert!(first.state.best_cost > 0.0);
+        assert_ne!(best_weights(&first), weights(&first));
+        let checkpoint = first.checkpoint();
+        let mut second = Backend::resume(&checkpoint, logger(), timer()).unwrap();
+        assert_eq!(second.state.epoch, 1);
+        assert_eq!(best_weights(&second), best_weights(&first));
+        second
+            .train(vec![samples(8, 0)], vec![], &resumed(4))
+            .unwrap();
+
+        assert_eq!(weights(&second), weights(&uninterrupted));
+        assert_eq!(second.state.best_cost, uninterrupted.state.best_cost);
+        assert_eq!(best_weights(&second), best_weights(&uninterrupted));
+    }
+
+    #[test]
+    fn minibatches_follow_the_order_and_keep_the_remainder() {
+        let dataset = samples(8, 0);
+        let order = [7, 0, 6, 1, 5, 2, 4, 3];
+        let batches: Vec<Vec<f32>> = Backend::minibatches(&dataset, 3, &order)
+            .iter()
+            .map(|batch| batch.inputs.iter().copied().collect())
+            .collect();
+        assert_eq!(
+            batches,
+            [vec![7.0, 0.0, 6.0], vec![1.0, 5.0, 2.0], vec![4.0, 3.0]]
+        );
+
+        let mut model = backend(LINEAR);
+        let options = TrainOptions {
+            batch_size: Some(0),
+            ..options(1)
+        };
+        let error = model
+            .train(vec![samples(8, 0)], vec![samples(4, 8)], &options)
+            .err()
+            .unwrap();
+        assert!(matches!(error, NetsaurError::InvalidOption(_)));
+    }
+
+    #[test]
+    fn a_seed_makes_initialization_and_training_reproducible() {
+        let train = |config: &str| {
+            let mut model = backend(config);
+            let initial = weights(&model);
+            model
+                .train(vec![samples(8, 0)], vec![], &shuffled(3))
+                .unwrap();
+            (initial, weights(&model))
+        };
+        let (initial, trained) = train(SEEDED);
+        assert_eq!(train(SEEDED), (initial.clone(), trained));
+        let (other, _) = train(&SEEDED.replace(r#""seed": 7"#, r#""seed": 8"#));
+        assert_ne!(other, initial);
+    }
+
+    #[test]
+    fn neighbouring_seeds_do_not_share_epochs() {
+        let seeds: HashSet<u64> = (0..16)
+            .flat_map(|seed| (0..16).map(move |epoch| Backend::epoch_seed(seed, epoch)))
+            .collect();
+        assert_eq!(seeds.len(), 16 * 16);
+    }
+
+    #[test]
+    fn schedules_count_epochs_with_adam() {
+        let train = |scheduler: &str| {
+            let mut model = backend(&SEEDED.replace(r#"{ "type": "none" }"#, scheduler));
+            let options = TrainOptions {
+                batch_size: Some(1),
+                ..shuffled(1)
+            };
+            model.train(vec![samples(8, 0)], vec![], &options).unwrap();
+            assert_eq!(model.optimizer.step(), 8);
+            weights(&model)
+        };
+        let multistep = r#"{
+            "type": "multistep",
+            "config": { "milestones": [1], "gamma": 0.5 }
+        }"#;
+        assert_eq!(train(multistep), train(r#"{ "type": "none" }"#));
+        let warmup = r#"{
+            "type": "warmup",
+            "config": { "steps": 2, "scheduler": { "type": "none" } }
+        }"#;
+        assert_ne!(train(warmup), train(r#"{ "type": "none" }"#));
+    }
+
+    #[test]
+    fn batches_of_any_size_pass_through_the_layers() {
+        let model = backend(RECURRENT);
+        for batches in [1, 5] {
+            let tokens = ArrayD::from_elem(vec![batches, 3], 1.0);
+            let output = model.predict(tokens, PostProcessor::None, None).unwrap();
+            assert_eq!(output.shape(), [batches, 1]);
+        }
 
-        Backend::new(config, logger, timer, Some(layers))
+        let mut model = backend(SEEDED);
+        model
+            .train(vec![samples(3, 0), samples(5, 3)], vec![], &options(2))
+            .unwrap();
+        let output = model
+            .predict(ArrayD::zeros(vec![7, 1]), PostProcessor::None, None)
+            .unwrap();
+        assert_eq!(output.shape(), [7, 1]);
     }
 }
@@ -1,5 +1,6 @@
 use ndarray::{ArrayD, IxDyn};
 use ndarray_rand::{
+    rand::{rngs::StdRng, SeedableRng},
     rand_distr::{Normal, Uniform},
     RandomExt,
 };
@@ -15,12 +16,18 @@ impl CPUInit {
         Self { init }
     }
 
-    pub fn init(&self, size: IxDyn, input_size: usize, output_size: usize) -> ArrayD<f32> {
+    pub fn init(
+        &self,
+        rng: &mut StdRng,
+        size: IxDyn,
+        input_size: usize,
+        output_size: usize,
+    ) -> ArrayD<f32> {
         match self.init {
-            Init::Uniform => uniform(size),
-            Init::Xavier => xavier(size, input_size),
-            Init::XavierN => xaviern(size, input_size, output_size),
-            Init::Kaiming => kaiming(size, input_size),
+            Init::Uniform => uniform(rng, size),
+            Init::Xavier => xavier(rng, size, input_size),
+            Init::XavierN => xaviern(rng, size, input_size, output_size),
+            Init::Kaiming => kaiming(rng, size, input_size),
         }
     }
 
@@ -32,21 +39,35 @@ impl CPUInit {
     }
 }
 
-pub fn uniform(size: IxDyn) -> ArrayD<f32> {
-    ArrayD::random(size, Uniform::new(-1.0, 1.0))
+pub fn uniform(rng: &mut StdRng, size: IxDyn) -> ArrayD<f32> {
+    ArrayD::random_using(size, Uniform::new(-1.0, 1.0), rng)
 }
 
-pub fn xavier(size: IxDyn, input_size: usize) -> ArrayD<f32> {
+pub fn xavier(rng: &mut StdRng, size: IxDyn, input_size: usize) -> ArrayD<f32> {
     let bounds = 1.0 / (input_size as f32).sqrt();
-    ArrayD::random(size, Uniform::new(-bounds, bounds))
+    ArrayD::random_using(size, Uniform::new(-bounds, bounds), rng)
 }
 
-pub fn xaviern(size: IxDyn, input_size: usize, output_size: usize) -> ArrayD<f32> {
+pub fn xaviern(
+    rng: &mut StdRng,
+    size: IxDyn,
+    input_size: usize,
+    output_size: usize,
+) -> ArrayD<f32> {
     let bounds = (6.0 as f32).sqrt() / ((input_size + output_size) as f32).sqrt();
-    ArrayD::random(size, Uniform::new(-bounds, bounds))
+    ArrayD::random_using(size, Uniform::new(-bounds, bounds), rng)
 }
 
-pub fn kaiming(size: IxDyn, input_size: usize) -> ArrayD<f32> {
+pub fn kaiming(rng: &mut StdRng, size: IxDyn, input_size: usize) -> ArrayD<f32> {
     let deviation = (2.0 / (input_size as f32)).sqrt();
-    ArrayD::random(size, Normal::new(0.0, deviation).unwrap())
+    ArrayD::random_using(size, Normal::new(0.0, deviation).unwrap(), rng)
+}
+
+/// Creates the random number generator of a backend, seeded when a seed is
+/// given and from system entropy otherwise.
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
 }
@@ -20,12 +20,6 @@ impl ActivationCPULayer {
         self.outputs.shape().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let mut output_size = self.outputs.shape().to_vec();
-        output_size[0] = batches;
-        self.outputs = ArrayD::zeros(output_size);
-    }
-
     pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
         let outputs = if CPUActivation::memoize_output(&self.activation) {
             self.outputs = inputs.map(self.activation.activate);
@@ -37,6 +31,10 @@ impl ActivationCPULayer {
         outputs.into_dyn()
     }
 
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        inputs.map(self.activation.activate)
+    }
+
     pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
         let d_inputs = d_outputs.mul(self.outputs.map(self.activation.prime));
         d_inputs.into_dyn()
@@ -60,24 +58,23 @@ impl SoftmaxCPULayer {
         self.outputs.shape().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let mut output_size = self.outputs.shape().to_vec();
-        output_size[0] = batches;
-        self.outputs = ArrayD::zeros(output_size);
+    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.outputs = self.infer(inputs);
+        self.outputs.clone().into_dyn()
     }
 
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
-        self.outputs = inputs.clone();
-        let batches = self.outputs.dim()[0];
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        let mut outputs = inputs.clone();
+        let batches = outputs.dim()[0];
         for b in 0..batches {
             let current_input = inputs.slice(s![b, ..]).map(|x| x / self.temperature);
             let max = current_input.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
             let exp = current_input.map(|x| (x - max).exp());
-            self.outputs
+            outputs
                 .slice_mut(s![b, ..])
                 .assign(&exp.clone().div(exp.sum() + EPSILON));
         }
-        self.outputs.clone().into_dyn()
+        outputs
     }
 
     pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
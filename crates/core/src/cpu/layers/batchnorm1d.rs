@@ -23,6 +23,7 @@ pub struct BatchNorm1DCPULayer {
     pub var: Array2<f32>,
     pub std_dev: Array2<f32>,
     pub normalized: Array2<f32>,
+    pub training: bool,
     pub epsilon: f32,
     pub momentum: f32,
 
@@ -68,6 +69,7 @@ impl BatchNorm1DCPULayer {
             var: Array2::zeros((1, size[1])),
             std_dev: Array2::zeros((1, size[1])),
             normalized: Array2::zeros(input_size),
+            training: false,
             epsilon: config.epsilon,
             momentum: config.momentum,
 
@@ -88,16 +90,10 @@ impl BatchNorm1DCPULayer {
         self.inputs.shape().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let size = self.inputs.shape();
-        self.inputs = Array2::zeros((batches, size[1]));
-        let size = self.inputs.shape();
-        self.normalized = Array2::zeros((batches, size[1]));
-    }
-
     pub fn forward_propagate(&mut self, inputs: ArrayD<f32>, training: bool) -> ArrayD<f32> {
         self.inputs = inputs.into_dimensionality::<Ix2>().unwrap();
 
+        self.training = training;
         if training {
             self.mean = axes!((self.inputs).mean_axes(0));
             self.var = axes!((self.inputs).var_axes(0));
@@ -130,13 +126,37 @@ impl BatchNorm1DCPULayer {
         batch_norm.into_dyn()
     }
 
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        let inputs = inputs.into_dimensionality::<Ix2>().unwrap();
+        let std_dev = self.running_var.map(|x| (x + self.epsilon).sqrt());
+        let normalized = inputs
+            .view()
+            .sub(&self.running_mean.view())
+            .div(&std_dev.view());
+        let batch_norm = self
+            .gamma
+            .view()
+            .mul(&normalized.view())
+            .add(self.beta.view());
+        batch_norm.into_dyn()
+    }
+
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         let d_outputs = d_outputs.into_dimensionality::<Ix2>().unwrap();
 
         let batches = self.inputs.shape()[0] as f32;
 
-        let mean_diff = self.inputs.view().sub(&self.mean.view());
+        if trainable {
+            self.d_gamma = axes!((d_outputs.view().mul(&self.normalized.view())).sum_axes(0));
+            self.d_beta = axes!((d_outputs).sum_axes(0));
+        }
         let d_normalized = d_outputs.view().mul(&self.gamma.view());
+        if !self.training {
+            // The running statistics are constants, so the layer is affine.
+            return d_normalized.div(&self.std_dev.view()).into_dyn();
+        }
+
+        let mean_diff = self.inputs.view().sub(&self.mean.view());
         let d_var = axes!((d_normalized
             .view()
             .mul(&mean_diff.view())
@@ -152,8 +172,6 @@ impl BatchNorm1DCPULayer {
                     .mul(&axes!(((-2.0).mul(&mean_diff.view())).sum_axes(0)))
                     .div(batches),
             );
-        self.d_gamma = axes!((d_outputs.view().mul(&self.normalized.view())).sum_axes(0));
-        self.d_beta = axes!((d_outputs).sum_axes(0));
 
         d_normalized
             .view()
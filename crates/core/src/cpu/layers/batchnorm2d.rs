@@ -20,6 +20,7 @@ pub struct BatchNorm2DCPULayer {
     pub var: Array4<f32>,
     pub std_dev: Array4<f32>,
     pub normalized: Array4<f32>,
+    pub training: bool,
     pub epsilon: f32,
     pub momentum: f32,
 
@@ -65,6 +66,7 @@ impl BatchNorm2DCPULayer {
             var: Array4::zeros((1, size[1], 1, 1)),
             std_dev: Array4::zeros((1, size[1], 1, 1)),
             normalized: Array4::zeros(input_size),
+            training: false,
             epsilon: config.epsilon,
             momentum: config.momentum,
 
@@ -85,16 +87,10 @@ impl BatchNorm2DCPULayer {
         self.inputs.shape().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let size = self.inputs.shape();
-        self.inputs = Array4::zeros((batches, size[1], size[2], size[3]));
-        let size = self.inputs.shape();
-        self.normalized = Array4::zeros((batches, size[1], size[2], size[3]));
-    }
-
     pub fn forward_propagate(&mut self, inputs: ArrayD<f32>, training: bool) -> ArrayD<f32> {
         self.inputs = inputs.into_dimensionality::<Ix4>().unwrap();
 
+        self.training = training;
         if training {
             self.mean = axes!((self.inputs).mean_axes(0, 2, 3));
             self.var = axes!((self.inputs.map(|x| x.powi(2))).sum_axes(0, 2, 3))
@@ -129,13 +125,37 @@ impl BatchNorm2DCPULayer {
         batch_norm.into_dyn()
     }
 
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        let inputs = inputs.into_dimensionality::<Ix4>().unwrap();
+        let std_dev = self.running_var.map(|x| (x + self.epsilon).sqrt());
+        let normalized = inputs
+            .view()
+            .sub(&self.running_mean.view())
+            .div(&std_dev.view());
+        let batch_norm = self
+            .gamma
+            .view()
+            .mul(&normalized.view())
+            .add(self.beta.view());
+        batch_norm.into_dyn()
+    }
+
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         let d_outputs = d_outputs.into_dimensionality::<Ix4>().unwrap();
         let output_y = self.inputs.shape()[2] as f32;
         let output_x = self.inputs.shape()[3] as f32;
 
-        let mean_diff = self.inputs.view().sub(&self.mean.view());
+        if trainable {
+            self.d_gamma = axes!((d_outputs.view().mul(&self.normalized.view())).sum_axes(0, 2, 3));
+            self.d_beta = axes!((d_outputs).sum_axes(0, 2, 3));
+        }
         let d_normalized = d_outputs.view().mul(&self.gamma.view());
+        if !self.training {
+            // The running statistics are constants, so the layer is affine.
+            return d_normalized.div(&self.std_dev.view()).into_dyn();
+        }
+
+        let mean_diff = self.inputs.view().sub(&self.mean.view());
         let d_var = axes!((d_normalized
             .view()
             .mul(&mean_diff.view())
@@ -151,8 +171,6 @@ impl BatchNorm2DCPULayer {
                     .mul(&axes!(((-2.0).mul(&mean_diff.view())).sum_axes(0, 2, 3)))
                     .div(output_y * output_x),
             );
-        self.d_gamma = axes!((d_outputs.view().mul(&self.normalized.view())).sum_axes(0, 2, 3));
-        self.d_beta = axes!((d_outputs).sum_axes(0, 2, 3));
 
         d_normalized
             .view()
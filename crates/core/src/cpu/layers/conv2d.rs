@@ -1,6 +1,8 @@
-use ndarray::{s, Array1, Array4, ArrayD, Dimension, Ix1, Ix4, IxDyn};
-use std::ops::{Add, AddAssign, Mul};
+use ndarray::{s, Array1, Array3, Array4, ArrayD, Axis, Dimension, Ix1, Ix4, IxDyn, Zip};
+use ndarray_rand::rand::rngs::StdRng;
+use std::ops::Add;
 
+use super::im2col::{col2im, im2col};
 use crate::{CPUInit, CPURegularizer, Conv2DLayer, Init, Tensors};
 
 pub struct Conv2DCPULayer {
@@ -26,14 +28,19 @@ pub struct Conv2DCPULayer {
 }
 
 impl Conv2DCPULayer {
-    pub fn new(config: Conv2DLayer, size: IxDyn, tensors: Option<Tensors>) -> Self {
+    pub fn new(
+        config: Conv2DLayer,
+        size: IxDyn,
+        tensors: Option<Tensors>,
+        rng: &mut StdRng,
+    ) -> Self {
         let strides = config.strides.unwrap_or(vec![1, 1]);
         let padding = config.padding.unwrap_or(vec![0, 0]);
         let input_y = size[2] + 2 * padding[0];
         let input_x = size[3] + 2 * padding[1];
         let output_y = 1 + (input_y - config.kernel_size[2]) / strides[0];
         let output_x = 1 + (input_x - config.kernel_size[3]) / strides[1];
-        let input_size = Ix4(size[0], size[1], input_y, input_x);
+        let input_size = Ix4(0, size[1], input_y, input_x);
         let weight_size = IxDyn(config.kernel_size.as_slice());
         let output_size = Ix4(size[0], weight_size[0], output_y, output_x);
         let (weights, biases) = if let Some(Tensors::Conv(tensors)) = tensors {
@@ -43,6 +50,7 @@ impl Conv2DCPULayer {
                 ArrayD::from_shape_vec(tensor.shape, tensor.data).unwrap()
             } else {
                 CPUInit::from_default(config.init, Init::Kaiming).init(
+                    rng,
                     weight_size.clone(),
                     size[1] * input_y * input_x,
                     weight_size[0] * output_y * output_x,
@@ -75,82 +83,212 @@ impl Conv2DCPULayer {
         self.output_size.as_array_view().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let input_size = self.inputs.shape();
-        self.inputs = Array4::zeros((batches, input_size[1], input_size[2], input_size[3]));
-        self.output_size[0] = batches;
+    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.inputs = self.pad(inputs);
+        self.convolve(&self.inputs).into_dyn()
     }
 
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.convolve(&self.pad(inputs)).into_dyn()
+    }
+
+    /// Copies the inputs into a zeroed buffer surrounded by the padding.
+    fn pad(&self, inputs: ArrayD<f32>) -> Array4<f32> {
         let inputs = inputs.into_dimensionality::<Ix4>().unwrap();
-        let (_, _, input_y, input_x) = self.inputs.dim();
-        let unpadded_y = self.padding[0]..input_y - self.padding[0];
-        let unpadded_x = self.padding[1]..input_x - self.padding[1];
-        self.inputs
+        let (batches, channels, input_y, input_x) = inputs.dim();
+        let mut padded = Array4::zeros((
+            batches,
+            channels,
+            input_y + 2 * self.padding[0],
+            input_x + 2 * self.padding[1],
+        ));
+        let unpadded_y = self.padding[0]..self.padding[0] + input_y;
+        let unpadded_x = self.padding[1]..self.padding[1] + input_x;
+        padded
             .slice_mut(s![.., .., unpadded_y, unpadded_x])
             .assign(&inputs);
+        padded
+    }
 
-        let (filters, _, weight_y, weight_x) = self.weights.dim();
-        let (batches, _, output_y, output_x) = self.output_size.into_pattern();
-
-        let mut outputs = Array4::zeros(self.output_size);
-        for b in 0..batches {
-            for f in 0..filters {
-                let mut h = 0;
-                for y in (0..output_y).step_by(self.strides[0]) {
-                    let mut w = 0;
-                    for x in (0..output_x).step_by(self.strides[1]) {
-                        outputs[(b, f, h, w)] = self
-                            .inputs
-                            .slice(s![b, .., y..y + weight_y, x..x + weight_x])
-                            .mul(&self.weights.slice(s![f, .., .., ..]))
-                            .sum()
-                            .add(self.biases[f]);
-                        w += 1;
-                    }
-                    h += 1;
-                }
-            }
-        }
+    fn convolve(&self, inputs: &Array4<f32>) -> Array4<f32> {
+        let (filters, channels, weight_y, weight_x) = self.weights.dim();
+        let (_, _, output_y, output_x) = self.output_size.into_pattern();
+        let batches = inputs.dim().0;
+        let weights = self
+            .weights
+            .to_shape((filters, channels * weight_y * weight_x))
+            .unwrap();
+        let biases = self.biases.view().insert_axis(Axis(1));
 
-        outputs.into_dyn()
+        let mut outputs = Array4::zeros((batches, filters, output_y, output_x));
+        for_each!(
+            Zip::from(outputs.outer_iter_mut()).and(inputs.outer_iter()),
+            |mut outputs, inputs| {
+                let cols = im2col(
+                    inputs,
+                    (weight_y, weight_x),
+                    &self.strides,
+                    (output_y, output_x),
+                );
+                let product = weights.dot(&cols).add(&biases);
+                outputs.assign(&product.to_shape((filters, output_y, output_x)).unwrap());
+            }
+        );
+        outputs
     }
 
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         let d_outputs = d_outputs.into_dimensionality::<Ix4>().unwrap();
 
-        let (filters, _, weight_y, weight_x) = self.weights.dim();
-        let (batches, _, output_y, output_x) = self.output_size.into_pattern();
+        let (filters, channels, weight_y, weight_x) = self.weights.dim();
+        let (batches, _, output_y, output_x) = d_outputs.dim();
+        let weights = self
+            .weights
+            .to_shape((filters, channels * weight_y * weight_x))
+            .unwrap();
 
         let mut d_inputs = Array4::zeros(self.inputs.dim());
-        self.d_weights = Array4::zeros(self.weights.dim());
-        self.d_biases = Array1::<f32>::zeros(self.biases.dim());
-        for b in 0..batches {
-            for f in 0..filters {
-                for y in (0..output_y).step_by(self.strides[0]) {
-                    for x in (0..output_x).step_by(self.strides[1]) {
-                        d_inputs
-                            .slice_mut(s![b, .., y..y + weight_y, x..x + weight_x])
-                            .add_assign(
-                                &self
-                                    .weights
-                                    .slice(s![f, .., .., ..])
-                                    .mul(d_outputs[(b, f, y, x)]),
-                            );
-                        self.d_weights.slice_mut(s![f, .., .., ..]).add_assign(
-                            &self
-                                .inputs
-                                .slice(s![b, .., y..y + weight_y, x..x + weight_x])
-                                .mul(d_outputs[(b, f, y, x)]),
-                        );
-                        self.d_biases[f] += d_outputs[(b, f, y, x)];
-                    }
+        let mut d_weights = Array3::zeros((batches, filters, channels * weight_y * weight_x));
+        for_each!(
+            Zip::from(d_inputs.outer_iter_mut())
+                .and(d_weights.outer_iter_mut())
+                .and(self.inputs.outer_iter())
+                .and(d_outputs.outer_iter()),
+            |d_inputs, mut d_weights, inputs, d_outputs| {
+                let d_outputs = d_outputs.to_shape((filters, output_y * output_x)).unwrap();
+                let cols = im2col(
+                    inputs,
+                    (weight_y, weight_x),
+                    &self.strides,
+                    (output_y, output_x),
+                );
+                if trainable {
+                    d_weights.assign(&d_outputs.dot(&cols.t()));
                 }
+                let d_cols = weights.t().dot(&d_outputs);
+                col2im(
+                    d_cols.view(),
+                    d_inputs,
+                    (weight_y, weight_x),
+                    &self.strides,
+                    (output_y, output_x),
+                );
             }
+        );
+        if trainable {
+            self.d_weights = d_weights
+                .sum_axis(Axis(0))
+                .into_shape_with_order(self.weights.dim())
+                .unwrap();
+            self.d_biases = d_outputs
+                .sum_axis(Axis(3))
+                .sum_axis(Axis(2))
+                .sum_axis(Axis(0));
+            self.l_weights = self
+                .regularizer
+                .coeff(&self.weights.clone().into_dyn())
+                .into_dimensionality::<Ix4>()
+                .unwrap();
+            self.l_biases = self
+                .regularizer
+                .coeff(&self.biases.clone().into_dyn())
+                .into_dimensionality::<Ix1>()
+                .unwrap();
         }
-        self.l_weights = self.regularizer.coeff(&self.weights.clone().into_dyn()).into_dimensionality::<Ix4>().unwrap();
-        self.l_biases = self.regularizer.coeff(&self.biases.clone().into_dyn()).into_dimensionality::<Ix1>().unwrap();
 
-        d_inputs.into_dyn()
+        let (_, _, input_y, input_x) = d_inputs.dim();
+        d_inputs
+            .slice_move(s![
+                ..,
+                ..,
+                self.padding[0]..input_y - self.padding[0],
+                self.padding[1]..input_x - self.padding[1]
+            ])
+            .into_dyn()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::layers::gradcheck::{self, derivative};
+    use crate::seeded_rng;
+
+    fn layer(strides: usize, padding: usize) -> Conv2DCPULayer {
+        let config = Conv2DLayer {
+            init: None,
+            kernel: None,
+            kernel_size: vec![3, 2, 3, 2],
+            padding: Some(vec![padding, padding]),
+            strides: Some(vec![strides, strides]),
+            c: None,
+            l1_ratio: None,
+        };
+        let size = IxDyn(&[2, 2, 5, 6]);
+        let mut layer = Conv2DCPULayer::new(config, size, None, &mut seeded_rng(Some(1)));
+        layer.biases = Array1::from(vec![0.1, -0.2, 0.3]);
+        layer
+    }
+
+    fn inputs() -> Array4<f32> {
+        gradcheck::inputs((2, 2, 5, 6))
+    }
+
+    /// Slides the kernel over the padded inputs one output pixel at a time.
+    fn reference(layer: &Conv2DCPULayer, inputs: &Array4<f32>) -> Array4<f32> {
+        let padded = layer.pad(inputs.clone().into_dyn());
+        let (_, _, weight_y, weight_x) = layer.weights.dim();
+        let s = &layer.strides;
+        let mut outputs = Array4::zeros(layer.output_size.into_pattern());
+        for ((b, f, y, x), output) in outputs.indexed_iter_mut() {
+            let window = padded.slice(s![
+                b,
+                ..,
+                y * s[0]..y * s[0] + weight_y,
+                x * s[1]..x * s[1] + weight_x
+            ]);
+            let kernel = layer.weights.slice(s![f, .., .., ..]);
+            *output = (&window * &kernel).sum() + layer.biases[f];
+        }
+        outputs
+    }
+
+    #[test]
+    fn matches_a_naive_convolution() {
+        for (strides, padding) in [(1, 0), (2, 0), (1, 1), (2, 1)] {
+            let mut layer = layer(strides, padding);
+            let expected = reference(&layer, &inputs());
+            let outputs = layer.forward_propagate(inputs().into_dyn());
+            let error = (&outputs.into_dimensionality::<Ix4>().unwrap() - &expected)
+                .iter()
+                .fold(0f32, |max, x| max.max(x.abs()));
+            assert!(error < 1e-5, "strides {} padding {}", strides, padding);
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        for (strides, padding) in [(1, 0), (2, 1)] {
+            let mut layer = layer(strides, padding);
+            let outputs = layer.forward_propagate(inputs().into_dyn());
+            let d_outputs = gradcheck::d_outputs(outputs.shape());
+            let d_inputs = layer.backward_propagate(d_outputs.clone(), true);
+            assert_eq!(d_inputs.shape(), [2, 2, 5, 6]);
+            let loss = |layer: &Conv2DCPULayer, inputs: &Array4<f32>| {
+                gradcheck::loss(reference(layer, inputs).into_dyn(), &d_outputs)
+            };
+            for index in [[0, 0, 0, 0], [1, 1, 4, 5], [0, 1, 2, 3]] {
+                let numeric = derivative(&mut inputs(), |x| &mut x[index], |x| loss(&layer, x));
+                assert!((numeric - d_inputs[IxDyn(&index)]).abs() < 1e-2);
+            }
+            for index in [[0, 0, 0, 0], [2, 1, 2, 1], [1, 0, 1, 1]] {
+                let numeric = derivative(
+                    &mut layer,
+                    |layer| &mut layer.weights[index],
+                    |layer| loss(layer, &inputs()),
+                );
+                assert!((numeric - layer.d_weights[index]).abs() < 1e-2);
+            }
+        }
     }
 }
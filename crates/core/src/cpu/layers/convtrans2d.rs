@@ -1,6 +1,8 @@
-use ndarray::{s, Array1, Array4, ArrayD, Dimension, Ix1, Ix4, IxDyn};
-use std::ops::{Add, AddAssign, Mul};
+use ndarray::{s, Array1, Array2, Array3, Array4, ArrayD, Axis, Dimension, Ix1, Ix4, IxDyn, Zip};
+use ndarray_rand::rand::rngs::StdRng;
+use std::ops::Add;
 
+use super::im2col::{col2im, im2col};
 use crate::{CPUInit, CPURegularizer, ConvTranspose2DLayer, Init, Tensors};
 
 pub struct ConvTranspose2DCPULayer {
@@ -26,14 +28,19 @@ pub struct ConvTranspose2DCPULayer {
 }
 
 impl ConvTranspose2DCPULayer {
-    pub fn new(config: ConvTranspose2DLayer, size: IxDyn, tensors: Option<Tensors>) -> Self {
+    pub fn new(
+        config: ConvTranspose2DLayer,
+        size: IxDyn,
+        tensors: Option<Tensors>,
+        rng: &mut StdRng,
+    ) -> Self {
         let strides = config.strides.unwrap_or(vec![1, 1]);
         let padding = config.padding.unwrap_or(vec![0, 0]);
         let input_y = size[2] + 2 * padding[0];
         let input_x = size[3] + 2 * padding[1];
-        let output_y = (input_y - 1) * strides[0] - config.kernel_size[2] + 2;
-        let output_x = (input_x - 1) * strides[1] - config.kernel_size[3] + 2;
-        let input_size = Ix4(size[0], size[1], input_y, input_x);
+        let output_y = (input_y - 1) * strides[0] + 2 - config.kernel_size[2];
+        let output_x = (input_x - 1) * strides[1] + 2 - config.kernel_size[3];
+        let input_size = Ix4(0, size[1], input_y, input_x);
         let weight_size = IxDyn(config.kernel_size.as_slice());
         let output_size = Ix4(size[0], weight_size[0], output_y, output_x);
         let (weights, biases) = if let Some(Tensors::Conv(tensors)) = tensors {
@@ -43,6 +50,7 @@ impl ConvTranspose2DCPULayer {
                 ArrayD::from_shape_vec(tensor.shape, tensor.data).unwrap()
             } else {
                 CPUInit::from_default(config.init, Init::Xavier).init(
+                    rng,
                     weight_size.clone(),
                     size[1] * input_y * input_x,
                     weight_size[0] * output_y * output_x,
@@ -75,92 +83,274 @@ impl ConvTranspose2DCPULayer {
         self.output_size.as_array_view().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let input_size = self.inputs.shape();
-        self.inputs = Array4::zeros((batches, input_size[1], input_size[2], input_size[3]));
-        self.output_size[0] = batches;
+    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.inputs = self.pad(inputs);
+        self.convolve(&self.inputs).into_dyn()
     }
 
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.convolve(&self.pad(inputs)).into_dyn()
+    }
+
+    /// Copies the inputs into a zeroed buffer surrounded by the padding.
+    fn pad(&self, inputs: ArrayD<f32>) -> Array4<f32> {
         let inputs = inputs.into_dimensionality::<Ix4>().unwrap();
-        let (batches, _, input_y, input_x) = self.inputs.dim();
-        let unpadded_y = self.padding[0]..input_y - self.padding[0];
-        let unpadded_x = self.padding[1]..input_x - self.padding[1];
-        self.inputs
+        let (batches, channels, input_y, input_x) = inputs.dim();
+        let mut padded = Array4::zeros((
+            batches,
+            channels,
+            input_y + 2 * self.padding[0],
+            input_x + 2 * self.padding[1],
+        ));
+        let unpadded_y = self.padding[0]..self.padding[0] + input_y;
+        let unpadded_x = self.padding[1]..self.padding[1] + input_x;
+        padded
             .slice_mut(s![.., .., unpadded_y, unpadded_x])
             .assign(&inputs);
+        padded
+    }
 
+    /// The kernel as a `(channels, filters * y * x)` matrix, so each input
+    /// pixel maps to the columns of one output window.
+    fn kernel_matrix(&self) -> Array2<f32> {
+        let (filters, channels, weight_y, weight_x) = self.weights.dim();
+        self.weights
+            .view()
+            .permuted_axes([1, 0, 2, 3])
+            .to_shape((channels, filters * weight_y * weight_x))
+            .unwrap()
+            .into_owned()
+    }
+
+    /// The size of the full transposed convolution of the padded inputs. The
+    /// output keeps its interior and drops `kernel - 1` from each side.
+    fn full_size(&self, input_y: usize, input_x: usize) -> (usize, usize) {
+        let (_, _, weight_y, weight_x) = self.weights.dim();
+        (
+            (input_y - 1) * self.strides[0] + weight_y,
+            (input_x - 1) * self.strides[1] + weight_x,
+        )
+    }
+
+    fn convolve(&self, inputs: &Array4<f32>) -> Array4<f32> {
+        let (batches, channels, input_y, input_x) = inputs.dim();
         let (filters, _, weight_y, weight_x) = self.weights.dim();
+        let (_, _, output_y, output_x) = self.output_size.into_pattern();
+        let (full_y, full_x) = self.full_size(input_y, input_x);
+        let weights = self.kernel_matrix();
+        let biases = self
+            .biases
+            .view()
+            .into_shape_with_order((filters, 1, 1))
+            .unwrap();
 
-        let mut outputs = Array4::zeros(self.output_size);
-        for b in 0..batches {
-            for f in 0..filters {
-                let mut h = 0;
-                for y in (0..input_y).step_by(self.strides[0]) {
-                    let mut w = 0;
-                    for x in (0..input_x).step_by(self.strides[1]) {
-                        outputs
-                            .slice_mut(s![b, .., y..y + weight_y, x..x + weight_x])
-                            .add_assign(
-                                &self.inputs[(b, f, h, w)]
-                                    .mul(&self.weights.slice(s![f, .., .., ..]))
-                                    .add(self.biases[f]),
-                            );
-                        w += 1;
-                    }
-                    h += 1;
-                }
+        let mut outputs = Array4::zeros((batches, filters, output_y, output_x));
+        for_each!(
+            Zip::from(outputs.outer_iter_mut()).and(inputs.outer_iter()),
+            |mut outputs, inputs| {
+                let inputs = inputs.to_shape((channels, input_y * input_x)).unwrap();
+                let cols = weights.t().dot(&inputs);
+                let mut full = Array3::zeros((filters, full_y, full_x));
+                col2im(
+                    cols.view(),
+                    full.view_mut(),
+                    (weight_y, weight_x),
+                    &self.strides,
+                    (input_y, input_x),
+                );
+                let cropped = full.slice(s![
+                    ..,
+                    weight_y - 1..weight_y - 1 + output_y,
+                    weight_x - 1..weight_x - 1 + output_x
+                ]);
+                outputs.assign(&cropped.add(&biases));
             }
-        }
-
-        outputs.into_dyn()
+        );
+        outputs
     }
 
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         let d_outputs = d_outputs.into_dimensionality::<Ix4>().unwrap();
 
-        let (batches, _, input_y, input_x) = self.inputs.dim();
+        let (batches, channels, input_y, input_x) = self.inputs.dim();
         let (filters, _, weight_y, weight_x) = self.weights.dim();
-        let unpadded_y = input_y - self.padding[0];
-        let unpadded_x = input_x - self.padding[1];
+        let (_, _, output_y, output_x) = d_outputs.dim();
+        let (full_y, full_x) = self.full_size(input_y, input_x);
+        let weights = self.kernel_matrix();
 
         let mut d_inputs = Array4::zeros(self.inputs.dim());
-        self.d_weights = Array4::zeros(self.weights.dim());
-        self.d_biases = Array1::<f32>::zeros(self.biases.dim());
-        for b in 0..batches {
-            for f in 0..filters {
-                for y in (self.padding[0]..unpadded_y).step_by(self.strides[0]) {
-                    for x in (self.padding[1]..unpadded_x).step_by(self.strides[1]) {
-                        d_inputs.slice_mut(s![b, .., y, x]).add_assign(
-                            &self
-                                .weights
-                                .slice(s![f, .., .., ..])
-                                .mul(&d_outputs.slice(s![b, f, y..y + weight_y, x..x + weight_x])),
-                        );
-                        self.d_weights.slice_mut(s![f, .., .., ..]).add_assign(
-                            &self.inputs.slice(s![b, .., y, x]).mul(&d_outputs.slice(s![
-                                b,
-                                f,
-                                y..y + weight_y,
-                                x..x + weight_x
-                            ])),
-                        );
-                        self.d_biases[f] += d_outputs[(b, f, y, x)];
-                    }
+        let mut d_weights = Array3::zeros((batches, channels, filters * weight_y * weight_x));
+        for_each!(
+            Zip::from(d_inputs.outer_iter_mut())
+                .and(d_weights.outer_iter_mut())
+                .and(self.inputs.outer_iter())
+                .and(d_outputs.outer_iter()),
+            |mut d_inputs, mut d_weights, inputs, d_outputs| {
+                let mut full = Array3::zeros((filters, full_y, full_x));
+                full.slice_mut(s![
+                    ..,
+                    weight_y - 1..weight_y - 1 + output_y,
+                    weight_x - 1..weight_x - 1 + output_x
+                ])
+                .assign(&d_outputs);
+                let cols = im2col(
+                    full.view(),
+                    (weight_y, weight_x),
+                    &self.strides,
+                    (input_y, input_x),
+                );
+                let d_product = weights.dot(&cols);
+                d_inputs.assign(&d_product.to_shape((channels, input_y, input_x)).unwrap());
+                if trainable {
+                    let inputs = inputs.to_shape((channels, input_y * input_x)).unwrap();
+                    d_weights.assign(&inputs.dot(&cols.t()));
                 }
             }
+        );
+        if trainable {
+            self.d_weights = d_weights
+                .sum_axis(Axis(0))
+                .into_shape_with_order((channels, filters, weight_y, weight_x))
+                .unwrap()
+                .permuted_axes([1, 0, 2, 3])
+                .as_standard_layout()
+                .into_owned();
+            self.d_biases = d_outputs
+                .sum_axis(Axis(3))
+                .sum_axis(Axis(2))
+                .sum_axis(Axis(0));
+
+            self.l_weights = self
+                .regularizer
+                .coeff(&self.weights.clone().into_dyn())
+                .into_dimensionality::<Ix4>()
+                .unwrap();
+            self.l_biases = self
+                .regularizer
+                .coeff(&self.biases.clone().into_dyn())
+                .into_dimensionality::<Ix1>()
+                .unwrap();
         }
+        let (_, _, padded_y, padded_x) = d_inputs.dim();
+        d_inputs
+            .slice(s![
+                ..,
+                ..,
+                self.padding[0]..padded_y - self.padding[0],
+                self.padding[1]..padded_x - self.padding[1]
+            ])
+            .to_owned()
+            .into_dyn()
+    }
+}
 
-        self.l_weights = self
-            .regularizer
-            .coeff(&self.weights.clone().into_dyn())
-            .into_dimensionality::<Ix4>()
-            .unwrap();
-        self.l_biases = self
-            .regularizer
-            .coeff(&self.biases.clone().into_dyn())
-            .into_dimensionality::<Ix1>()
-            .unwrap();
-        d_inputs.into_dyn()
+#[cfg(test)]
+mod tests {
+    use ndarray::Ix4;
+
+    use super::*;
+    use crate::cpu::layers::gradcheck::{self, derivative};
+    use crate::seeded_rng;
+
+    fn layer(strides: usize, padding: usize) -> ConvTranspose2DCPULayer {
+        let config = ConvTranspose2DLayer {
+            init: None,
+            kernel: None,
+            kernel_size: vec![3, 2, 3, 2],
+            padding: Some(vec![padding, padding]),
+            strides: Some(vec![strides, strides]),
+            c: None,
+            l1_ratio: None,
+        };
+        let mut layer = ConvTranspose2DCPULayer::new(
+            config,
+            IxDyn(&[2, 2, 3, 4]),
+            None,
+            &mut seeded_rng(Some(1)),
+        );
+        layer.biases = Array1::from(vec![0.1, -0.2, 0.3]);
+        layer
+    }
+
+    fn inputs() -> Array4<f32> {
+        gradcheck::inputs((2, 2, 3, 4))
+    }
+
+    /// Scatters every padded input pixel over a kernel sized window of the
+    /// full output and keeps its interior.
+    fn reference(layer: &ConvTranspose2DCPULayer, inputs: &Array4<f32>) -> Array4<f32> {
+        let padded = layer.pad(inputs.clone().into_dyn());
+        let (batches, _, input_y, input_x) = padded.dim();
+        let (filters, _, weight_y, weight_x) = layer.weights.dim();
+        let (s, output) = (&layer.strides, layer.output_size.into_pattern());
+        let full_y = (input_y - 1) * s[0] + weight_y;
+        let full_x = (input_x - 1) * s[1] + weight_x;
+        let mut full = Array4::zeros((batches, filters, full_y, full_x));
+        for ((b, c, y, x), input) in padded.indexed_iter() {
+            for ((f, ky, kx), weight) in layer.weights.slice(s![.., c, .., ..]).indexed_iter() {
+                full[[b, f, y * s[0] + ky, x * s[1] + kx]] += input * weight;
+            }
+        }
+        let mut outputs = full
+            .slice(s![.., .., weight_y - 1.., weight_x - 1..])
+            .slice(s![.., .., ..output.2, ..output.3])
+            .to_owned();
+        for f in 0..filters {
+            outputs
+                .slice_mut(s![.., f, .., ..])
+                .mapv_inplace(|x| x + layer.biases[f]);
+        }
+        outputs
+    }
+
+    #[test]
+    fn keeps_the_output_size_of_padded_inputs() {
+        assert_eq!(layer(1, 0).output_size(), [2, 3, 1, 3]);
+        assert_eq!(layer(2, 0).output_size(), [2, 3, 3, 6]);
+        assert_eq!(layer(2, 1).output_size(), [2, 3, 7, 10]);
+    }
+
+    #[test]
+    fn matches_a_naive_transposed_convolution() {
+        for (strides, padding) in [(1, 0), (2, 0), (1, 1), (2, 1)] {
+            let mut layer = layer(strides, padding);
+            let expected = reference(&layer, &inputs());
+            let outputs = layer.forward_propagate(inputs().into_dyn());
+            assert_eq!(outputs.shape(), layer.output_size());
+            let error = (&outputs.into_dimensionality::<Ix4>().unwrap() - &expected)
+                .iter()
+                .fold(0f32, |max, x| max.max(x.abs()));
+            assert!(error < 1e-5, "strides {} padding {}", strides, padding);
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        for (strides, padding) in [(1, 0), (2, 1)] {
+            let mut layer = layer(strides, padding);
+            let outputs = layer.forward_propagate(inputs().into_dyn());
+            let d_outputs = gradcheck::d_outputs(outputs.shape());
+            let d_inputs = layer.backward_propagate(d_outputs.clone(), true);
+            let loss = |layer: &ConvTranspose2DCPULayer, inputs: &Array4<f32>| {
+                gradcheck::loss(reference(layer, inputs).into_dyn(), &d_outputs)
+            };
+            for index in [[0, 0, 0, 0], [1, 1, 2, 3], [0, 1, 1, 2]] {
+                let numeric = derivative(&mut inputs(), |x| &mut x[index], |x| loss(&layer, x));
+                assert!((numeric - d_inputs[IxDyn(&index)]).abs() < 1e-2);
+            }
+            for index in [[0, 0, 0, 0], [2, 1, 2, 1], [1, 0, 1, 1]] {
+                let numeric = derivative(
+                    &mut layer,
+                    |layer| &mut layer.weights[index],
+                    |layer| loss(layer, &inputs()),
+                );
+                assert!((numeric - layer.d_weights[index]).abs() < 1e-2);
+            }
+            let d_biases = d_outputs
+                .sum_axis(Axis(3))
+                .sum_axis(Axis(2))
+                .sum_axis(Axis(0));
+            assert_eq!(layer.d_biases.clone().into_dyn(), d_biases);
+        }
     }
 }
@@ -1,4 +1,5 @@
 use ndarray::{Array1, Array2, ArrayD, Axis, Dimension, Ix1, Ix2, IxDyn};
+use ndarray_rand::rand::rngs::StdRng;
 use std::ops::Add;
 
 use crate::{CPUInit, CPURegularizer, DenseLayer, Init, Tensors};
@@ -24,16 +25,21 @@ pub struct DenseCPULayer {
 }
 
 impl DenseCPULayer {
-    pub fn new(config: DenseLayer, size: IxDyn, tensors: Option<Tensors>) -> Self {
+    pub fn new(
+        config: DenseLayer,
+        size: IxDyn,
+        tensors: Option<Tensors>,
+        rng: &mut StdRng,
+    ) -> Self {
         let init = CPUInit::from_default(config.init, Init::Uniform);
-        let input_size = Ix2(size[0], size[1]);
+        let input_size = Ix2(0, size[1]);
         let weight_size = Ix2(size[1], config.size[0]);
         let output_size = Ix2(size[0], config.size[0]);
 
         let (weights, biases) = if let Some(Tensors::Dense(tensors)) = tensors {
             (tensors.weights, tensors.biases)
         } else {
-            let weights = init.init(weight_size.into_dyn(), size[1], config.size[0]);
+            let weights = init.init(rng, weight_size.into_dyn(), size[1], config.size[0]);
             let biases = ArrayD::zeros(config.size.clone());
             (weights, biases)
         };
@@ -55,22 +61,24 @@ impl DenseCPULayer {
         self.output_size.as_array_view().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let input_size = self.inputs.dim().1;
-        self.inputs = Array2::zeros((batches, input_size));
-        self.output_size[0] = batches;
-    }
-
     pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
         self.inputs = inputs.into_dimensionality::<Ix2>().unwrap();
         self.inputs.dot(&self.weights).add(&self.biases).into_dyn()
     }
 
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        let inputs = inputs.into_dimensionality::<Ix2>().unwrap();
+        inputs.dot(&self.weights).add(&self.biases).into_dyn()
+    }
+
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         let d_outputs = d_outputs.into_dimensionality::<Ix2>().unwrap();
         let mut weights_t = self.weights.view();
         weights_t.swap_axes(0, 1);
         let d_inputs = d_outputs.dot(&weights_t);
+        if !trainable {
+            return d_inputs.into_dyn();
+        }
         let mut inputs_t = self.inputs.view();
         inputs_t.swap_axes(0, 1);
         self.d_weights = inputs_t.dot(&d_outputs);
@@ -1,13 +1,14 @@
 use std::ops::Mul;
 
-use ndarray::{Array2, Array4, ArrayD, Axis, IxDyn};
-use ndarray_rand::{rand_distr::Uniform, RandomExt};
+use ndarray::{Array2, Array4, ArrayD, Axis, Ix4, IxDyn};
+use ndarray_rand::{rand::rngs::StdRng, rand_distr::Uniform, RandomExt};
 
 use crate::DropoutLayer;
 
 pub struct Dropout1DCPULayer {
     mask: ArrayD<f32>,
     probability: f32,
+    training: bool,
 }
 
 impl Dropout1DCPULayer {
@@ -15,6 +16,7 @@ impl Dropout1DCPULayer {
         Self {
             mask: ArrayD::zeros(size),
             probability: config.probability,
+            training: false,
         }
     }
 
@@ -22,15 +24,15 @@ impl Dropout1DCPULayer {
         self.mask.shape().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let mut output_size = self.mask.shape().to_vec();
-        output_size[0] = batches;
-        self.mask = ArrayD::zeros(output_size);
-    }
-
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>, training: bool) -> ArrayD<f32> {
+    pub fn forward_propagate(
+        &mut self,
+        inputs: ArrayD<f32>,
+        training: bool,
+        rng: &mut StdRng,
+    ) -> ArrayD<f32> {
+        self.training = training;
         if training {
-            self.mask = ArrayD::random(inputs.dim(), Uniform::new(0.0, 1.0))
+            self.mask = ArrayD::random_using(inputs.dim(), Uniform::new(0.0, 1.0), rng)
                 .map(|x| (if x > &self.probability { 1.0 } else { 0.0 }));
             inputs.mul(&self.mask).mul(1.0 / 1.0 - self.probability)
         } else {
@@ -38,7 +40,15 @@ impl Dropout1DCPULayer {
         }
     }
 
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        inputs
+    }
+
     pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+        if !self.training {
+            // An inference pass, e.g. of a frozen layer, used no mask.
+            return d_outputs;
+        }
         d_outputs.mul(&self.mask).mul(1.0 / 1.0 - self.probability)
     }
 }
@@ -46,6 +56,7 @@ impl Dropout1DCPULayer {
 pub struct Dropout2DCPULayer {
     mask: Array4<f32>,
     probability: f32,
+    training: bool,
 }
 
 impl Dropout2DCPULayer {
@@ -53,6 +64,7 @@ impl Dropout2DCPULayer {
         Self {
             mask: Array4::zeros([size[0], size[1], size[2], size[3]]),
             probability: config.probability,
+            training: false,
         }
     }
 
@@ -60,15 +72,16 @@ impl Dropout2DCPULayer {
         self.mask.shape().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let size = self.mask.dim();
-        self.mask = Array4::zeros([batches, size.1, size.2, size.3]);
-    }
-
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>, training: bool) -> ArrayD<f32> {
+    pub fn forward_propagate(
+        &mut self,
+        inputs: ArrayD<f32>,
+        training: bool,
+        rng: &mut StdRng,
+    ) -> ArrayD<f32> {
+        self.training = training;
         if training {
-            let size = self.mask.dim();
-            self.mask = Array2::random([size.0, size.1], Uniform::new(0.0, 1.0))
+            let size = inputs.view().into_dimensionality::<Ix4>().unwrap().dim();
+            self.mask = Array2::random_using([size.0, size.1], Uniform::new(0.0, 1.0), rng)
                 .map(|x| (if x > &self.probability { 1.0 } else { 0.0 }))
                 .insert_axis(Axis(2))
                 .insert_axis(Axis(3))
@@ -81,7 +94,15 @@ impl Dropout2DCPULayer {
         }
     }
 
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        inputs
+    }
+
     pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+        if !self.training {
+            // An inference pass, e.g. of a frozen layer, used no mask.
+            return d_outputs;
+        }
         d_outputs.mul(&self.mask).mul(1.0 / 1.0 - self.probability)
     }
 }
@@ -1,7 +1,8 @@
 use ndarray::{Array2, ArrayD, Axis, Ix2, IxDyn};
+use ndarray_rand::rand::rngs::StdRng;
 use std::ops::AddAssign;
 
-use crate::{CPUInit, CPURegularizer, EmbeddingLayer, Init};
+use crate::{CPUInit, CPURegularizer, EmbeddingLayer, Init, Tensors};
 
 pub struct EmbeddingCPULayer {
     pub input_size: IxDyn,
@@ -18,13 +19,26 @@ pub struct EmbeddingCPULayer {
 }
 
 impl EmbeddingCPULayer {
-    pub fn new(config: EmbeddingLayer, size: IxDyn) -> Self {
-        let init = CPUInit::from(Init::Uniform);
+    pub fn new(
+        config: EmbeddingLayer,
+        size: IxDyn,
+        tensors: Option<Tensors>,
+        rng: &mut StdRng,
+    ) -> Self {
         let output_size = vec![size[0], size[1], config.embedding_size];
-        let embeddings = init
-            .init(IxDyn(&[config.vocab_size, config.embedding_size]), 0, 0)
-            .into_dimensionality::<Ix2>()
-            .unwrap();
+        let embeddings = if let Some(Tensors::Embedding(tensors)) = tensors {
+            tensors.embeddings
+        } else {
+            let init = CPUInit::from(Init::Uniform);
+            init.init(
+                rng,
+                IxDyn(&[config.vocab_size, config.embedding_size]),
+                0,
+                0,
+            )
+        }
+        .into_dimensionality::<Ix2>()
+        .unwrap();
         let d_embeddings = Array2::zeros((config.vocab_size, config.embedding_size));
         Self {
             input_size: size,
@@ -46,13 +60,13 @@ impl EmbeddingCPULayer {
         self.output_size.clone()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        self.output_size[0] = batches
+    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.input_indices = inputs.iter().map(|&x| x as usize).collect();
+        self.infer(inputs)
     }
 
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
         let input_indices: Vec<usize> = inputs.iter().map(|&x| x as usize).collect();
-        self.input_indices = input_indices.clone();
         let embeddings = self.embeddings.select(Axis(0), input_indices.as_slice());
         //        let output_size = IxDyn(&self.output_size);
         embeddings
@@ -64,12 +78,22 @@ impl EmbeddingCPULayer {
             .unwrap()
     }
 
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         let indices = Array2::from_shape_vec(
             Ix2(d_outputs.shape()[0], self.input_size[1]),
             self.input_indices.clone(),
         )
         .unwrap();
+        let mut input_size = self.input_size.clone();
+        input_size[0] = d_outputs.shape()[0];
+        let d_inputs = ArrayD::from_shape_vec(
+            input_size,
+            self.input_indices.iter().map(|x| *x as f32).collect(),
+        )
+        .unwrap();
+        if !trainable {
+            return d_inputs;
+        }
         self.d_embeddings.fill(0.0);
         d_outputs
             .axis_iter(Axis(0))
@@ -88,12 +112,6 @@ impl EmbeddingCPULayer {
             .coeff(&self.embeddings.clone().into_dyn())
             .into_dimensionality::<Ix2>()
             .unwrap();
-        let mut input_size = self.input_size.clone();
-        input_size[0] = d_outputs.shape()[0];
-        ArrayD::from_shape_vec(
-            input_size,
-            self.input_indices.iter().map(|x| *x as f32).collect(),
-        )
-        .unwrap()
+        d_inputs
     }
 }
@@ -17,11 +17,11 @@ impl FlattenCPULayer {
         self.output_size.clone()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        self.output_size[0] = batches
+    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.infer(inputs)
     }
 
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
         let output_size = IxDyn(&[inputs.shape()[0], self.output_size[1]]);
         inputs.into_shape_with_order(output_size).unwrap()
     }
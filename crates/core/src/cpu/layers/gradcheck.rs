@@ -0,0 +1,45 @@
+//! Fixtures and central differences shared by the gradient tests of the
+//! layers. The loss of a check is the sum of the outputs weighted by
+//! `d_outputs`, so `d_outputs` is also the gradient fed to the layer.
+
+use ndarray::{Array, ArrayD, Dimension, IntoDimension};
+
+const EPS: f32 = 1e-2;
+
+/// Smooth inputs with both signs.
+pub fn inputs<Sh: IntoDimension>(shape: Sh) -> Array<f32, Sh::Dim> {
+    let shape = shape.into_dimension();
+    Array::range(0.0, shape.size() as f32, 1.0)
+        .mapv(|x: f32| (x * 0.7).sin())
+        .into_shape_with_order(shape)
+        .unwrap()
+}
+
+/// Weights of the outputs in the loss, for outputs of `shape`.
+pub fn d_outputs(shape: &[usize]) -> ArrayD<f32> {
+    let len = shape.iter().product::<usize>();
+    Array::range(0.0, len as f32, 1.0)
+        .mapv(|x: f32| (x * 0.3).cos())
+        .into_shape_with_order(shape)
+        .unwrap()
+}
+
+pub fn loss(outputs: ArrayD<f32>, d_outputs: &ArrayD<f32>) -> f32 {
+    (outputs * d_outputs).sum()
+}
+
+/// Estimates the derivative of `loss` with respect to the value `at` points
+/// to inside `target`, and restores that value.
+pub fn derivative<T>(
+    target: &mut T,
+    at: impl Fn(&mut T) -> &mut f32,
+    loss: impl Fn(&T) -> f32,
+) -> f32 {
+    let value = *at(target);
+    *at(target) = value + EPS;
+    let plus = loss(target);
+    *at(target) = value - EPS;
+    let minus = loss(target);
+    *at(target) = value;
+    (plus - minus) / (2.0 * EPS)
+}
@@ -0,0 +1,345 @@
+use crate::{Activation, CPUActivation, CPUInit, CPURegularizer, GRULayer, Init, Tensors};
+use ndarray::{
+    s, Array2, Array3, Array4, ArrayD, ArrayView2, Axis, Dimension, Ix2, Ix3, IxDyn, Zip,
+};
+use ndarray_rand::rand::rngs::StdRng;
+use std::ops::AddAssign;
+/// Indices
+/// 0 - Update Gate
+/// 1 - Reset Gate
+/// 2 - Candidate
+pub struct GRUCPULayer {
+    pub output_size: IxDyn,
+    pub inputs: Array3<f32>,
+    pub return_sequences: bool,
+    pub activation_h: CPUActivation,
+    pub activation_o: CPUActivation,
+
+    pub w_ih: Array3<f32>,
+    pub w_hh: Array3<f32>,
+    pub biases: Array2<f32>,
+
+    pub d_w_ih: Array3<f32>,
+    pub d_w_hh: Array3<f32>,
+    pub d_biases: Array2<f32>,
+
+    pub l_w_ih: Array3<f32>,
+    pub l_w_hh: Array3<f32>,
+    pub l_biases: Array2<f32>,
+
+    // cache, indexed by time step
+    pub states: Array3<f32>,
+    pub linear: Array4<f32>,
+    pub gates: Array4<f32>,
+
+    pub regularizer: CPURegularizer,
+}
+
+impl GRUCPULayer {
+    pub fn new(config: GRULayer, size: IxDyn, tensors: Option<Tensors>, rng: &mut StdRng) -> Self {
+        let return_sequences = config.return_sequences.unwrap_or(false);
+        let init = CPUInit::from_default(config.init, Init::Uniform);
+        let weight_size = Ix3(3, size[2], config.size);
+        let output_size = if return_sequences {
+            IxDyn(&[size[0], size[1], config.size])
+        } else {
+            IxDyn(&[size[0], config.size])
+        };
+
+        let (w_ih, w_hh, biases) = if let Some(Tensors::Recurrent(tensors)) = tensors {
+            (tensors.w_ih, tensors.w_hh, tensors.biases)
+        } else {
+            (
+                init.init(rng, weight_size.into_dyn(), size[2], config.size),
+                init.init(
+                    rng,
+                    IxDyn(&[3, config.size, config.size]),
+                    size[2],
+                    config.size,
+                ),
+                ArrayD::zeros(vec![3, config.size]),
+            )
+        };
+
+        Self {
+            return_sequences,
+            output_size,
+            inputs: Array3::zeros((0, size[1], size[2])),
+            w_ih: w_ih.into_dimensionality::<Ix3>().unwrap(),
+            w_hh: w_hh.into_dimensionality::<Ix3>().unwrap(),
+            biases: biases.into_dimensionality::<Ix2>().unwrap(),
+            d_w_ih: Array3::zeros(weight_size),
+            d_w_hh: Array3::zeros((3, config.size, config.size)),
+            d_biases: Array2::zeros((3, config.size)),
+            l_w_ih: Array3::zeros(weight_size),
+            l_w_hh: Array3::zeros((3, config.size, config.size)),
+            l_biases: Array2::zeros((3, config.size)),
+            states: Array3::zeros((size[1] + 1, 0, config.size)),
+            linear: Array4::zeros((size[1], 3, 0, config.size)),
+            gates: Array4::zeros((size[1], 3, 0, config.size)),
+            regularizer: CPURegularizer::from(
+                config.c.unwrap_or(0.0),
+                config.l1_ratio.unwrap_or(1.0),
+            ),
+
+            activation_h: CPUActivation::from(
+                config.recurrent_activation.unwrap_or(Activation::Sigmoid),
+            ),
+            activation_o: CPUActivation::from(config.activation.unwrap_or(Activation::Tanh)),
+        }
+    }
+
+    pub fn output_size(&self) -> Vec<usize> {
+        self.output_size.as_array_view().to_vec()
+    }
+
+    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        self.inputs = inputs.into_dimensionality::<Ix3>().unwrap();
+        let (batches, sequence_length, _) = self.inputs.dim();
+        let output_size = self.w_ih.shape()[2];
+        self.states = Array3::zeros((sequence_length + 1, batches, output_size));
+        self.linear = Array4::zeros((sequence_length, 3, batches, output_size));
+        self.gates = Array4::zeros(self.linear.raw_dim());
+
+        for t in 0..sequence_length {
+            let x_t = self.inputs.slice(s![.., t, ..]);
+            let h_t = self.states.index_axis(Axis(0), t).to_owned();
+            let (linear, gates, h_t) = self.step(x_t, &h_t);
+            for (i, (linear, gate)) in linear.iter().zip(&gates).enumerate() {
+                self.linear.slice_mut(s![t, i, .., ..]).assign(linear);
+                self.gates.slice_mut(s![t, i, .., ..]).assign(gate);
+            }
+            self.states.index_axis_mut(Axis(0), t + 1).assign(&h_t);
+        }
+
+        if self.return_sequences {
+            let mut outputs = self.states.slice(s![1.., .., ..]).to_owned();
+            outputs.swap_axes(0, 1);
+            outputs.as_standard_layout().into_owned().into_dyn()
+        } else {
+            self.states
+                .index_axis(Axis(0), sequence_length)
+                .to_owned()
+                .into_dyn()
+        }
+    }
+
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        let inputs = inputs.into_dimensionality::<Ix3>().unwrap();
+        let (batches, sequence_length, _) = inputs.dim();
+        let output_size = self.w_ih.shape()[2];
+        let mut h_t = Array2::zeros((batches, output_size));
+        let mut outputs = Array3::zeros((batches, sequence_length, output_size));
+
+        for t in 0..sequence_length {
+            (_, _, h_t) = self.step(inputs.slice(s![.., t, ..]), &h_t);
+            outputs.slice_mut(s![.., t, ..]).assign(&h_t);
+        }
+
+        if self.return_sequences {
+            outputs.into_dyn()
+        } else {
+            h_t.into_dyn()
+        }
+    }
+
+    /// Runs one time step and returns the gate inputs, the gates and the
+    /// new state. The update and reset gates are computed on their own
+    /// threads when the `parallel` feature is enabled.
+    fn step(
+        &self,
+        x_t: ArrayView2<f32>,
+        h_t: &Array2<f32>,
+    ) -> ([Array2<f32>; 3], [Array2<f32>; 3], Array2<f32>) {
+        let linear = |i: usize, h_t: &Array2<f32>| {
+            x_t.dot(&self.w_ih.index_axis(Axis(0), i))
+                + h_t.dot(&self.w_hh.index_axis(Axis(0), i))
+                + self.biases.index_axis(Axis(0), i)
+        };
+        #[cfg(feature = "parallel")]
+        let (a_z, a_r) = rayon::join(|| linear(0, h_t), || linear(1, h_t));
+        #[cfg(not(feature = "parallel"))]
+        let (a_z, a_r) = (linear(0, h_t), linear(1, h_t));
+        let z_t = a_z.mapv(|x| (self.activation_h.activate)(&x));
+        let r_t = a_r.mapv(|x| (self.activation_h.activate)(&x));
+        let a_n = linear(2, &(&r_t * h_t));
+        let n_t = a_n.mapv(|x| (self.activation_o.activate)(&x));
+        let h_next = &z_t * h_t + &(1.0 - &z_t) * &n_t;
+        ([a_z, a_r, a_n], [z_t, r_t, n_t], h_next)
+    }
+
+    /// Backpropagates through time. The gradient of a sequence output
+    /// reaches every step, the gradient of the last state only the last.
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
+        let (batches, sequence_length, _) = self.inputs.dim();
+        let hidden_size = self.w_hh.shape()[1];
+        let d_outputs = match d_outputs.ndim() {
+            2 => {
+                let mut d_states = Array3::zeros((batches, sequence_length, hidden_size));
+                d_states
+                    .index_axis_mut(Axis(1), sequence_length - 1)
+                    .assign(&d_outputs.into_dimensionality::<Ix2>().unwrap());
+                d_states
+            }
+            _ => d_outputs.into_dimensionality::<Ix3>().unwrap(),
+        };
+
+        self.d_w_ih = Array3::zeros(self.w_ih.raw_dim());
+        self.d_w_hh = Array3::zeros(self.w_hh.raw_dim());
+        self.d_biases = Array2::zeros(self.biases.raw_dim());
+        let mut d_inputs = Array3::zeros(self.inputs.raw_dim());
+        let mut d_h = Array2::<f32>::zeros((batches, hidden_size));
+
+        for t in (0..sequence_length).rev() {
+            d_h += &d_outputs.index_axis(Axis(1), t);
+            let x_t = self.inputs.index_axis(Axis(1), t);
+            let h_prev = self.states.index_axis(Axis(0), t);
+            let linear = self.linear.index_axis(Axis(0), t);
+            let gates = self.gates.index_axis(Axis(0), t);
+            let derivative = |i: usize, activation: &CPUActivation| {
+                Zip::from(linear.index_axis(Axis(0), i))
+                    .and(gates.index_axis(Axis(0), i))
+                    .map_collect(|x, y| activation.derivative(x, y))
+            };
+            let z_t = gates.index_axis(Axis(0), 0);
+            let r_t = gates.index_axis(Axis(0), 1);
+            let n_t = gates.index_axis(Axis(0), 2);
+
+            let d_z = &d_h * &(&h_prev - &n_t) * derivative(0, &self.activation_h);
+            let d_n = &d_h * &(1.0 - &z_t) * derivative(2, &self.activation_o);
+            let d_reset_h = d_n.dot(&self.w_hh.index_axis(Axis(0), 2).t());
+            let d_r = &d_reset_h * &h_prev * derivative(1, &self.activation_h);
+            let reset_h = &r_t * &h_prev;
+
+            let mut d_x = d_inputs.index_axis_mut(Axis(1), t);
+            for (i, (d_gate, h)) in [(&d_z, &h_prev.view()), (&d_r, &h_prev.view())]
+                .into_iter()
+                .chain([(&d_n, &reset_h.view())])
+                .enumerate()
+            {
+                if trainable {
+                    self.d_w_ih
+                        .index_axis_mut(Axis(0), i)
+                        .add_assign(&x_t.t().dot(d_gate));
+                    self.d_w_hh
+                        .index_axis_mut(Axis(0), i)
+                        .add_assign(&h.t().dot(d_gate));
+                    self.d_biases
+                        .index_axis_mut(Axis(0), i)
+                        .add_assign(&d_gate.sum_axis(Axis(0)));
+                }
+                d_x.add_assign(&d_gate.dot(&self.w_ih.index_axis(Axis(0), i).t()));
+            }
+
+            d_h = &d_h * &z_t
+                + &d_reset_h * &r_t
+                + d_z.dot(&self.w_hh.index_axis(Axis(0), 0).t())
+                + d_r.dot(&self.w_hh.index_axis(Axis(0), 1).t());
+        }
+
+        if trainable {
+            self.l_w_ih = self
+                .regularizer
+                .coeff(&self.w_ih.clone().into_dyn())
+                .into_dimensionality::<Ix3>()
+                .unwrap();
+            self.l_w_hh = self
+                .regularizer
+                .coeff(&self.w_hh.clone().into_dyn())
+                .into_dimensionality::<Ix3>()
+                .unwrap();
+            self.l_biases = self
+                .regularizer
+                .coeff(&self.biases.clone().into_dyn())
+                .into_dimensionality::<Ix2>()
+                .unwrap();
+        }
+        d_inputs.into_dyn()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array;
+
+    use super::*;
+    use crate::cpu::layers::gradcheck::{self, derivative};
+    use crate::seeded_rng;
+
+    fn layer(return_sequences: bool, activation: Activation, gate: Activation) -> GRUCPULayer {
+        let config = GRULayer {
+            size: 4,
+            init: None,
+            c: None,
+            l1_ratio: None,
+            return_sequences: Some(return_sequences),
+            recurrent_activation: Some(gate),
+            activation: Some(activation),
+        };
+        let size = IxDyn(&[2, 5, 3]);
+        let mut layer = GRUCPULayer::new(config, size, None, &mut seeded_rng(Some(7)));
+        layer.biases = Array::range(0.0, 12.0, 1.0)
+            .mapv(|x: f32| (x * 1.3).sin() * 0.5)
+            .into_shape_with_order((3, 4))
+            .unwrap();
+        layer
+    }
+
+    fn inputs() -> Array3<f32> {
+        gradcheck::inputs((2, 5, 3))
+    }
+
+    /// Weights (0), recurrent weights (1) or biases (2) at `index`.
+    fn param(layer: &mut GRUCPULayer, which: usize, index: [usize; 3]) -> &mut f32 {
+        match which {
+            0 => &mut layer.w_ih[index],
+            1 => &mut layer.w_hh[index],
+            _ => &mut layer.biases[[index[0], index[1]]],
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let cases = [
+            (true, Activation::Tanh, Activation::Sigmoid),
+            (false, Activation::Tanh, Activation::Sigmoid),
+            (false, Activation::Elu, Activation::Tanh),
+        ];
+        for (return_sequences, activation, gate) in cases {
+            let mut layer = layer(return_sequences, activation, gate);
+            let outputs = layer.forward_propagate(inputs().into_dyn());
+            assert_eq!(outputs, layer.infer(inputs().into_dyn()));
+            let d_outputs = gradcheck::d_outputs(outputs.shape());
+            let d_inputs = layer.backward_propagate(d_outputs.clone(), true);
+            let loss = |layer: &GRUCPULayer, inputs: &Array3<f32>| {
+                gradcheck::loss(layer.infer(inputs.clone().into_dyn()), &d_outputs)
+            };
+            for index in [[0, 0, 0], [1, 2, 1], [0, 4, 2], [1, 0, 2]] {
+                let numeric = derivative(&mut inputs(), |x| &mut x[index], |x| loss(&layer, x));
+                let error = (numeric - d_inputs[IxDyn(&index)]).abs();
+                assert!(error < 5e-3, "inputs {:?}", index);
+            }
+            let params = [
+                (0, [0, 1, 2]),
+                (0, [2, 2, 3]),
+                (1, [1, 3, 0]),
+                (1, [2, 0, 1]),
+                (2, [0, 0, 0]),
+                (2, [2, 3, 0]),
+            ];
+            for (which, index) in params {
+                let analytic = match which {
+                    0 => layer.d_w_ih[index],
+                    1 => layer.d_w_hh[index],
+                    _ => layer.d_biases[[index[0], index[1]]],
+                };
+                let numeric = derivative(
+                    &mut layer,
+                    |layer| param(layer, which, index),
+                    |layer| loss(layer, &inputs()),
+                );
+                assert!((numeric - analytic).abs() < 5e-3, "{} {:?}", which, index);
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use ndarray::{s, Array2, ArrayView2, ArrayView3, ArrayViewMut3, Axis};
+use std::ops::AddAssign;
+
+/// Unrolls every `weight_y` x `weight_x` window of the input into a column,
+/// giving a `(channels * weight_y * weight_x, output_y * output_x)` matrix so
+/// a convolution becomes a single matrix product.
+pub fn im2col(
+    inputs: ArrayView3<f32>,
+    kernel: (usize, usize),
+    strides: &[usize],
+    output: (usize, usize),
+) -> Array2<f32> {
+    let channels = inputs.dim().0;
+    let (weight_y, weight_x) = kernel;
+    let (output_y, output_x) = output;
+    let end_y = (output_y - 1) * strides[0] + 1;
+    let end_x = (output_x - 1) * strides[1] + 1;
+
+    let mut cols = Array2::zeros((channels * weight_y * weight_x, output_y * output_x));
+    for (row, mut col) in cols.axis_iter_mut(Axis(0)).enumerate() {
+        let (c, i, j) = (
+            row / (weight_y * weight_x),
+            row / weight_x % weight_y,
+            row % weight_x,
+        );
+        let window = inputs.slice(s![
+            c,
+            i..i + end_y;strides[0],
+            j..j + end_x;strides[1]
+        ]);
+        col.assign(&window.to_shape(output_y * output_x).unwrap());
+    }
+    cols
+}
+
+/// Adds every column back onto the window it was taken from, the adjoint of
+/// [`im2col`]. Overlapping windows accumulate.
+pub fn col2im(
+    cols: ArrayView2<f32>,
+    mut outputs: ArrayViewMut3<f32>,
+    kernel: (usize, usize),
+    strides: &[usize],
+    output: (usize, usize),
+) {
+    let (weight_y, weight_x) = kernel;
+    let (output_y, output_x) = output;
+    let end_y = (output_y - 1) * strides[0] + 1;
+    let end_x = (output_x - 1) * strides[1] + 1;
+
+    for (row, col) in cols.axis_iter(Axis(0)).enumerate() {
+        let (c, i, j) = (
+            row / (weight_y * weight_x),
+            row / weight_x % weight_y,
+            row % weight_x,
+        );
+        outputs
+            .slice_mut(s![c, i..i + end_y;strides[0], j..j + end_x;strides[1]])
+            .add_assign(&col.to_shape((output_y, output_x)).unwrap());
+    }
+}
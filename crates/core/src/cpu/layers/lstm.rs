@@ -2,7 +2,10 @@ use crate::{
     Activation, CPUActivation, CPUInit, CPURegularizer, Init, LSTMLayer, LayerNorm, Tensors,
 };
 use core::f32;
-use ndarray::{concatenate, s, Array2, Array3, ArrayD, Axis, Dimension, Ix2, Ix3, IxDyn};
+use ndarray::{
+    concatenate, s, Array1, Array2, Array3, ArrayD, Axis, Dimension, Ix2, Ix3, IxDyn, Zip,
+};
+use ndarray_rand::rand::rngs::StdRng;
 use std::ops::AddAssign;
 /// Indices
 /// 0 - Input Gate
@@ -43,10 +46,10 @@ pub struct LSTMCPULayer {
 
 #[allow(unused_mut)]
 impl LSTMCPULayer {
-    pub fn new(config: LSTMLayer, size: IxDyn, _tensors: Option<Tensors>) -> Self {
+    pub fn new(config: LSTMLayer, size: IxDyn, tensors: Option<Tensors>, rng: &mut StdRng) -> Self {
         let return_sequences = config.return_sequences.unwrap_or(false);
         let init = CPUInit::from_default(config.init, Init::Uniform);
-        let input_size = Ix3(size[0], size[1], size[2]);
+        let input_size = Ix3(0, size[1], size[2]);
         let weight_size = Ix3(4, size[2], config.size);
         let output_size = if return_sequences {
             IxDyn(&[size[0], size[1], config.size])
@@ -54,32 +57,41 @@ impl LSTMCPULayer {
             IxDyn(&[size[0], config.size])
         };
 
+        let (w_ih, w_hh, biases) = if let Some(Tensors::Recurrent(tensors)) = tensors {
+            (tensors.w_ih, tensors.w_hh, tensors.biases)
+        } else {
+            (
+                init.init(rng, weight_size.into_dyn(), size[2], config.size),
+                init.init(
+                    rng,
+                    IxDyn(&[4, config.size, config.size]),
+                    size[2],
+                    config.size,
+                ),
+                ArrayD::zeros(vec![4, config.size]),
+            )
+        };
+
         Self {
             return_sequences,
             output_size,
             layer_norm: LayerNorm::new(config.size, f32::EPSILON),
             inputs: Array3::zeros(input_size),
-            w_ih: init
-                .init(weight_size.into_dyn(), size[2], config.size)
-                .into_dimensionality::<Ix3>()
-                .unwrap(),
-            w_hh: init
-                .init(IxDyn(&[4, config.size, config.size]), size[2], config.size)
-                .into_dimensionality::<Ix3>()
-                .unwrap(),
-            biases: Array2::zeros((4, config.size)),
+            w_ih: w_ih.into_dimensionality::<Ix3>().unwrap(),
+            w_hh: w_hh.into_dimensionality::<Ix3>().unwrap(),
+            biases: biases.into_dimensionality::<Ix2>().unwrap(),
             d_w_ih: Array3::zeros(weight_size),
             d_w_hh: Array3::zeros((4, config.size, config.size)),
             d_biases: Array2::zeros((4, config.size)),
             l_w_ih: Array3::zeros(weight_size),
             l_w_hh: Array3::zeros((4, config.size, config.size)),
             l_biases: Array2::zeros((4, config.size)),
-            i_t: Array3::zeros((size[1], size[0], config.size)),
-            f_t: Array3::zeros((size[1], size[0], config.size)),
-            o_t: Array3::zeros((size[1], size[0], config.size)),
-            g_t: Array3::zeros((size[1], size[0], config.size)),
-            h_prev: Array2::zeros((size[0], config.size)),
-            c_prev: Array2::zeros((size[0], config.size)),
+            i_t: Array3::zeros((size[1], 0, config.size)),
+            f_t: Array3::zeros((size[1], 0, config.size)),
+            o_t: Array3::zeros((size[1], 0, config.size)),
+            g_t: Array3::zeros((size[1], 0, config.size)),
+            h_prev: Array2::zeros((0, config.size)),
+            c_prev: Array2::zeros((0, config.size)),
             regularizer: CPURegularizer::from(
                 config.c.unwrap_or(0.0),
                 config.l1_ratio.unwrap_or(1.0),
@@ -96,11 +108,6 @@ impl LSTMCPULayer {
         self.output_size.as_array_view().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        self.inputs = Array3::zeros((batches, self.inputs.dim().1, self.inputs.dim().2));
-        self.output_size[0] = batches;
-    }
-
     pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
         self.inputs = inputs.into_dimensionality::<Ix3>().unwrap();
         let output_size = self.w_ih.shape()[2];
@@ -118,6 +125,7 @@ impl LSTMCPULayer {
             (self.inputs.shape()[0], 1, output_size)
         });
 
+        let weights = self.fused_weights();
         for t in 0..self.inputs.shape()[1] {
             let x_t = self
                 .inputs
@@ -126,22 +134,7 @@ impl LSTMCPULayer {
                 .into_dimensionality::<Ix2>()
                 .unwrap();
 
-            let i_t = (&x_t.dot(&self.w_ih.index_axis(Axis(0), 0))
-                + &h_t.dot(&self.w_hh.index_axis(Axis(0), 0))
-                + &self.biases.index_axis(Axis(0), 0))
-                .mapv(|x| (self.activation_h.activate)(&x));
-            let f_t = (&x_t.dot(&self.w_ih.index_axis(Axis(0), 1))
-                + &h_t.dot(&self.w_hh.index_axis(Axis(0), 1))
-                + &self.biases.index_axis(Axis(0), 1))
-                .mapv(|x| (self.activation_h.activate)(&x));
-            let o_t = (&x_t.dot(&self.w_ih.index_axis(Axis(0), 2))
-                + &h_t.dot(&self.w_hh.index_axis(Axis(0), 2))
-                + &self.biases.index_axis(Axis(0), 2))
-                .mapv(|x| (self.activation_h.activate)(&x));
-            let g_t = (&x_t.dot(&self.w_ih.index_axis(Axis(0), 3))
-                + &h_t.dot(&self.w_hh.index_axis(Axis(0), 3))
-                + &self.biases.index_axis(Axis(0), 3))
-                .mapv(|x| (self.activation_o.activate)(&x));
+            let (i_t, f_t, o_t, g_t) = self.gates(&x_t, &h_t, &weights);
 
             self.i_t.index_axis_mut(Axis(0), t).assign(&i_t);
             self.f_t.index_axis_mut(Axis(0), t).assign(&f_t);
@@ -164,22 +157,93 @@ impl LSTMCPULayer {
             h_t.into_dyn()
         }
     }
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        let inputs = inputs.into_dimensionality::<Ix3>().unwrap();
+        let (batches, sequence_length, _) = inputs.dim();
+        let output_size = self.w_ih.shape()[2];
+        let mut h_t = Array2::zeros((batches, output_size));
+        let mut c_t = Array2::zeros(h_t.raw_dim());
+        let mut outputs = Array3::zeros((batches, sequence_length, output_size));
+
+        let weights = self.fused_weights();
+        for t in 0..sequence_length {
+            let x_t = inputs.slice(s![.., t, ..]).to_owned();
+            let (i_t, f_t, o_t, g_t) = self.gates(&x_t, &h_t, &weights);
+            c_t = &(&c_t * &f_t) + &(&g_t * &i_t);
+            h_t = &c_t.mapv(|x| (self.activation_o.activate)(&x)) * &o_t;
+            outputs.slice_mut(s![.., t, ..]).assign(&h_t);
+        }
+
+        if self.return_sequences {
+            outputs.into_dyn()
+        } else {
+            h_t.into_dyn()
+        }
+    }
+
+    /// Returns the input and recurrent weights and the biases with the four
+    /// gates side by side, so one product computes every gate of a step.
+    fn fused_weights(&self) -> (Array2<f32>, Array2<f32>, Array1<f32>) {
+        (
+            fuse(&self.w_ih),
+            fuse(&self.w_hh),
+            self.biases.iter().copied().collect(),
+        )
+    }
+
+    /// Computes the input, forget, output and candidate gates for one step.
+    /// The activations run on rows spread across threads when the `parallel`
+    /// feature is enabled, which does not change the results.
+    fn gates(
+        &self,
+        x_t: &Array2<f32>,
+        h_t: &Array2<f32>,
+        (w_ih, w_hh, biases): &(Array2<f32>, Array2<f32>, Array1<f32>),
+    ) -> (Array2<f32>, Array2<f32>, Array2<f32>, Array2<f32>) {
+        let hidden_size = biases.len() / 4;
+        let mut gates = x_t.dot(w_ih) + h_t.dot(w_hh) + biases;
+        for_each!(Zip::from(gates.rows_mut()), |mut row| {
+            for (i, mut gate) in row.axis_chunks_iter_mut(Axis(0), hidden_size).enumerate() {
+                let activation = if i == 3 {
+                    &self.activation_o
+                } else {
+                    &self.activation_h
+                };
+                gate.mapv_inplace(|x| (activation.activate)(&x));
+            }
+        });
+        let gate = |i: usize| {
+            gates
+                .slice(s![.., i * hidden_size..(i + 1) * hidden_size])
+                .to_owned()
+        };
+        (gate(0), gate(1), gate(2), gate(3))
+    }
+
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         match d_outputs.shape().len() {
             2 => {
-                let d_inputs =
-                    self.backward_propagate_2d(d_outputs.into_dimensionality::<Ix2>().unwrap());
+                let d_inputs = self.backward_propagate_2d(
+                    d_outputs.into_dimensionality::<Ix2>().unwrap(),
+                    trainable,
+                );
                 d_inputs.into_dyn()
             }
             3 => {
-                let d_inputs =
-                    self.backward_propagate_3d(d_outputs.into_dimensionality::<Ix3>().unwrap());
+                let d_inputs = self.backward_propagate_3d(
+                    d_outputs.into_dimensionality::<Ix3>().unwrap(),
+                    trainable,
+                );
                 d_inputs.into_dyn()
             }
             _ => d_outputs,
         }
     }
-    pub fn backward_propagate_3d(&mut self, d_outputs: Array3<f32>) -> Array3<f32> {
+    pub fn backward_propagate_3d(
+        &mut self,
+        d_outputs: Array3<f32>,
+        trainable: bool,
+    ) -> Array3<f32> {
         let sequence_length = self.inputs.shape()[1];
         let batch_size = d_outputs.shape()[0];
         let input_size = self.w_ih.shape()[1];
@@ -197,33 +261,9 @@ impl LSTMCPULayer {
         let mut d_h_prev = Array2::<f32>::zeros((batch_size, hidden_size));
         let mut d_c_prev = Array2::<f32>::zeros((batch_size, hidden_size));
 
-        let w_ih = concatenate(
-            Axis(1),
-            &[
-                self.w_ih.index_axis(Axis(0), 0),
-                self.w_ih.index_axis(Axis(0), 1),
-                self.w_ih.index_axis(Axis(0), 2),
-                self.w_ih.index_axis(Axis(0), 3),
-            ],
-        )
-        .unwrap();
-        let w_hh = concatenate(
-            Axis(1),
-            &[
-                self.w_hh.index_axis(Axis(0), 0),
-                self.w_hh.index_axis(Axis(0), 1),
-                self.w_hh.index_axis(Axis(0), 2),
-                self.w_hh.index_axis(Axis(0), 3),
-            ],
-        )
-        .unwrap();
+        let w_ih = fuse(&self.w_ih);
+        let w_hh = fuse(&self.w_hh);
         for t in (0..sequence_length).rev() {
-            let x_t = self
-                .inputs
-                .slice(s![.., t, ..])
-                .into_dimensionality::<Ix2>()
-                .unwrap();
-
             let d_h = d_outputs
                 .slice(s![.., t, ..])
                 .clone()
@@ -250,51 +290,65 @@ impl LSTMCPULayer {
                 .slice_mut(s![.., t, ..])
                 .assign(&d_gates.dot(&w_ih.t()));
 
-            let d_gates_x = &d_gates.t().dot(&x_t);
-            for (i, x) in d_gates_x
-                .t()
-                .axis_chunks_iter(Axis(1), hidden_size)
-                .enumerate()
-            {
-                self.d_w_ih.index_axis_mut(Axis(0), i).add_assign(&x);
-            }
-
-            let d_gates_h = &d_gates.t().dot(&h_prev);
-
-            for (i, x) in d_gates_h
-                .t()
-                .axis_chunks_iter(Axis(1), hidden_size)
-                .enumerate()
-            {
-                self.d_w_hh.index_axis_mut(Axis(0), i).add_assign(&x);
-            }
-            self.d_biases += &d_gates
-                .sum_axis(Axis(0))
-                .to_shape((hidden_size, 4))
-                .unwrap()
-                .t();
-            self.l_w_ih = self
-                .regularizer
-                .coeff(&(self.w_ih.clone().into_dyn()))
-                .into_dimensionality::<Ix3>()
-                .unwrap();
-            self.l_w_hh = self
-                .regularizer
-                .coeff(&(self.w_hh.clone().into_dyn()))
-                .into_dimensionality::<Ix3>()
-                .unwrap();
-            self.l_biases = self
-                .regularizer
-                .coeff(&(self.biases.clone().into_dyn()))
-                .into_dimensionality::<Ix2>()
-                .unwrap();
             d_h_prev = d_gates.dot(&w_hh.t());
             d_c_prev = d_c_t * f_t;
+            if trainable {
+                self.accumulate_grads(&d_gates, t, &h_prev);
+            }
         }
 
         d_inputs
     }
-    fn backward_propagate_2d(&mut self, d_outputs: Array2<f32>) -> Array3<f32> {
+    /// Adds the parameter gradients of step `t` given the gradient of its
+    /// gates.
+    fn accumulate_grads(&mut self, d_gates: &Array2<f32>, t: usize, h_prev: &Array2<f32>) {
+        let hidden_size = self.w_ih.shape()[2];
+        let x_t = self
+            .inputs
+            .slice(s![.., t, ..])
+            .into_dimensionality::<Ix2>()
+            .unwrap();
+        let d_gates_x = &d_gates.t().dot(&x_t);
+        for (i, x) in d_gates_x
+            .t()
+            .axis_chunks_iter(Axis(1), hidden_size)
+            .enumerate()
+        {
+            self.d_w_ih.index_axis_mut(Axis(0), i).add_assign(&x);
+        }
+
+        let d_gates_h = &d_gates.t().dot(h_prev);
+
+        for (i, x) in d_gates_h
+            .t()
+            .axis_chunks_iter(Axis(1), hidden_size)
+            .enumerate()
+        {
+            self.d_w_hh.index_axis_mut(Axis(0), i).add_assign(&x);
+        }
+        self.d_biases += &d_gates
+            .sum_axis(Axis(0))
+            .to_shape((hidden_size, 4))
+            .unwrap()
+            .t();
+        self.l_w_ih = self
+            .regularizer
+            .coeff(&(self.w_ih.clone().into_dyn()))
+            .into_dimensionality::<Ix3>()
+            .unwrap();
+        self.l_w_hh = self
+            .regularizer
+            .coeff(&(self.w_hh.clone().into_dyn()))
+            .into_dimensionality::<Ix3>()
+            .unwrap();
+        self.l_biases = self
+            .regularizer
+            .coeff(&(self.biases.clone().into_dyn()))
+            .into_dimensionality::<Ix2>()
+            .unwrap();
+    }
+
+    fn backward_propagate_2d(&mut self, d_outputs: Array2<f32>, trainable: bool) -> Array3<f32> {
         let sequence_length = self.inputs.shape()[1];
         let batch_size = d_outputs.shape()[0];
         let input_size = self.w_ih.shape()[1];
@@ -312,34 +366,10 @@ impl LSTMCPULayer {
         let mut d_h_prev = d_outputs.clone();
         let mut d_c_prev = Array2::<f32>::zeros((batch_size, hidden_size));
 
-        let w_ih = concatenate(
-            Axis(1),
-            &[
-                self.w_ih.index_axis(Axis(0), 0),
-                self.w_ih.index_axis(Axis(0), 1),
-                self.w_ih.index_axis(Axis(0), 2),
-                self.w_ih.index_axis(Axis(0), 3),
-            ],
-        )
-        .unwrap();
-        let w_hh = concatenate(
-            Axis(1),
-            &[
-                self.w_hh.index_axis(Axis(0), 0),
-                self.w_hh.index_axis(Axis(0), 1),
-                self.w_hh.index_axis(Axis(0), 2),
-                self.w_hh.index_axis(Axis(0), 3),
-            ],
-        )
-        .unwrap();
+        let w_ih = fuse(&self.w_ih);
+        let w_hh = fuse(&self.w_hh);
 
         for t in (0..sequence_length).rev() {
-            let x_t = self
-                .inputs
-                .slice(s![.., t, ..])
-                .into_dimensionality::<Ix2>()
-                .unwrap();
-
             let i_t = self.i_t.index_axis(Axis(0), t);
             let f_t = self.f_t.index_axis(Axis(0), t);
             let o_t = self.o_t.index_axis(Axis(0), t);
@@ -359,52 +389,22 @@ impl LSTMCPULayer {
                 .slice_mut(s![.., t, ..])
                 .assign(&d_gates.dot(&w_ih.t()));
 
-            let d_gates_x = &d_gates.t().dot(&x_t);
-            for (i, x) in d_gates_x
-                .t()
-                .axis_chunks_iter(Axis(1), hidden_size)
-                .enumerate()
-            {
-                self.d_w_ih.index_axis_mut(Axis(0), i).add_assign(&x);
-            }
-
-            let d_gates_h = &d_gates.t().dot(&h_prev);
-
-            for (i, x) in d_gates_h
-                .t()
-                .axis_chunks_iter(Axis(1), hidden_size)
-                .enumerate()
-            {
-                self.d_w_hh.index_axis_mut(Axis(0), i).add_assign(&x);
-            }
-            self.d_biases += &d_gates
-                .sum_axis(Axis(0))
-                .to_shape((hidden_size, 4))
-                .unwrap()
-                .t();
-            self.l_w_ih = self
-                .regularizer
-                .coeff(&(self.w_ih.clone().into_dyn()))
-                .into_dimensionality::<Ix3>()
-                .unwrap();
-            self.l_w_hh = self
-                .regularizer
-                .coeff(&(self.w_hh.clone().into_dyn()))
-                .into_dimensionality::<Ix3>()
-                .unwrap();
-            self.l_biases = self
-                .regularizer
-                .coeff(&(self.biases.clone().into_dyn()))
-                .into_dimensionality::<Ix2>()
-                .unwrap();
             d_h_prev = d_gates.dot(&w_hh.t());
             d_c_prev = d_c_t * f_t;
+            if trainable {
+                self.accumulate_grads(&d_gates, t, &h_prev);
+            }
         }
 
         d_inputs
     }
 }
 
+/// Places the weights of the four gates side by side along the columns.
+fn fuse(weights: &Array3<f32>) -> Array2<f32> {
+    concatenate(Axis(1), &weights.outer_iter().collect::<Vec<_>>()).unwrap()
+}
+
 #[allow(dead_code)]
 fn clip_gradients(grad: &mut Array2<f32>, threshold: f32) -> () {
     let norm = grad.mapv(|x| x.powi(2)).sum().sqrt();
@@ -413,3 +413,71 @@ fn clip_gradients(grad: &mut Array2<f32>, threshold: f32) -> () {
         grad.map_inplace(|x| *x *= scale);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array;
+
+    use super::*;
+    use crate::seeded_rng;
+
+    fn layer() -> LSTMCPULayer {
+        let config = LSTMLayer {
+            size: 4,
+            init: None,
+            c: None,
+            l1_ratio: None,
+            return_sequences: Some(true),
+            recurrent_activation: None,
+            activation: None,
+        };
+        let size = IxDyn(&[2, 5, 3]);
+        let mut layer = LSTMCPULayer::new(config, size, None, &mut seeded_rng(Some(7)));
+        layer.biases = Array::range(0.0, 16.0, 1.0)
+            .mapv(|x: f32| (x * 1.3).sin() * 0.5)
+            .into_shape_with_order((4, 4))
+            .unwrap();
+        layer
+    }
+
+    fn inputs() -> Array3<f32> {
+        Array::range(0.0, 30.0, 1.0)
+            .mapv(|x: f32| (x * 0.7).sin())
+            .into_shape_with_order((2, 5, 3))
+            .unwrap()
+    }
+
+    /// Runs the layer one gate after the other on a single thread.
+    fn serial(layer: &LSTMCPULayer, inputs: &Array3<f32>) -> Array3<f32> {
+        let (batches, sequence_length, _) = inputs.dim();
+        let hidden_size = layer.w_ih.shape()[2];
+        let mut h_t = Array2::zeros((batches, hidden_size));
+        let mut c_t = Array2::zeros((batches, hidden_size));
+        let mut outputs = Array3::zeros((batches, sequence_length, hidden_size));
+        for t in 0..sequence_length {
+            let x_t = inputs.slice(s![.., t, ..]);
+            let gate = |i: usize, activation: &CPUActivation| {
+                (x_t.dot(&layer.w_ih.index_axis(Axis(0), i))
+                    + h_t.dot(&layer.w_hh.index_axis(Axis(0), i))
+                    + layer.biases.index_axis(Axis(0), i))
+                .mapv(|x| (activation.activate)(&x))
+            };
+            let i_t = gate(0, &layer.activation_h);
+            let f_t = gate(1, &layer.activation_h);
+            let o_t = gate(2, &layer.activation_h);
+            let g_t = gate(3, &layer.activation_o);
+            c_t = &c_t * &f_t + &g_t * &i_t;
+            h_t = c_t.mapv(|x| (layer.activation_o.activate)(&x)) * &o_t;
+            outputs.slice_mut(s![.., t, ..]).assign(&h_t);
+        }
+        outputs
+    }
+
+    #[test]
+    fn fused_gates_match_a_serial_run() {
+        let mut layer = layer();
+        let expected = serial(&layer, &inputs()).into_dyn();
+        assert_eq!(layer.infer(inputs().into_dyn()), expected);
+        assert_eq!(layer.forward_propagate(inputs().into_dyn()), expected);
+    }
+}
@@ -1,3 +1,15 @@
+/// Runs a `Zip` over independent slices of an array, spread across threads
+/// when the `parallel` feature is enabled. Each slice is computed the same way
+/// either way, so the results do not depend on the feature.
+macro_rules! for_each {
+    ($zip:expr, $f:expr) => {{
+        #[cfg(feature = "parallel")]
+        $zip.par_for_each($f);
+        #[cfg(not(feature = "parallel"))]
+        $zip.for_each($f);
+    }};
+}
+
 mod activation;
 mod batchnorm1d;
 mod batchnorm2d;
@@ -7,6 +19,10 @@ mod dense;
 mod dropout;
 mod flatten;
 mod embedding;
+mod gru;
+#[cfg(test)]
+mod gradcheck;
+mod im2col;
 mod pool2d;
 mod lstm;
 
@@ -19,10 +35,12 @@ pub use dense::*;
 pub use dropout::*;
 pub use flatten::*;
 pub use embedding::*;
+pub use gru::*;
 pub use pool2d::*;
 pub use lstm::*;
 
 use ndarray::ArrayD;
+use ndarray_rand::rand::rngs::StdRng;
 
 pub enum CPULayer {
     Activation(ActivationCPULayer),
@@ -34,6 +52,7 @@ pub enum CPULayer {
     Flatten(FlattenCPULayer),
     Embedding(EmbeddingCPULayer),
     LSTM(LSTMCPULayer),
+    GRU(GRUCPULayer),
     Pool2D(Pool2DCPULayer),
     Softmax(SoftmaxCPULayer),
     BatchNorm1D(BatchNorm1DCPULayer),
@@ -53,13 +72,46 @@ impl CPULayer {
             CPULayer::Dropout2D(layer) => layer.output_size(),
             CPULayer::Embedding(layer) => layer.output_size(),
             CPULayer::LSTM(layer) => layer.output_size(),
+            CPULayer::GRU(layer) => layer.output_size(),
             CPULayer::Flatten(layer) => layer.output_size(),
             CPULayer::Pool2D(layer) => layer.output_size(),
             CPULayer::Softmax(layer) => layer.output_size(),
         }
     }
 
-    pub fn forward_propagate(&mut self, inputs: ArrayD<f32>, training: bool) -> ArrayD<f32> {
+    /// Returns the number of trainable and non-trainable parameters.
+    pub fn params(&self) -> (usize, usize) {
+        match self {
+            CPULayer::BatchNorm1D(layer) => (
+                layer.gamma.len() + layer.beta.len(),
+                layer.running_mean.len() + layer.running_var.len(),
+            ),
+            CPULayer::BatchNorm2D(layer) => (
+                layer.gamma.len() + layer.beta.len(),
+                layer.running_mean.len() + layer.running_var.len(),
+            ),
+            CPULayer::Conv2D(layer) => (layer.weights.len() + layer.biases.len(), 0),
+            CPULayer::ConvTranspose2D(layer) => (layer.weights.len() + layer.biases.len(), 0),
+            CPULayer::Dense(layer) => (layer.weights.len() + layer.biases.len(), 0),
+            CPULayer::Embedding(layer) => (layer.embeddings.len(), 0),
+            CPULayer::LSTM(layer) => (
+                layer.w_ih.len() + layer.w_hh.len() + layer.biases.len(),
+                0,
+            ),
+            CPULayer::GRU(layer) => (
+                layer.w_ih.len() + layer.w_hh.len() + layer.biases.len(),
+                0,
+            ),
+            _ => (0, 0),
+        }
+    }
+
+    pub fn forward_propagate(
+        &mut self,
+        inputs: ArrayD<f32>,
+        training: bool,
+        rng: &mut StdRng,
+    ) -> ArrayD<f32> {
         match self {
             CPULayer::Activation(layer) => layer.forward_propagate(inputs),
             CPULayer::BatchNorm1D(layer) => layer.forward_propagate(inputs, training),
@@ -67,49 +119,56 @@ impl CPULayer {
             CPULayer::Conv2D(layer) => layer.forward_propagate(inputs),
             CPULayer::ConvTranspose2D(layer) => layer.forward_propagate(inputs),
             CPULayer::Dense(layer) => layer.forward_propagate(inputs),
-            CPULayer::Dropout1D(layer) => layer.forward_propagate(inputs, training),
-            CPULayer::Dropout2D(layer) => layer.forward_propagate(inputs, training),
+            CPULayer::Dropout1D(layer) => layer.forward_propagate(inputs, training, rng),
+            CPULayer::Dropout2D(layer) => layer.forward_propagate(inputs, training, rng),
             CPULayer::Embedding(layer) => layer.forward_propagate(inputs),
             CPULayer::LSTM(layer) => layer.forward_propagate(inputs),
+            CPULayer::GRU(layer) => layer.forward_propagate(inputs),
             CPULayer::Flatten(layer) => layer.forward_propagate(inputs),
             CPULayer::Pool2D(layer) => layer.forward_propagate(inputs),
             CPULayer::Softmax(layer) => layer.forward_propagate(inputs),
         }
     }
 
-    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
+    /// Runs the layer in inference mode without touching its caches, so a
+    /// shared model can serve several predictions at once.
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        match self {
+            CPULayer::Activation(layer) => layer.infer(inputs),
+            CPULayer::BatchNorm1D(layer) => layer.infer(inputs),
+            CPULayer::BatchNorm2D(layer) => layer.infer(inputs),
+            CPULayer::Conv2D(layer) => layer.infer(inputs),
+            CPULayer::ConvTranspose2D(layer) => layer.infer(inputs),
+            CPULayer::Dense(layer) => layer.infer(inputs),
+            CPULayer::Dropout1D(layer) => layer.infer(inputs),
+            CPULayer::Dropout2D(layer) => layer.infer(inputs),
+            CPULayer::Embedding(layer) => layer.infer(inputs),
+            CPULayer::LSTM(layer) => layer.infer(inputs),
+            CPULayer::GRU(layer) => layer.infer(inputs),
+            CPULayer::Flatten(layer) => layer.infer(inputs),
+            CPULayer::Pool2D(layer) => layer.infer(inputs),
+            CPULayer::Softmax(layer) => layer.infer(inputs),
+        }
+    }
+
+    /// Backpropagates `d_outputs`. Layers that are not `trainable` only
+    /// compute the gradient of their inputs.
+    pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>, trainable: bool) -> ArrayD<f32> {
         match self {
             CPULayer::Activation(layer) => layer.backward_propagate(d_outputs),
-            CPULayer::BatchNorm1D(layer) => layer.backward_propagate(d_outputs),
-            CPULayer::BatchNorm2D(layer) => layer.backward_propagate(d_outputs),
-            CPULayer::Conv2D(layer) => layer.backward_propagate(d_outputs),
-            CPULayer::ConvTranspose2D(layer) => layer.backward_propagate(d_outputs),
-            CPULayer::Dense(layer) => layer.backward_propagate(d_outputs),
+            CPULayer::BatchNorm1D(layer) => layer.backward_propagate(d_outputs, trainable),
+            CPULayer::BatchNorm2D(layer) => layer.backward_propagate(d_outputs, trainable),
+            CPULayer::Conv2D(layer) => layer.backward_propagate(d_outputs, trainable),
+            CPULayer::ConvTranspose2D(layer) => layer.backward_propagate(d_outputs, trainable),
+            CPULayer::Dense(layer) => layer.backward_propagate(d_outputs, trainable),
             CPULayer::Dropout1D(layer) => layer.backward_propagate(d_outputs),
             CPULayer::Dropout2D(layer) => layer.backward_propagate(d_outputs),
-            CPULayer::Embedding(layer) => layer.backward_propagate(d_outputs),
-            CPULayer::LSTM(layer) => layer.backward_propagate(d_outputs),
+            CPULayer::Embedding(layer) => layer.backward_propagate(d_outputs, trainable),
+            CPULayer::LSTM(layer) => layer.backward_propagate(d_outputs, trainable),
+            CPULayer::GRU(layer) => layer.backward_propagate(d_outputs, trainable),
             CPULayer::Flatten(layer) => layer.backward_propagate(d_outputs),
             CPULayer::Pool2D(layer) => layer.backward_propagate(d_outputs),
             CPULayer::Softmax(layer) => layer.backward_propagate(d_outputs),
         }
     }
-
-    pub fn reset(&mut self, batches: usize) {
-        match self {
-            CPULayer::Activation(layer) => layer.reset(batches),
-            CPULayer::BatchNorm1D(layer) => layer.reset(batches),
-            CPULayer::BatchNorm2D(layer) => layer.reset(batches),
-            CPULayer::Conv2D(layer) => layer.reset(batches),
-            CPULayer::Dense(layer) => layer.reset(batches),
-            CPULayer::Dropout1D(layer) => layer.reset(batches),
-            CPULayer::Dropout2D(layer) => layer.reset(batches),
-            CPULayer::Embedding(layer) => layer.reset(batches),
-            CPULayer::LSTM(layer) => layer.reset(batches),
-            CPULayer::Flatten(layer) => layer.reset(batches),
-            CPULayer::Pool2D(layer) => layer.reset(batches),
-            CPULayer::Softmax(layer) => layer.reset(batches),
-            CPULayer::ConvTranspose2D(layer) => layer.reset(batches),
-        }
-    }
 }
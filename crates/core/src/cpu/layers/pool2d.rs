@@ -1,4 +1,4 @@
-use ndarray::{s, Array4, Array5, ArrayD, Ix4, Ix5, IxDyn};
+use ndarray::{s, Array4, Array5, ArrayD, Ix4, Ix5, IxDyn, Zip};
 
 use crate::Pool2DLayer;
 
@@ -13,10 +13,10 @@ pub struct Pool2DCPULayer {
 impl Pool2DCPULayer {
     pub fn new(config: Pool2DLayer, size: IxDyn) -> Self {
         let strides = config.strides.unwrap_or(vec![1, 1]);
-        let input_size = Ix4(size[0], size[1], size[2], size[3]);
+        let input_size = Ix4(0, size[1], size[2], size[3]);
         let output_y = size[2] / strides[0];
         let output_x = size[3] / strides[1];
-        let indice_size = Ix5(size[0], size[1], output_y, output_x, 2);
+        let indice_size = Ix5(0, size[1], output_y, output_x, 2);
         let output_size = Ix4(size[0], size[1], output_y, output_x);
         let max = config.mode == 1;
         Self {
@@ -32,22 +32,29 @@ impl Pool2DCPULayer {
         self.outputs.shape().to_vec()
     }
 
-    pub fn reset(&mut self, batches: usize) {
-        let input_size = self.inputs.shape();
-        self.inputs = Array4::zeros((batches, input_size[1], input_size[2], input_size[3]));
-        let indice_size = self.outputs.shape();
-        self.indices = Array5::zeros((batches, indice_size[1], indice_size[2], indice_size[3], 2));
-        let output_size = self.outputs.shape();
-        self.outputs = Array4::zeros((batches, output_size[1], output_size[2], output_size[3]));
-    }
-
     pub fn forward_propagate(&mut self, inputs: ArrayD<f32>) -> ArrayD<f32> {
         self.inputs = inputs.into_dimensionality::<Ix4>().unwrap();
+        (self.outputs, self.indices) = self.pool(&self.inputs);
+        self.outputs.clone().into_dyn()
+    }
+
+    pub fn infer(&self, inputs: ArrayD<f32>) -> ArrayD<f32> {
+        let inputs = inputs.into_dimensionality::<Ix4>().unwrap();
+        self.pool(&inputs).0.into_dyn()
+    }
 
-        let (batches, channels, output_y, output_x) = self.outputs.dim();
+    /// Returns the pooled outputs along with the position of every maximum
+    /// inside its window.
+    fn pool(&self, inputs: &Array4<f32>) -> (Array4<f32>, Array5<usize>) {
+        let (_, channels, output_y, output_x) = self.outputs.dim();
+        let batches = inputs.dim().0;
 
-        for b in 0..batches {
-            for c in 0..channels {
+        let mut outputs = Array4::zeros((batches, channels, output_y, output_x));
+        let mut indices = Array5::zeros((batches, channels, output_y, output_x, 2));
+        let zip = Zip::indexed(outputs.outer_iter_mut()).and(indices.outer_iter_mut());
+        for_each!(zip, |b, mut outputs, mut indices| {
+            let zip = Zip::indexed(outputs.outer_iter_mut()).and(indices.outer_iter_mut());
+            for_each!(zip, |c, mut outputs, mut indices| {
                 for y in 0..output_y {
                     for x in 0..output_x {
                         let input_y = y * self.strides[0];
@@ -56,8 +63,8 @@ impl Pool2DCPULayer {
                         let stride_x = (x + 1) * self.strides[1];
                         if self.max {
                             let mut max_index = (0, 0);
-                            let mut max_value = 0.0;
-                            self.inputs
+                            let mut max_value = f32::NEG_INFINITY;
+                            inputs
                                 .slice(s![b, c, input_y..stride_y, input_x..stride_x])
                                 .indexed_iter()
                                 .for_each(|(index, value)| {
@@ -66,57 +73,136 @@ impl Pool2DCPULayer {
                                         max_index = index;
                                     }
                                 });
-                            let mut position = self.indices.slice_mut(s![b, c, y, x, ..]);
+                            let mut position = indices.slice_mut(s![y, x, ..]);
                             position[0] = max_index.0;
                             position[1] = max_index.1;
-                            self.outputs[[b, c, y, x]] = max_value;
+                            outputs[[y, x]] = max_value;
                         } else {
-                            self.outputs[[b, c, y, x]] = self
-                                .inputs
+                            outputs[[y, x]] = inputs
                                 .slice(s![b, c, input_y..stride_y, input_x..stride_x])
                                 .mean()
                                 .unwrap();
                         }
                     }
                 }
-            }
-        }
-
-        self.outputs.clone().into_dyn()
+            });
+        });
+        (outputs, indices)
     }
 
     pub fn backward_propagate(&mut self, d_outputs: ArrayD<f32>) -> ArrayD<f32> {
         let d_outputs = d_outputs.into_dimensionality::<Ix4>().unwrap();
 
-        let (batches, channels, output_y, output_x) = self.outputs.dim();
+        let (_, _, output_y, output_x) = self.outputs.dim();
 
         let mut d_inputs = Array4::zeros(self.inputs.dim());
-        for b in 0..batches {
-            for c in 0..channels {
-                for y in 0..output_y {
-                    for x in 0..output_x {
-                        let input_y = y * self.strides[0];
-                        let input_x = x * self.strides[1];
-                        let stride_y = (y + 1) * self.strides[0];
-                        let stride_x = (x + 1) * self.strides[1];
-                        if self.max {
-                            let index = self.indices.slice(s![b, c, y, x, ..]);
-                            d_inputs[[b, c, input_y + index[0], input_x + index[1]]] =
-                                d_outputs[[b, c, y, x]];
-                        } else {
-                            d_inputs
-                                .slice_mut(s![b, c, input_y..stride_y, input_x..stride_x])
-                                .fill(
-                                    d_outputs[[b, c, y, x]]
-                                        / self.strides[0] as f32
-                                        / self.strides[1] as f32,
-                                );
+        for_each!(
+            Zip::indexed(d_inputs.outer_iter_mut()),
+            |b, mut d_inputs| {
+                for_each!(
+                    Zip::indexed(d_inputs.outer_iter_mut()),
+                    |c, mut d_inputs| {
+                        for y in 0..output_y {
+                            for x in 0..output_x {
+                                let input_y = y * self.strides[0];
+                                let input_x = x * self.strides[1];
+                                let stride_y = (y + 1) * self.strides[0];
+                                let stride_x = (x + 1) * self.strides[1];
+                                if self.max {
+                                    let index = self.indices.slice(s![b, c, y, x, ..]);
+                                    d_inputs[[input_y + index[0], input_x + index[1]]] =
+                                        d_outputs[[b, c, y, x]];
+                                } else {
+                                    d_inputs
+                                        .slice_mut(s![input_y..stride_y, input_x..stride_x])
+                                        .fill(
+                                            d_outputs[[b, c, y, x]]
+                                                / self.strides[0] as f32
+                                                / self.strides[1] as f32,
+                                        );
+                                }
+                            }
                         }
                     }
-                }
+                );
             }
-        }
+        );
 
         d_inputs.into_dyn()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array, ArrayD};
+
+    use super::*;
+
+    fn layer(mode: usize) -> Pool2DCPULayer {
+        let config = Pool2DLayer {
+            mode,
+            strides: Some(vec![2, 2]),
+        };
+        Pool2DCPULayer::new(config, IxDyn(&[2, 2, 4, 4]))
+    }
+
+    fn inputs() -> ArrayD<f32> {
+        Array::range(0.0, 64.0, 1.0)
+            .into_shape_with_order(vec![2, 2, 4, 4])
+            .unwrap()
+    }
+
+    #[test]
+    fn max_pooling_routes_gradients_to_the_maxima() {
+        let mut pool = layer(1);
+        let outputs = pool.forward_propagate(inputs());
+        assert_eq!(outputs.shape(), [2, 2, 2, 2]);
+        let first: Vec<f32> = outputs.iter().take(4).copied().collect();
+        assert_eq!(first, [5.0, 7.0, 13.0, 15.0]);
+        assert_eq!(outputs[[1, 1, 1, 1]], 63.0);
+
+        let d_inputs = pool.backward_propagate(ArrayD::ones(vec![2, 2, 2, 2]));
+        // The maximum of every window sits in its bottom right corner.
+        for ((_, _, y, x), d_input) in d_inputs
+            .into_dimensionality::<Ix4>()
+            .unwrap()
+            .indexed_iter()
+        {
+            let expected = if y % 2 == 1 && x % 2 == 1 { 1.0 } else { 0.0 };
+            assert_eq!(*d_input, expected);
+        }
+    }
+
+    #[test]
+    fn max_pooling_keeps_negative_maxima() {
+        let mut pool = layer(1);
+        let inputs = inputs().mapv(|x| -x - 1.0);
+        let outputs = pool.forward_propagate(inputs.clone());
+        let first: Vec<f32> = outputs.iter().take(4).copied().collect();
+        assert_eq!(first, [-1.0, -3.0, -9.0, -11.0]);
+        assert_eq!(pool.infer(inputs), outputs);
+
+        let d_inputs = pool.backward_propagate(ArrayD::ones(vec![2, 2, 2, 2]));
+        // The maximum of every window now sits in its top left corner.
+        for ((_, _, y, x), d_input) in d_inputs
+            .into_dimensionality::<Ix4>()
+            .unwrap()
+            .indexed_iter()
+        {
+            let expected = if y % 2 == 0 && x % 2 == 0 { 1.0 } else { 0.0 };
+            assert_eq!(*d_input, expected);
+        }
+    }
+
+    #[test]
+    fn average_pooling_spreads_gradients_over_the_window() {
+        let mut pool = layer(0);
+        let outputs = pool.forward_propagate(inputs());
+        let first: Vec<f32> = outputs.iter().take(4).copied().collect();
+        assert_eq!(first, [2.5, 4.5, 10.5, 12.5]);
+        assert_eq!(pool.infer(inputs()), outputs);
+
+        let d_inputs = pool.backward_propagate(ArrayD::ones(vec![2, 2, 2, 2]));
+        assert_eq!(d_inputs, ArrayD::from_elem(vec![2, 2, 4, 4], 0.25));
+    }
+}
@@ -0,0 +1,275 @@
+use ndarray::{ArrayView2, ArrayViewD, Axis};
+
+use crate::{Metric, MetricsReport};
+
+/// Computes the requested metrics for the predictions `y_hat` against `y`.
+pub fn evaluate_metrics(
+    metrics: &[Metric],
+    y_hat: ArrayViewD<f32>,
+    y: ArrayViewD<f32>,
+    loss: f32,
+) -> MetricsReport {
+    let samples = y_hat.shape()[0];
+    let (outputs, targets) = (y_hat.len() / samples, y.len() / samples);
+    let y_hat = y_hat.into_shape_with_order((samples, outputs)).unwrap();
+    let y = y.into_shape_with_order((samples, targets)).unwrap();
+
+    let mut report = MetricsReport {
+        loss,
+        ..Default::default()
+    };
+    for metric in metrics {
+        match metric {
+            Metric::Accuracy => report.accuracy = Some(accuracy(y_hat, y)),
+            Metric::TopKAccuracy(config) => {
+                report.top_k_accuracy = Some(top_k_accuracy(y_hat, y, config.k))
+            }
+            Metric::Precision => report.precision = Some(per_class(y_hat, y).0),
+            Metric::Recall => report.recall = Some(per_class(y_hat, y).1),
+            Metric::F1 => report.f1 = Some(per_class(y_hat, y).2),
+            Metric::ConfusionMatrix => report.confusion_matrix = Some(confusion_matrix(y_hat, y)),
+            Metric::MAE => report.mae = Some(mae(y_hat, y)),
+            Metric::RMSE => report.rmse = Some(rmse(y_hat, y)),
+            Metric::R2 => report.r2 = Some(r2(y_hat, y)),
+            Metric::RocAuc => report.roc_auc = Some(roc_auc(y_hat, y)),
+        }
+    }
+    report
+}
+
+fn argmax(row: ArrayViewD<f32>) -> usize {
+    row.iter()
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |(i, max), (j, x)| {
+            if *x > max {
+                (j, *x)
+            } else {
+                (i, max)
+            }
+        })
+        .0
+}
+
+/// Number of classes, a single output column is treated as a binary classifier.
+fn classes(y_hat: ArrayView2<f32>) -> usize {
+    y_hat.shape()[1].max(2)
+}
+
+/// Converts outputs into class labels, either by thresholding a single
+/// column, reading sparse labels or taking the argmax of one-hot rows.
+fn labels(x: ArrayView2<f32>, classes: usize) -> Vec<usize> {
+    x.axis_iter(Axis(0))
+        .map(|row| match row.len() {
+            1 if classes == 2 => (row[0] >= 0.5) as usize,
+            1 => row[0] as usize,
+            _ => argmax(row.into_dyn()),
+        })
+        .collect()
+}
+
+fn accuracy(y_hat: ArrayView2<f32>, y: ArrayView2<f32>) -> f32 {
+    let classes = classes(y_hat);
+    let predicted = labels(y_hat, classes);
+    let actual = labels(y, classes);
+    let correct = predicted
+        .iter()
+        .zip(&actual)
+        .filter(|(a, b)| a == b)
+        .count();
+    correct as f32 / predicted.len() as f32
+}
+
+fn top_k_accuracy(y_hat: ArrayView2<f32>, y: ArrayView2<f32>, k: usize) -> f32 {
+    if y_hat.shape()[1] == 1 {
+        return accuracy(y_hat, y);
+    }
+    let actual = labels(y, classes(y_hat));
+    let correct = y_hat
+        .axis_iter(Axis(0))
+        .zip(actual)
+        .filter(|(row, label)| match row.get(*label) {
+            Some(score) => row.iter().filter(|x| *x > score).count() < k,
+            None => false,
+        })
+        .count();
+    correct as f32 / y_hat.shape()[0] as f32
+}
+
+fn confusion_matrix(y_hat: ArrayView2<f32>, y: ArrayView2<f32>) -> Vec<Vec<usize>> {
+    let classes = classes(y_hat);
+    let mut matrix = vec![vec![0; classes]; classes];
+    for (predicted, actual) in labels(y_hat, classes).into_iter().zip(labels(y, classes)) {
+        if predicted < classes && actual < classes {
+            matrix[actual][predicted] += 1;
+        }
+    }
+    matrix
+}
+
+/// Returns the per-class precision, recall and F1 score.
+fn per_class(y_hat: ArrayView2<f32>, y: ArrayView2<f32>) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let matrix = confusion_matrix(y_hat, y);
+    let classes = matrix.len();
+    let mut precision = Vec::with_capacity(classes);
+    let mut recall = Vec::with_capacity(classes);
+    let mut f1 = Vec::with_capacity(classes);
+    for (c, row) in matrix.iter().enumerate() {
+        let tp = row[c] as f32;
+        let predicted = matrix.iter().map(|r| r[c]).sum::<usize>() as f32;
+        let actual = row.iter().sum::<usize>() as f32;
+        let p = if predicted > 0.0 { tp / predicted } else { 0.0 };
+        let r = if actual > 0.0 { tp / actual } else { 0.0 };
+        precision.push(p);
+        recall.push(r);
+        f1.push(if p + r > 0.0 {
+            2.0 * p * r / (p + r)
+        } else {
+            0.0
+        });
+    }
+    (precision, recall, f1)
+}
+
+fn mae(y_hat: ArrayView2<f32>, y: ArrayView2<f32>) -> f32 {
+    (&y_hat - &y).mapv(f32::abs).mean().unwrap_or(0.0)
+}
+
+fn rmse(y_hat: ArrayView2<f32>, y: ArrayView2<f32>) -> f32 {
+    (&y_hat - &y).mapv(|x| x * x).mean().unwrap_or(0.0).sqrt()
+}
+
+/// Coefficient of determination averaged uniformly over the output columns.
+fn r2(y_hat: ArrayView2<f32>, y: ArrayView2<f32>) -> f32 {
+    let mean = y.mean_axis(Axis(0)).unwrap();
+    let ss_res = (&y - &y_hat).mapv(|x| x * x).sum_axis(Axis(0));
+    let ss_tot = (&y - &mean).mapv(|x| x * x).sum_axis(Axis(0));
+    let scores =
+        ss_res
+            .iter()
+            .zip(ss_tot.iter())
+            .map(|(res, tot)| if *tot > 0.0 { 1.0 - res / tot } else { 0.0 });
+    scores.sum::<f32>() / ss_res.len() as f32
+}
+
+/// Area under the ROC curve of a binary classifier, computed from the
+/// Mann-Whitney U statistic with averaged ranks for ties.
+fn roc_auc(y_hat: ArrayView2<f32>, y: ArrayView2<f32>) -> f32 {
+    let column = y_hat.shape()[1] - 1;
+    let scores = y_hat.index_axis(Axis(1), column);
+    let actual = labels(y, 2);
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|a, b| scores[*a].total_cmp(&scores[*b]));
+
+    let mut ranks = vec![0.0; order.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && scores[order[j + 1]] == scores[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f32 / 2.0 + 1.0;
+        for k in i..=j {
+            ranks[order[k]] = rank;
+        }
+        i = j + 1;
+    }
+
+    let positives = actual.iter().filter(|x| **x == 1).count() as f32;
+    let negatives = actual.len() as f32 - positives;
+    if positives == 0.0 || negatives == 0.0 {
+        return f32::NAN;
+    }
+    let rank_sum: f32 = ranks
+        .iter()
+        .zip(&actual)
+        .filter(|(_, x)| **x == 1)
+        .map(|(rank, _)| rank)
+        .sum();
+    (rank_sum - positives * (positives + 1.0) / 2.0) / (positives * negatives)
+}
+
+impl MetricsReport {
+    /// Formats the scalar metrics for the training log, per-class scores are
+    /// macro averaged.
+    pub fn log(&self) -> String {
+        let mean = |x: &Vec<f32>| x.iter().sum::<f32>() / x.len() as f32;
+        let mut parts = Vec::new();
+        if let Some(x) = self.accuracy {
+            parts.push(format!("Accuracy={}", x));
+        }
+        if let Some(x) = self.top_k_accuracy {
+            parts.push(format!("TopKAccuracy={}", x));
+        }
+        if let Some(x) = &self.precision {
+            parts.push(format!("Precision={}", mean(x)));
+        }
+        if let Some(x) = &self.recall {
+            parts.push(format!("Recall={}", mean(x)));
+        }
+        if let Some(x) = &self.f1 {
+            parts.push(format!("F1={}", mean(x)));
+        }
+        if let Some(x) = self.mae {
+            parts.push(format!("MAE={}", x));
+        }
+        if let Some(x) = self.rmse {
+            parts.push(format!("RMSE={}", x));
+        }
+        if let Some(x) = self.r2 {
+            parts.push(format!("R2={}", x));
+        }
+        if let Some(x) = self.roc_auc {
+            parts.push(format!("ROC-AUC={}", x));
+        }
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr2, Array2};
+
+    use super::*;
+
+    fn report(metrics: &[Metric], y_hat: Array2<f32>, y: Array2<f32>) -> MetricsReport {
+        evaluate_metrics(metrics, y_hat.into_dyn().view(), y.into_dyn().view(), 0.0)
+    }
+
+    #[test]
+    fn classification_metrics_follow_the_confusion_matrix() {
+        let y_hat = arr2(&[[0.9, 0.1], [0.2, 0.8], [0.6, 0.4], [0.3, 0.7]]);
+        let y = arr2(&[[1.0, 0.0], [0.0, 1.0], [0.0, 1.0], [0.0, 1.0]]);
+        let metrics = [
+            Metric::Accuracy,
+            Metric::ConfusionMatrix,
+            Metric::Precision,
+            Metric::Recall,
+            Metric::F1,
+        ];
+        let report = report(&metrics, y_hat, y);
+        assert_eq!(report.accuracy, Some(0.75));
+        assert_eq!(report.confusion_matrix, Some(vec![vec![1, 0], vec![1, 2]]));
+        assert_eq!(report.precision, Some(vec![0.5, 1.0]));
+        assert_eq!(report.recall, Some(vec![1.0, 2.0 / 3.0]));
+        assert_eq!(report.f1, Some(vec![2.0 / 3.0, 0.8]));
+    }
+
+    #[test]
+    fn a_single_column_is_a_binary_classifier() {
+        let y_hat = arr2(&[[0.9], [0.2], [0.6], [0.4]]);
+        let y = arr2(&[[1.0], [0.0], [0.0], [1.0]]);
+        let report = report(&[Metric::Accuracy, Metric::RocAuc], y_hat, y);
+        assert_eq!(report.accuracy, Some(0.5));
+        assert_eq!(report.roc_auc, Some(0.75));
+    }
+
+    #[test]
+    fn regression_metrics() {
+        let y_hat = arr2(&[[1.0], [2.0], [4.0]]);
+        let y = arr2(&[[1.0], [3.0], [5.0]]);
+        let report = report(&[Metric::MAE, Metric::RMSE, Metric::R2], y_hat, y);
+        assert!((report.mae.unwrap() - 2.0 / 3.0).abs() < 1e-6);
+        assert!((report.rmse.unwrap() - (2.0f32 / 3.0).sqrt()).abs() < 1e-6);
+        assert!((report.r2.unwrap() - (1.0 - 2.0 / 8.0)).abs() < 1e-6);
+    }
+}
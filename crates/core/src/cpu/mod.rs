@@ -4,10 +4,13 @@ mod cost;
 mod init;
 mod layers;
 mod layer_norm;
+mod metrics;
 mod optimizers;
 mod postprocessing;
+mod pretrained;
 mod regularizer;
 mod schedulers;
+mod surgery;
 
 pub use activation::*;
 pub use backend::*;
@@ -15,6 +18,7 @@ pub use cost::*;
 pub use init::*;
 pub use layers::*;
 pub use layer_norm::*;
+pub use metrics::*;
 pub use optimizers::*;
 pub use postprocessing::*;
 pub use regularizer::*;
@@ -0,0 +1,78 @@
+use std::ops::{Add, Div, Mul, Sub, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{AdaBeliefOptimizer, UpdateContext};
+
+pub struct CPUAdaBeliefOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+    pub m: Vec<Vec<ArrayD<f32>>>,
+    pub s: Vec<Vec<ArrayD<f32>>>,
+    pub t: f32,
+}
+
+impl CPUAdaBeliefOptimizer {
+    pub fn new(config: AdaBeliefOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut m = Vec::new();
+        let mut s = Vec::new();
+        for params in params {
+            m.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+            s.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            beta1: config.beta1,
+            beta2: config.beta2,
+            epsilon: config.epsilon,
+            m,
+            s,
+            t: 0.0,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        ctx: &UpdateContext,
+        l: Vec<ArrayViewD<f32>>,
+    ) {
+        for (j, ((param, grad), li)) in params.iter_mut().zip(grads).zip(l).enumerate() {
+            self.m[idx][j] = self
+                .beta1
+                .mul(&self.m[idx][j])
+                .add((1.0 - self.beta1).mul(&grad));
+            // the second moment tracks the deviation of the gradient from its
+            // running mean instead of the raw squared gradient
+            let belief = (&grad - &self.m[idx][j]).map(|x| x.powi(2));
+            self.s[idx][j] = self
+                .beta2
+                .mul(&self.s[idx][j])
+                .add((1.0 - self.beta2).mul(&belief))
+                .add(self.epsilon);
+
+            let m_hat = self.m[idx][j].view().div(1.0 - self.beta1.powf(self.t));
+            let s_hat = self.s[idx][j].view().div(1.0 - self.beta2.powf(self.t));
+            let rate = ctx.eta();
+
+            param.sub_assign(
+                &rate
+                    .mul(m_hat)
+                    .div(s_hat.map(|x| x.sqrt()).add(self.epsilon))
+                    .sub(&li),
+            );
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::ops::{Add, Div, Mul, Sub, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{AdagradOptimizer, UpdateContext};
+
+pub struct CPUAdagradOptimizer {
+    pub epsilon: f32,
+    pub acc_sg: Vec<Vec<ArrayD<f32>>>,
+}
+
+impl CPUAdagradOptimizer {
+    pub fn new(config: AdagradOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut acc_sg = Vec::new();
+        for params in params {
+            acc_sg.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            acc_sg,
+            epsilon: config.epsilon,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        ctx: &UpdateContext,
+        l: Vec<ArrayViewD<f32>>,
+    ) {
+        for (j, ((param, grad), li)) in params.iter_mut().zip(grads).zip(l).enumerate() {
+            self.acc_sg[idx][j] = self.acc_sg[idx][j].view().add(&grad.map(|x| x.powi(2)));
+
+            let rate = ctx.eta();
+
+            param.sub_assign(
+                &rate
+                    .mul(&grad)
+                    .div(self.acc_sg[idx][j].map(|x| x.sqrt()).add(self.epsilon))
+                    .sub(&li),
+            )
+        }
+    }
+}
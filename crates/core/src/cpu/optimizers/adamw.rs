@@ -0,0 +1,80 @@
+use std::ops::{Add, Div, Mul, Sub, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{AdamWOptimizer, UpdateContext};
+
+pub struct CPUAdamWOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+    pub weight_decay: f32,
+    pub m: Vec<Vec<ArrayD<f32>>>,
+    pub v: Vec<Vec<ArrayD<f32>>>,
+    pub t: f32,
+}
+
+impl CPUAdamWOptimizer {
+    pub fn new(config: AdamWOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut m = Vec::new();
+        let mut v = Vec::new();
+        for params in params {
+            m.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+            v.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            beta1: config.beta1,
+            beta2: config.beta2,
+            epsilon: config.epsilon,
+            weight_decay: config.weight_decay,
+            m,
+            v,
+            t: 0.0,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        ctx: &UpdateContext,
+        l: Vec<ArrayViewD<f32>>,
+    ) {
+        for (j, ((param, grad), li)) in params.iter_mut().zip(grads).zip(l).enumerate() {
+            self.m[idx][j] = self
+                .beta1
+                .mul(&self.m[idx][j])
+                .add((1.0 - self.beta1).mul(&grad));
+            self.v[idx][j] = self
+                .beta2
+                .mul(&self.v[idx][j])
+                .add((1.0 - self.beta2).mul(&grad.map(|x| x.powi(2))));
+
+            let m_hat = self.m[idx][j].view().div(1.0 - self.beta1.powf(self.t));
+            let v_hat = self.v[idx][j].view().div(1.0 - self.beta2.powf(self.t));
+            let rate = ctx.eta();
+
+            // the decay is applied to the weights directly instead of being
+            // folded into the gradient
+            let decay = param.mul(rate * self.weight_decay);
+            param.sub_assign(
+                &rate
+                    .mul(m_hat)
+                    .div(v_hat.map(|x| x.sqrt()).add(self.epsilon))
+                    .add(&decay)
+                    .sub(&li),
+            );
+        }
+    }
+}
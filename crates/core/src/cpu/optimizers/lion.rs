@@ -0,0 +1,58 @@
+use std::ops::{Add, Mul, Sub, SubAssign};
+
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+
+use crate::{LionOptimizer, UpdateContext};
+
+pub struct CPULionOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub weight_decay: f32,
+    pub m: Vec<Vec<ArrayD<f32>>>,
+}
+
+impl CPULionOptimizer {
+    pub fn new(config: LionOptimizer, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let mut m = Vec::new();
+        for params in params {
+            m.push(
+                params
+                    .iter()
+                    .map(|param| ArrayD::zeros(param.dim()))
+                    .collect(),
+            );
+        }
+        Self {
+            beta1: config.beta1,
+            beta2: config.beta2,
+            weight_decay: config.weight_decay.unwrap_or(0.0),
+            m,
+        }
+    }
+
+    pub fn update_grads(
+        &mut self,
+        mut params: Vec<ArrayViewMutD<f32>>,
+        grads: Vec<ArrayViewD<f32>>,
+        idx: usize,
+        ctx: &UpdateContext,
+        l: Vec<ArrayViewD<f32>>,
+    ) {
+        for (j, ((param, grad), li)) in params.iter_mut().zip(grads).zip(l).enumerate() {
+            let update = self
+                .beta1
+                .mul(&self.m[idx][j])
+                .add((1.0 - self.beta1).mul(&grad))
+                .map(|x| if *x == 0.0 { 0.0 } else { x.signum() });
+            self.m[idx][j] = self
+                .beta2
+                .mul(&self.m[idx][j])
+                .add((1.0 - self.beta2).mul(&grad));
+
+            let rate = ctx.eta();
+
+            let decay = param.mul(self.weight_decay);
+            param.sub_assign(&rate.mul(update.add(&decay)).sub(&li));
+        }
+    }
+}
@@ -1,21 +1,53 @@
+mod adabelief;
+mod adadelta;
+mod adagrad;
 mod adam;
+mod adamw;
+mod lion;
 mod nadam;
 mod sgd;
 mod rmsprop;
 
-use ndarray::{ArrayViewD, ArrayViewMutD};
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
+pub use adabelief::*;
+pub use adadelta::*;
+pub use adagrad::*;
 pub use adam::*;
+pub use adamw::*;
+pub use lion::*;
 pub use nadam::*;
 pub use rmsprop::*;
 pub use sgd::*;
 
-use crate::{CPULayer, CPUScheduler, Optimizer};
+use crate::{CPULayer, CPUScheduler, GradientClipping, Optimizer};
+
+/// Learning rate schedule and training progress shared by the parameter
+/// updates of one optimizer step.
+pub struct UpdateContext<'a> {
+    pub scheduler: &'a CPUScheduler,
+    pub rate: f32,
+    /// Progress the scheduler is evaluated at. Every optimizer passes the
+    /// epoch, so schedules are counted in epochs regardless of batching.
+    pub step: usize,
+}
+
+impl UpdateContext<'_> {
+    /// Returns the scheduled learning rate for the current step.
+    pub fn eta(&self) -> f32 {
+        self.scheduler.eta(self.rate, self.step)
+    }
+}
 
 pub enum CPUOptimizer {
     SGD(CPUSGDOptimizer),
     Adam(CPUAdamOptimizer),
     Nadam(CPUNadamOptimizer),
     RMSProp(CPURMSPropOptimizer),
+    AdamW(CPUAdamWOptimizer),
+    Adagrad(CPUAdagradOptimizer),
+    Adadelta(CPUAdadeltaOptimizer),
+    AdaBelief(CPUAdaBeliefOptimizer),
+    Lion(CPULionOptimizer),
 }
 
 impl CPUOptimizer {
@@ -27,7 +59,7 @@ impl CPUOptimizer {
             }
         }
         match optimizer {
-            Optimizer::SGD => CPUOptimizer::SGD(CPUSGDOptimizer::new()),
+            Optimizer::SGD(config) => CPUOptimizer::SGD(CPUSGDOptimizer::new(config, all_params)),
             Optimizer::Adam(config) => {
                 CPUOptimizer::Adam(CPUAdamOptimizer::new(config, all_params))
             },
@@ -37,41 +69,164 @@ impl CPUOptimizer {
             Optimizer::RMSProp(config) => {
                 CPUOptimizer::RMSProp(CPURMSPropOptimizer::new(config, all_params))
             }
+            Optimizer::AdamW(config) => {
+                CPUOptimizer::AdamW(CPUAdamWOptimizer::new(config, all_params))
+            }
+            Optimizer::Adagrad(config) => {
+                CPUOptimizer::Adagrad(CPUAdagradOptimizer::new(config, all_params))
+            }
+            Optimizer::Adadelta(config) => {
+                CPUOptimizer::Adadelta(CPUAdadeltaOptimizer::new(config, all_params))
+            }
+            Optimizer::AdaBelief(config) => {
+                CPUOptimizer::AdaBelief(CPUAdaBeliefOptimizer::new(config, all_params))
+            }
+            Optimizer::Lion(config) => {
+                CPUOptimizer::Lion(CPULionOptimizer::new(config, all_params))
+            }
+        }
+    }
+
+    /// Returns the number of values held in the optimizer state.
+    pub fn state_len(&self) -> usize {
+        self.state()
+            .iter()
+            .map(|(_, state)| state.iter().flatten().map(|x| x.len()).sum::<usize>())
+            .sum()
+    }
+
+    /// Returns the named per-layer state tensors of the optimizer.
+    pub fn state(&self) -> Vec<(&'static str, &Vec<Vec<ArrayD<f32>>>)> {
+        match self {
+            CPUOptimizer::SGD(sgd) => vec![("velocity", &sgd.velocity)],
+            CPUOptimizer::Adam(adam) => vec![("m", &adam.m), ("v", &adam.v)],
+            CPUOptimizer::Nadam(nadam) => vec![("m", &nadam.m), ("n", &nadam.n)],
+            CPUOptimizer::RMSProp(rmsprop) => vec![("acc_sg", &rmsprop.acc_sg)],
+            CPUOptimizer::AdamW(adamw) => vec![("m", &adamw.m), ("v", &adamw.v)],
+            CPUOptimizer::Adagrad(adagrad) => vec![("acc_sg", &adagrad.acc_sg)],
+            CPUOptimizer::Adadelta(adadelta) => vec![
+                ("acc_sg", &adadelta.acc_sg),
+                ("acc_delta", &adadelta.acc_delta),
+            ],
+            CPUOptimizer::AdaBelief(adabelief) => vec![("m", &adabelief.m), ("s", &adabelief.s)],
+            CPUOptimizer::Lion(lion) => vec![("m", &lion.m)],
+        }
+    }
+
+    pub fn state_mut(&mut self) -> Vec<(&'static str, &mut Vec<Vec<ArrayD<f32>>>)> {
+        match self {
+            CPUOptimizer::SGD(sgd) => vec![("velocity", &mut sgd.velocity)],
+            CPUOptimizer::Adam(adam) => vec![("m", &mut adam.m), ("v", &mut adam.v)],
+            CPUOptimizer::Nadam(nadam) => vec![("m", &mut nadam.m), ("n", &mut nadam.n)],
+            CPUOptimizer::RMSProp(rmsprop) => vec![("acc_sg", &mut rmsprop.acc_sg)],
+            CPUOptimizer::AdamW(adamw) => vec![("m", &mut adamw.m), ("v", &mut adamw.v)],
+            CPUOptimizer::Adagrad(adagrad) => vec![("acc_sg", &mut adagrad.acc_sg)],
+            CPUOptimizer::Adadelta(adadelta) => vec![
+                ("acc_sg", &mut adadelta.acc_sg),
+                ("acc_delta", &mut adadelta.acc_delta),
+            ],
+            CPUOptimizer::AdaBelief(adabelief) => {
+                vec![("m", &mut adabelief.m), ("s", &mut adabelief.s)]
+            }
+            CPUOptimizer::Lion(lion) => vec![("m", &mut lion.m)],
         }
     }
 
+    /// Returns the number of updates used for bias correction.
+    pub fn step(&self) -> usize {
+        match self {
+            CPUOptimizer::Adam(adam) => adam.t as usize,
+            CPUOptimizer::Nadam(nadam) => nadam.t as usize,
+            CPUOptimizer::AdamW(adamw) => adamw.t as usize,
+            CPUOptimizer::AdaBelief(adabelief) => adabelief.t as usize,
+            _ => 0,
+        }
+    }
+
+    pub fn set_step(&mut self, step: usize) {
+        match self {
+            CPUOptimizer::Adam(adam) => adam.t = step as f32,
+            CPUOptimizer::Nadam(nadam) => nadam.t = step as f32,
+            CPUOptimizer::AdamW(adamw) => adamw.t = step as f32,
+            CPUOptimizer::AdaBelief(adabelief) => adabelief.t = step as f32,
+            _ => {}
+        }
+    }
+
+    /// Applies one optimizer step and returns the global gradient norm
+    /// before clipping. Layers that are not `trainable` keep their
+    /// parameters and do not count towards the norm.
     pub fn update_grads(
         &mut self,
         layers: &mut Vec<CPULayer>,
+        trainable: &[bool],
         scheduler: &CPUScheduler,
         rate: f32,
         epoch: usize,
-    ) {
+        clipping: Option<&GradientClipping>,
+    ) -> f32 {
         match self {
             CPUOptimizer::Adam(adam) => adam.t += 1.0,
             CPUOptimizer::Nadam(nadam) => nadam.t += 1.0,
+            CPUOptimizer::AdamW(adamw) => adamw.t += 1.0,
+            CPUOptimizer::AdaBelief(adabelief) => adabelief.t += 1.0,
             _ => {}
         }
+        let mut norm = 0.0;
+        for (layer, _) in layers.iter_mut().zip(trainable).filter(|(_, x)| **x) {
+            if let Some((_, grads, _)) = CPUOptimizer::get_params(layer) {
+                norm += grads
+                    .iter()
+                    .map(|grad| grad.fold(0.0, |acc, x| acc + x * x))
+                    .sum::<f32>();
+            }
+        }
+        let norm = norm.sqrt();
+        let scale = match clipping.and_then(|clipping| clipping.global_norm) {
+            Some(max) if norm > max => max / norm,
+            _ => 1.0,
+        };
+        let ctx = UpdateContext {
+            scheduler,
+            rate,
+            step: epoch,
+        };
         let mut idx = 0;
-        for layer in layers.iter_mut() {
+        for (layer, trainable) in layers.iter_mut().zip(trainable) {
             if let Some((params, grads, l)) = CPUOptimizer::get_params(layer) {
+                // The state is indexed by layer, so frozen layers still count.
+                if !trainable {
+                    idx += 1;
+                    continue;
+                }
+                let clipped = clipping.map(|clipping| clip(&grads, clipping, scale));
+                let grads = match &clipped {
+                    Some(clipped) => clipped.iter().map(|grad| grad.view()).collect(),
+                    None => grads,
+                };
                 match self {
-                    CPUOptimizer::SGD(sgd) => {
-                        sgd.update_grads(params, grads, scheduler, rate, epoch, l)
+                    CPUOptimizer::SGD(sgd) => sgd.update_grads(params, grads, idx, &ctx, l),
+                    CPUOptimizer::Adam(adam) => adam.update_grads(params, grads, idx, &ctx, l),
+                    CPUOptimizer::Nadam(nadam) => nadam.update_grads(params, grads, idx, &ctx, l),
+                    CPUOptimizer::RMSProp(rmsprop) => {
+                        rmsprop.update_grads(params, grads, idx, &ctx, l)
                     }
-                    CPUOptimizer::Adam(adam) => {
-                        adam.update_grads(params, grads, idx, scheduler, rate, l)
+                    CPUOptimizer::AdamW(adamw) => adamw.update_grads(params, grads, idx, &ctx, l),
+                    CPUOptimizer::Adagrad(adagrad) => {
+                        adagrad.update_grads(params, grads, idx, &ctx, l)
                     }
-                    CPUOptimizer::Nadam(nadam) => {
-                        nadam.update_grads(params, grads, idx, scheduler, rate, l)
+                    CPUOptimizer::Adadelta(adadelta) => {
+                        adadelta.update_grads(params, grads, idx, &ctx, l)
                     }
-                    CPUOptimizer::RMSProp(rmsprop) => {
-                        rmsprop.update_grads(params, grads, idx, scheduler, rate, epoch, l)
+                    CPUOptimizer::AdaBelief(adabelief) => {
+                        adabelief.update_grads(params, grads, idx, &ctx, l)
                     }
+                    CPUOptimizer::Lion(lion) => lion.update_grads(params, grads, idx, &ctx, l),
                 }
                 idx += 1;
             }
         }
+        norm
     }
 
     pub fn get_params<'a>(
@@ -176,7 +331,94 @@ impl CPUOptimizer {
                     layer.l_biases.view().into_dyn(),
                 ],
             )),
+            CPULayer::GRU(layer) => Some((
+                vec![
+                    layer.w_hh.view_mut().into_dyn(),
+                    layer.w_ih.view_mut().into_dyn(),
+                    layer.biases.view_mut().into_dyn(),
+                ],
+                vec![
+                    layer.d_w_hh.view().into_dyn(),
+                    layer.d_w_ih.view().into_dyn(),
+                    layer.d_biases.view().into_dyn(),
+                ],
+                vec![
+                    layer.l_w_hh.view().into_dyn(),
+                    layer.l_w_ih.view().into_dyn(),
+                    layer.l_biases.view().into_dyn(),
+                ],
+            )),
             _ => return None,
         }
     }
 }
+
+/// Scales the gradients by the global norm factor, then clips each tensor by
+/// its own norm and finally every element by value.
+fn clip(grads: &[ArrayViewD<f32>], clipping: &GradientClipping, scale: f32) -> Vec<ArrayD<f32>> {
+    grads
+        .iter()
+        .map(|grad| {
+            let mut grad = grad.mapv(|x| x * scale);
+            if let Some(max) = clipping.norm {
+                let norm = grad.fold(0.0, |acc, x| acc + x * x).sqrt();
+                if norm > max {
+                    grad.mapv_inplace(|x| x * max / norm);
+                }
+            }
+            if let Some(max) = clipping.value {
+                grad.mapv_inplace(|x| x.clamp(-max, max));
+            }
+            grad
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr1, ArrayD};
+
+    use super::*;
+
+    fn clipping(value: Option<f32>, norm: Option<f32>) -> GradientClipping {
+        GradientClipping {
+            value,
+            norm,
+            global_norm: None,
+        }
+    }
+
+    fn clipped(grads: &[ArrayD<f32>], clipping: &GradientClipping, scale: f32) -> Vec<Vec<f32>> {
+        let views: Vec<_> = grads.iter().map(|grad| grad.view()).collect();
+        clip(&views, clipping, scale)
+            .into_iter()
+            .map(|grad| grad.into_iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn clips_each_tensor_by_its_norm_then_by_value() {
+        let grads = [arr1(&[3.0, 4.0]).into_dyn(), arr1(&[0.3, -0.4]).into_dyn()];
+        assert_eq!(
+            clipped(&grads, &clipping(None, Some(1.0)), 1.0),
+            [vec![0.6, 0.8], vec![0.3, -0.4]]
+        );
+        assert_eq!(
+            clipped(&grads, &clipping(Some(0.35), None), 1.0),
+            [vec![0.35, 0.35], vec![0.3, -0.35]]
+        );
+        assert_eq!(
+            clipped(&grads, &clipping(Some(0.7), Some(1.0)), 1.0),
+            [vec![0.6, 0.7], vec![0.3, -0.4]]
+        );
+    }
+
+    #[test]
+    fn applies_the_global_norm_scale_first() {
+        let grads = [arr1(&[3.0, 4.0]).into_dyn()];
+        assert_eq!(
+            clipped(&grads, &clipping(None, Some(10.0)), 0.5),
+            [vec![1.5, 2.0]]
+        );
+    }
+}
@@ -2,7 +2,7 @@ use std::ops::{Add, Div, Mul, SubAssign, Sub};
 
 use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
 
-use crate::{NadamOptimizer, CPUScheduler};
+use crate::{NadamOptimizer, UpdateContext};
 
 pub struct CPUNadamOptimizer {
     pub beta1: f32,
@@ -46,8 +46,7 @@ impl CPUNadamOptimizer {
         mut params: Vec<ArrayViewMutD<f32>>,
         grads: Vec<ArrayViewD<f32>>,
         idx: usize,
-        scheduler: &CPUScheduler,
-        rate: f32,
+        ctx: &UpdateContext,
         l: Vec<ArrayViewD<f32>>,
     ) {
         for (j, ((param, grad), li)) in params.iter_mut().zip(grads).zip(l).enumerate() {
@@ -65,7 +64,7 @@ impl CPUNadamOptimizer {
 
             let nestrov_m_hat = self.beta1.mul(&m_hat).add((1.0 - self.beta1).mul(&grad)).div(1.0 - self.beta1.powf(self.t));
 
-            let rate = scheduler.eta(rate, self.t as usize);
+            let rate = ctx.eta();
 
             param.sub_assign(
                 &rate
@@ -2,12 +2,12 @@ use std::ops::{Add, Div, Mul, SubAssign, Sub};
 
 use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
 
-use crate::{CPUScheduler, RMSPropOptimizer};
+use crate::{RMSPropOptimizer, UpdateContext};
 
 pub struct CPURMSPropOptimizer {
-    decay_rate: f32,
-    epsilon: f32,
-    acc_sg: Vec<Vec<ArrayD<f32>>>,
+    pub decay_rate: f32,
+    pub epsilon: f32,
+    pub acc_sg: Vec<Vec<ArrayD<f32>>>,
 }
 
 impl CPURMSPropOptimizer {
@@ -33,9 +33,7 @@ impl CPURMSPropOptimizer {
         mut params: Vec<ArrayViewMutD<f32>>,
         grads: Vec<ArrayViewD<f32>>,
         idx: usize,
-        scheduler: &CPUScheduler,
-        rate: f32,
-        epoch: usize,
+        ctx: &UpdateContext,
         l: Vec<ArrayViewD<f32>>,
     ) {
         for (j, ((param, grad), li)) in params.iter_mut().zip(grads).zip(l).enumerate() {
@@ -44,7 +42,7 @@ impl CPURMSPropOptimizer {
                 .mul(&self.acc_sg[idx][j])
                 .add((1.0 - self.decay_rate).mul(&grad.map(|x| x.powi(2))));
 
-            let rate = scheduler.eta(rate, epoch);
+            let rate = ctx.eta();
 
             param.sub_assign(
                 &rate
@@ -1,28 +1,66 @@
-use std::ops::{Mul, SubAssign};
+use std::ops::{Add, Mul, SubAssign};
 
-use ndarray::{ArrayViewD, ArrayViewMutD};
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD};
 
-use crate::CPUScheduler;
+use crate::{SGDOptimizer, UpdateContext};
 
-pub struct CPUSGDOptimizer {}
+pub struct CPUSGDOptimizer {
+    pub momentum: f32,
+    pub nesterov: bool,
+    pub velocity: Vec<Vec<ArrayD<f32>>>,
+}
 
 impl CPUSGDOptimizer {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: Option<SGDOptimizer>, params: Vec<Vec<ArrayViewMutD<f32>>>) -> Self {
+        let (momentum, nesterov) = match config {
+            Some(config) => (config.momentum, config.nesterov.unwrap_or(false)),
+            None => (0.0, false),
+        };
+        let mut velocity = Vec::new();
+        if momentum != 0.0 {
+            for params in params {
+                velocity.push(
+                    params
+                        .iter()
+                        .map(|param| ArrayD::zeros(param.dim()))
+                        .collect(),
+                );
+            }
+        }
+        Self {
+            momentum,
+            nesterov,
+            velocity,
+        }
     }
 
     pub fn update_grads(
         &mut self,
         mut params: Vec<ArrayViewMutD<f32>>,
         grads: Vec<ArrayViewD<f32>>,
-        scheduler: &CPUScheduler,
-        rate: f32,
-        epoch: usize,
+        idx: usize,
+        ctx: &UpdateContext,
         l: Vec<ArrayViewD<f32>>,
     ) {
-        let eta = scheduler.eta(rate, epoch);
-        for ((param, grad), li) in params.iter_mut().zip(grads).zip(l) {
-            param.sub_assign(&(&grad - &li).mul(eta));
+        let eta = ctx.eta();
+        for (j, ((param, grad), li)) in params.iter_mut().zip(grads).zip(l).enumerate() {
+            let grad = &grad - &li;
+            if self.momentum == 0.0 {
+                param.sub_assign(&grad.mul(eta));
+                continue;
+            }
+            self.velocity[idx][j] = self.momentum.mul(&self.velocity[idx][j]).add(&grad);
+            if self.nesterov {
+                param.sub_assign(
+                    &self
+                        .momentum
+                        .mul(&self.velocity[idx][j])
+                        .add(&grad)
+                        .mul(eta),
+                );
+            } else {
+                param.sub_assign(&self.velocity[idx][j].view().mul(eta));
+            }
         }
     }
 }
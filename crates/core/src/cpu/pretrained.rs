@@ -0,0 +1,353 @@
+use std::collections::{HashMap, HashSet};
+
+use ndarray::ArrayD;
+use safetensors::SafeTensors;
+
+use crate::{
+    to_arr, validate, Backend, BackendConfig, Logger, MismatchedTensor, MissingTensor,
+    NetsaurError, NetsaurResult, PretrainedReport, TensorMapping, Timer,
+};
+
+/// Drops the axes of length one, so `[C]` fits a `(1, C)` parameter.
+fn squeezed(shape: &[usize]) -> Vec<usize> {
+    shape.iter().cloned().filter(|x| *x != 1).collect()
+}
+
+fn arrange(tensor: ArrayD<f32>, mapping: &TensorMapping) -> NetsaurResult<ArrayD<f32>> {
+    let tensor = match (&mapping.permute, mapping.transpose.unwrap_or(false)) {
+        (Some(_), true) => {
+            return Err(NetsaurError::InvalidOption(format!(
+                "mapping of {} sets both transpose and permute",
+                mapping.source
+            )))
+        }
+        (Some(axes), false) => {
+            let mut sorted = axes.clone();
+            sorted.sort();
+            if sorted != (0..tensor.ndim()).collect::<Vec<_>>() {
+                return Err(NetsaurError::InvalidOption(format!(
+                    "permute {:?} does not fit tensor {} of rank {}",
+                    axes,
+                    mapping.source,
+                    tensor.ndim()
+                )));
+            }
+            tensor.permuted_axes(axes.clone())
+        }
+        (None, true) => tensor.reversed_axes(),
+        (None, false) => tensor,
+    };
+    Ok(tensor.as_standard_layout().into_owned())
+}
+
+impl Backend {
+    /// Builds a backend from `config` and fills its parameters with tensors
+    /// of a safetensors file written by another framework, converting them
+    /// to f32. Parameters that no mapping provides keep their initial
+    /// values and are listed in the report, as are tensors with a shape
+    /// that does not fit.
+    pub fn load_pretrained(
+        config: BackendConfig,
+        buffer: &[u8],
+        mappings: &[TensorMapping],
+        logger: Logger,
+        timer: Timer,
+    ) -> NetsaurResult<(Self, PretrainedReport)> {
+        validate(&config)?;
+        let file = SafeTensors::deserialize(buffer)?;
+        let initial = Backend::new(config.clone(), logger.clone(), timer.clone(), None);
+        let mut params: HashMap<String, ArrayD<f32>> = initial
+            .tensors()
+            .into_iter()
+            .map(|(key, tensor)| (key, tensor.data.to_owned()))
+            .collect();
+        let names = config.layer_names();
+
+        let mut report = PretrainedReport::default();
+        let mut loaded = HashSet::new();
+        for mapping in mappings {
+            let layer = &config
+                .layers
+                .get(mapping.layer)
+                .ok_or(NetsaurError::UnknownLayer(mapping.layer))?
+                .layer;
+            if !layer.params().contains(&mapping.param) {
+                return Err(NetsaurError::InvalidOption(format!(
+                    "layer #{} ({}) has no {} parameter",
+                    mapping.layer,
+                    layer.name(),
+                    mapping.param.name()
+                )));
+            }
+            let tensor = match file.tensor(&mapping.source) {
+                Ok(view) => arrange(to_arr(view)?, mapping)?,
+                Err(_) => continue,
+            };
+            let target = params
+                .get_mut(&mapping.param.key(&names[mapping.layer]))
+                .unwrap();
+            if tensor.shape() == target.shape() {
+                *target = tensor;
+            } else if squeezed(tensor.shape()) == squeezed(target.shape()) {
+                *target = tensor.into_shape_with_order(target.raw_dim())?;
+            } else {
+                report.mismatched.push(MismatchedTensor {
+                    layer: mapping.layer,
+                    param: mapping.param,
+                    source: mapping.source.clone(),
+                    expected: target.shape().to_vec(),
+                    got: tensor.shape().to_vec(),
+                });
+                continue;
+            }
+            loaded.insert((mapping.layer, mapping.param));
+        }
+
+        for (i, entry) in config.layers.iter().enumerate() {
+            for param in entry.layer.params() {
+                let mapped = mappings
+                    .iter()
+                    .rfind(|mapping| mapping.layer == i && mapping.param == *param);
+                let mismatched = report
+                    .mismatched
+                    .iter()
+                    .any(|tensor| tensor.layer == i && tensor.param == *param);
+                if loaded.contains(&(i, *param)) || mismatched {
+                    continue;
+                }
+                report.missing.push(MissingTensor {
+                    layer: i,
+                    param: *param,
+                    source: mapped.map(|mapping| mapping.source.clone()),
+                });
+            }
+        }
+        let used: HashSet<&str> = mappings.iter().map(|x| x.source.as_str()).collect();
+        report.unused = file
+            .names()
+            .into_iter()
+            .filter(|name| !used.contains(name.as_str()))
+            .cloned()
+            .collect();
+        report.unused.sort();
+
+        let tensors = Self::layer_tensors(&config, |i, param| {
+            let key = param.key(&names[i]);
+            params
+                .remove(&key)
+                .ok_or(NetsaurError::Internal(format!("missing tensor {}", key)))
+        })?;
+        Ok((Backend::new(config, logger, timer, Some(tensors)), report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use half::{bf16, f16};
+    use ndarray::{Array, IxDyn};
+    use safetensors::{tensor::TensorView, Dtype};
+
+    use super::*;
+    use crate::{LayerParam, Tensor};
+
+    const CONFIG: &str = r#"{
+        "size": [2, 3],
+        "seed": 1,
+        "layers": [
+            { "type": "dense", "config": { "size": [4] } },
+            { "type": "batchnorm1d", "config": { "momentum": 0.9, "epsilon": 0.001 } },
+            { "type": "dense", "config": { "size": [2] } }
+        ],
+        "cost": "mse",
+        "optimizer": { "type": "sgd" },
+        "scheduler": { "type": "none" }
+    }"#;
+
+    const MAPPINGS: &str = r#"[
+        { "source": "fc1.weight", "layer": 0, "param": "weight", "transpose": true },
+        { "source": "fc1.bias", "layer": 0, "param": "bias" },
+        { "source": "bn.weight", "layer": 1, "param": "gamma" },
+        { "source": "bn.bias", "layer": 1, "param": "beta" },
+        { "source": "fc2.weight", "layer": 2, "param": "weight", "transpose": true }
+    ]"#;
+
+    fn logger() -> Logger {
+        Logger { log: |_| {} }
+    }
+
+    fn timer() -> Timer {
+        Timer { now: || 0 }
+    }
+
+    fn range(shape: &[usize]) -> ArrayD<f32> {
+        let len = shape.iter().product::<usize>();
+        Array::range(0.0, len as f32, 1.0)
+            .into_shape_with_order(IxDyn(shape))
+            .unwrap()
+    }
+
+    /// A PyTorch style file: `fc1.weight` is `[out, in]`, `fc2.weight` has
+    /// the wrong number of inputs, `bn.bias` is missing and `extra` is not
+    /// mapped.
+    fn file() -> Vec<u8> {
+        let tensors = [
+            ("fc1.weight", range(&[4, 3])),
+            ("fc1.bias", range(&[4])),
+            ("bn.weight", range(&[4])),
+            ("fc2.weight", range(&[2, 5])),
+            ("extra", range(&[1])),
+        ];
+        let views = tensors
+            .iter()
+            .map(|(name, tensor)| (name.to_string(), Tensor::new(tensor.view())));
+        safetensors::serialize(views, &None).unwrap()
+    }
+
+    fn load() -> NetsaurResult<(Backend, PretrainedReport)> {
+        Backend::load_pretrained(
+            serde_json::from_str(CONFIG).unwrap(),
+            &file(),
+            &serde_json::from_str::<Vec<TensorMapping>>(MAPPINGS).unwrap(),
+            logger(),
+            timer(),
+        )
+    }
+
+    #[test]
+    fn loads_mapped_tensors() {
+        let (backend, _) = load().unwrap();
+        let weights: HashMap<_, _> = backend
+            .tensors()
+            .into_iter()
+            .map(|(key, tensor)| (key, tensor.data.to_owned()))
+            .collect();
+        let transposed = range(&[4, 3]).reversed_axes();
+        assert_eq!(weights["dense0.weight"], transposed);
+        assert_eq!(weights["dense0.bias"], range(&[4]));
+        // `[C]` fills the `(1, C)` batch norm parameters
+        let gamma = range(&[4]).into_shape_with_order((1, 4)).unwrap();
+        assert_eq!(weights["batchnorm1d0.gamma"], gamma.into_dyn());
+    }
+
+    #[test]
+    fn reports_mismatched_missing_and_unused_tensors() {
+        let (backend, report) = load().unwrap();
+        let initial = Backend::new(
+            serde_json::from_str(CONFIG).unwrap(),
+            logger(),
+            timer(),
+            None,
+        );
+
+        assert_eq!(report.mismatched.len(), 1);
+        let mismatched = &report.mismatched[0];
+        assert_eq!(
+            (mismatched.layer, mismatched.param),
+            (2, LayerParam::Weight)
+        );
+        assert_eq!(mismatched.source, "fc2.weight");
+        assert_eq!(
+            (&mismatched.expected[..], &mismatched.got[..]),
+            (&[4, 2][..], &[5, 2][..])
+        );
+
+        let missing: Vec<_> = report
+            .missing
+            .iter()
+            .map(|x| (x.layer, x.param, x.source.as_deref()))
+            .collect();
+        assert_eq!(
+            missing,
+            [
+                (1, LayerParam::Beta, Some("bn.bias")),
+                (1, LayerParam::RunningMean, None),
+                (1, LayerParam::RunningVar, None),
+                (2, LayerParam::Bias, None),
+            ]
+        );
+        assert_eq!(report.unused, ["extra"]);
+
+        // parameters that were not loaded keep their initial values
+        let (initial, loaded) = (initial.tensors(), backend.tensors());
+        let weight = |tensors: &[(String, Tensor)], key: &str| {
+            let (_, tensor) = tensors.iter().find(|(name, _)| name == key).unwrap();
+            tensor.data.to_owned()
+        };
+        for key in ["dense1.weight", "dense1.bias", "batchnorm1d0.beta"] {
+            assert_eq!(weight(&loaded, key), weight(&initial, key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn rejects_mappings_to_missing_parameters() {
+        let file = file();
+        let mappings = [
+            r#"[{ "source": "fc1.weight", "layer": 3, "param": "weight" }]"#,
+            r#"[{ "source": "fc1.weight", "layer": 1, "param": "weight" }]"#,
+            r#"[{ "source": "fc1.weight", "layer": 0, "param": "weight", "permute": [0, 0] }]"#,
+        ];
+        for mapping in mappings {
+            let result = Backend::load_pretrained(
+                serde_json::from_str(CONFIG).unwrap(),
+                &file,
+                &serde_json::from_str::<Vec<TensorMapping>>(mapping).unwrap(),
+                logger(),
+                timer(),
+            );
+            assert!(result.is_err(), "{}", mapping);
+        }
+    }
+
+    #[test]
+    fn converts_half_and_double_precision_tensors() {
+        let values = range(&[4, 3]);
+        let f16: Vec<u8> = values
+            .iter()
+            .flat_map(|x| f16::from_f32(*x).to_le_bytes())
+            .collect();
+        let bf16: Vec<u8> = range(&[4])
+            .iter()
+            .flat_map(|x| bf16::from_f32(*x).to_le_bytes())
+            .collect();
+        let f64: Vec<u8> = range(&[4])
+            .iter()
+            .flat_map(|x| (*x as f64).to_le_bytes())
+            .collect();
+        let views = [
+            ("fc1.weight", TensorView::new(Dtype::F16, vec![4, 3], &f16)),
+            ("fc1.bias", TensorView::new(Dtype::BF16, vec![4], &bf16)),
+            ("bn.weight", TensorView::new(Dtype::F64, vec![4], &f64)),
+        ];
+        for (name, view) in &views {
+            let view = view.as_ref().unwrap();
+            assert_eq!(
+                to_arr(view.clone()).unwrap(),
+                range(view.shape()),
+                "{}",
+                name
+            );
+        }
+
+        let file = safetensors::serialize(
+            views.into_iter().map(|(name, view)| (name, view.unwrap())),
+            &None,
+        )
+        .unwrap();
+        let (backend, report) = Backend::load_pretrained(
+            serde_json::from_str(CONFIG).unwrap(),
+            &file,
+            &serde_json::from_str::<Vec<TensorMapping>>(MAPPINGS).unwrap(),
+            logger(),
+            timer(),
+        )
+        .unwrap();
+        assert!(report.mismatched.is_empty());
+        let weights: HashMap<_, _> = backend
+            .tensors()
+            .into_iter()
+            .map(|(key, tensor)| (key, tensor.data.to_owned()))
+            .collect();
+        assert_eq!(weights["dense0.weight"], values.reversed_axes());
+        assert_eq!(weights["dense0.bias"], range(&[4]));
+    }
+}
@@ -0,0 +1,34 @@
+use std::f32::consts::PI;
+
+use crate::CosineAnnealingScheduler;
+
+pub struct CPUCosineAnnealingScheduler {
+    pub min_rate: f32,
+    pub step_size: usize,
+    pub multiplier: usize,
+}
+
+impl CPUCosineAnnealingScheduler {
+    pub fn new(config: &CosineAnnealingScheduler) -> Self {
+        CPUCosineAnnealingScheduler {
+            min_rate: config.min_rate,
+            step_size: config.step_size.max(1),
+            multiplier: config.multiplier.unwrap_or(1).max(1),
+        }
+    }
+    /// Anneals from `rate` to `min_rate` over each cycle and restarts at
+    /// `rate`, growing the cycle by `multiplier` after every restart.
+    pub fn eta(&self, rate: f32, step: usize) -> f32 {
+        let (mut step, mut cycle) = (step, self.step_size);
+        if self.multiplier == 1 {
+            step %= cycle;
+        } else {
+            while step >= cycle {
+                step -= cycle;
+                cycle *= self.multiplier;
+            }
+        }
+        let progress = step as f32 / cycle as f32;
+        self.min_rate + 0.5 * (rate - self.min_rate) * (1.0 + (PI * progress).cos())
+    }
+}
@@ -15,7 +15,10 @@ impl CPUDecayScheduler {
     pub fn exponential(&self, rate: f32, step: usize) -> f32 {
         rate * self.rate.powi((step / self.step_size) as i32)
     }
+    /// Removes `1 - rate` of the initial rate every `step_size` steps,
+    /// stopping at zero.
     pub fn linear(&self, rate: f32, step: usize) -> f32 {
-        rate / (self.rate * (1 + step / self.step_size) as f32)
+        let decay = (1.0 - self.rate) * (step / self.step_size) as f32;
+        rate * (1.0 - decay).max(0.0)
     }
 }
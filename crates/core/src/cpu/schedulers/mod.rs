@@ -1,15 +1,27 @@
+mod cosine;
 mod decay;
 mod oc;
+mod plateau;
+mod step;
+mod warmup;
 
-use crate::Scheduler;
+use crate::{PlateauState, Scheduler};
 
+pub use cosine::*;
 pub use decay::*;
 pub use oc::*;
+pub use plateau::*;
+pub use step::*;
+pub use warmup::*;
 pub enum CPUScheduler {
     None,
     LinearDecay(CPUDecayScheduler),
     ExponentialDecay(CPUDecayScheduler),
     OneCycle(CPUOneCycleScheduler),
+    CosineAnnealing(CPUCosineAnnealingScheduler),
+    Warmup(CPUWarmupScheduler),
+    MultiStep(CPUMultiStepScheduler),
+    ReduceOnPlateau(CPUReduceOnPlateauScheduler),
 }
 
 impl CPUScheduler {
@@ -25,6 +37,16 @@ impl CPUScheduler {
             Scheduler::OneCycle(config) => {
                 CPUScheduler::OneCycle(CPUOneCycleScheduler::new(config))
             }
+            Scheduler::CosineAnnealing(config) => {
+                CPUScheduler::CosineAnnealing(CPUCosineAnnealingScheduler::new(config))
+            }
+            Scheduler::Warmup(config) => CPUScheduler::Warmup(CPUWarmupScheduler::new(config)),
+            Scheduler::MultiStep(config) => {
+                CPUScheduler::MultiStep(CPUMultiStepScheduler::new(config))
+            }
+            Scheduler::ReduceOnPlateau(config) => {
+                CPUScheduler::ReduceOnPlateau(CPUReduceOnPlateauScheduler::new(config))
+            }
         }
     }
     pub fn eta(&self, rate: f32, step: usize) -> f32 {
@@ -33,6 +55,102 @@ impl CPUScheduler {
             CPUScheduler::LinearDecay(scheduler) => scheduler.linear(rate, step),
             CPUScheduler::ExponentialDecay(scheduler) => scheduler.exponential(rate, step),
             CPUScheduler::OneCycle(scheduler) => scheduler.eta(rate, step),
+            CPUScheduler::CosineAnnealing(scheduler) => scheduler.eta(rate, step),
+            CPUScheduler::Warmup(scheduler) => scheduler.eta(rate, step),
+            CPUScheduler::MultiStep(scheduler) => scheduler.eta(rate, step),
+            CPUScheduler::ReduceOnPlateau(scheduler) => scheduler.eta(rate),
+        }
+    }
+    /// Feeds the cost of a finished epoch to schedulers that adapt to it.
+    pub fn observe(&mut self, cost: f32) {
+        match self {
+            CPUScheduler::Warmup(warmup) => warmup.scheduler.observe(cost),
+            CPUScheduler::ReduceOnPlateau(scheduler) => scheduler.observe(cost),
+            _ => {}
+        }
+    }
+    pub fn plateau(&self) -> Option<&PlateauState> {
+        match self {
+            CPUScheduler::Warmup(warmup) => warmup.scheduler.plateau(),
+            CPUScheduler::ReduceOnPlateau(scheduler) => Some(&scheduler.state),
+            _ => None,
+        }
+    }
+    pub fn set_plateau(&mut self, state: PlateauState) {
+        match self {
+            CPUScheduler::Warmup(warmup) => warmup.scheduler.set_plateau(state),
+            CPUScheduler::ReduceOnPlateau(scheduler) => scheduler.state = state,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(json: &str) -> CPUScheduler {
+        CPUScheduler::from(&serde_json::from_str(json).unwrap())
+    }
+
+    fn rates(scheduler: &CPUScheduler, steps: usize) -> Vec<f32> {
+        (0..steps).map(|step| scheduler.eta(1.0, step)).collect()
+    }
+
+    #[test]
+    fn linear_decay_stops_at_zero() {
+        let linear =
+            scheduler(r#"{ "type": "lineardecay", "config": { "rate": 0.5, "step_size": 1 } }"#);
+        assert_eq!(rates(&linear, 4), [1.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn cosine_annealing_restarts_with_growing_cycles() {
+        let cosine = scheduler(
+            r#"{
+                "type": "cosineannealing",
+                "config": { "minRate": 0.0, "stepSize": 2, "multiplier": 2 }
+            }"#,
+        );
+        let rates = rates(&cosine, 7);
+        let expected = [1.0, 0.5, 1.0, 0.8535534, 0.5, 0.14644662, 1.0];
+        for (rate, expected) in rates.iter().zip(expected) {
+            assert!((rate - expected).abs() < 1e-6, "{:?}", rates);
+        }
+    }
+
+    #[test]
+    fn warmup_hands_over_to_the_wrapped_scheduler() {
+        let warmup = scheduler(
+            r#"{
+                "type": "warmup",
+                "config": {
+                    "steps": 2,
+                    "scheduler": { "type": "multistep", "config": { "milestones": [1], "gamma": 0.1 } }
+                }
+            }"#,
+        );
+        assert_eq!(rates(&warmup, 4), [0.0, 0.5, 1.0, 0.1]);
+    }
+
+    #[test]
+    fn reduce_on_plateau_waits_for_patience() {
+        let mut plateau = scheduler(
+            r#"{ "type": "reduceonplateau", "config": { "factor": 0.5, "patience": 1 } }"#,
+        );
+        let mut rates = Vec::new();
+        for cost in [1.0, 0.5, 0.5, 0.5, 0.4] {
+            plateau.observe(cost);
+            rates.push(plateau.eta(1.0, 0));
         }
+        assert_eq!(rates, [1.0, 1.0, 1.0, 0.5, 0.5]);
+        assert_eq!(
+            plateau.plateau(),
+            Some(&PlateauState {
+                scale: 0.5,
+                best: Some(0.4),
+                wait: 0
+            })
+        );
     }
 }
@@ -0,0 +1,45 @@
+use crate::{PlateauState, ReduceOnPlateauScheduler};
+
+pub struct CPUReduceOnPlateauScheduler {
+    pub factor: f32,
+    pub patience: usize,
+    pub threshold: f32,
+    pub min_rate: f32,
+    pub state: PlateauState,
+}
+
+impl CPUReduceOnPlateauScheduler {
+    pub fn new(config: &ReduceOnPlateauScheduler) -> Self {
+        CPUReduceOnPlateauScheduler {
+            factor: config.factor,
+            patience: config.patience,
+            threshold: config.threshold.unwrap_or(1e-4),
+            min_rate: config.min_rate.unwrap_or(0.0),
+            state: PlateauState {
+                scale: 1.0,
+                best: None,
+                wait: 0,
+            },
+        }
+    }
+    pub fn eta(&self, rate: f32) -> f32 {
+        (rate * self.state.scale).max(self.min_rate)
+    }
+    /// Records the cost of an epoch, reducing the rate by `factor` once it
+    /// has not improved for more than `patience` epochs.
+    pub fn observe(&mut self, cost: f32) {
+        match self.state.best {
+            Some(best) if cost >= best * (1.0 - self.threshold) => {
+                self.state.wait += 1;
+                if self.state.wait > self.patience {
+                    self.state.scale *= self.factor;
+                    self.state.wait = 0;
+                }
+            }
+            _ => {
+                self.state.best = Some(cost);
+                self.state.wait = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::MultiStepScheduler;
+
+pub struct CPUMultiStepScheduler {
+    pub milestones: Vec<usize>,
+    pub gamma: f32,
+}
+
+impl CPUMultiStepScheduler {
+    pub fn new(config: &MultiStepScheduler) -> Self {
+        CPUMultiStepScheduler {
+            milestones: config.milestones.clone(),
+            gamma: config.gamma,
+        }
+    }
+    /// Multiplies the rate by `gamma` for every milestone already reached.
+    pub fn eta(&self, rate: f32, step: usize) -> f32 {
+        let reached = self.milestones.iter().filter(|m| **m <= step).count();
+        rate * self.gamma.powi(reached as i32)
+    }
+}
@@ -0,0 +1,27 @@
+use crate::{CPUScheduler, WarmupScheduler};
+
+pub struct CPUWarmupScheduler {
+    pub steps: usize,
+    pub start_factor: f32,
+    pub scheduler: Box<CPUScheduler>,
+}
+
+impl CPUWarmupScheduler {
+    pub fn new(config: &WarmupScheduler) -> Self {
+        CPUWarmupScheduler {
+            steps: config.steps,
+            start_factor: config.start_factor.unwrap_or(0.0),
+            scheduler: Box::new(CPUScheduler::from(&config.scheduler)),
+        }
+    }
+    /// Ramps linearly from `start_factor * rate` to `rate`, then hands over
+    /// to the wrapped scheduler counting from the end of the warmup.
+    pub fn eta(&self, rate: f32, step: usize) -> f32 {
+        if step < self.steps {
+            let progress = step as f32 / self.steps as f32;
+            rate * (self.start_factor + (1.0 - self.start_factor) * progress)
+        } else {
+            self.scheduler.eta(rate, step - self.steps)
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use ndarray::ArrayD;
+
+use crate::{validate, Backend, BackendConfig, LayerEntry, NetsaurError, NetsaurResult};
+
+impl Backend {
+    /// Drops every layer from index `len` on, e.g. to remove the head of a
+    /// pretrained classifier before appending a new one.
+    pub fn truncate(&mut self, len: usize) -> NetsaurResult<()> {
+        if len > self.layers.len() {
+            return Err(NetsaurError::UnknownLayer(len));
+        }
+        let mut config = self.config.clone();
+        config.layers.truncate(len);
+        self.rebuild(config, len)
+    }
+
+    /// Appends `layers` to the network. The new layers start from freshly
+    /// initialized parameters while the existing ones keep theirs.
+    pub fn append(&mut self, layers: Vec<LayerEntry>) -> NetsaurResult<()> {
+        let mut config = self.config.clone();
+        let kept = config.layers.len();
+        config.layers.extend(layers);
+        self.rebuild(config, kept)
+    }
+
+    /// Replaces the backend with one built from `config`, carrying over the
+    /// parameters of the first `kept` layers. The optimizer state and the
+    /// training progress start over, since they belong to the old network.
+    fn rebuild(&mut self, config: BackendConfig, kept: usize) -> NetsaurResult<()> {
+        validate(&config)?;
+        let mut params: HashMap<String, ArrayD<f32>> = self
+            .tensors()
+            .into_iter()
+            .map(|(key, tensor)| (key, tensor.data.to_owned()))
+            .collect();
+        let names = self.config.layer_names();
+        let mut prefix = config.clone();
+        prefix.layers.truncate(kept);
+        let tensors = Self::layer_tensors(&prefix, |i, param| {
+            let key = param.key(&names[i]);
+            params
+                .remove(&key)
+                .ok_or(NetsaurError::Internal(format!("missing tensor {}", key)))
+        })?;
+        let (logger, timer) = (self.logger.clone(), self.timer.clone());
+        *self = Backend::new(config, logger, timer, Some(tensors));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Logger, PostProcessor, Timer};
+
+    const CLASSIFIER: &str = r#"{
+        "size": [4, 3],
+        "seed": 5,
+        "layers": [
+            { "type": "dense", "config": { "size": [6] }, "trainable": false },
+            { "type": "batchnorm1d", "config": { "momentum": 0.9, "epsilon": 0.001 } },
+            { "type": "activation", "config": { "activation": "relu" } },
+            { "type": "dense", "config": { "size": [3] } },
+            { "type": "softmax", "config": {} }
+        ],
+        "cost": "crossentropy",
+        "optimizer": { "type": "sgd" },
+        "scheduler": { "type": "none" }
+    }"#;
+
+    fn backend() -> Backend {
+        Backend::new(
+            serde_json::from_str(CLASSIFIER).unwrap(),
+            Logger { log: |_| {} },
+            Timer { now: || 0 },
+            None,
+        )
+    }
+
+    fn weights(backend: &Backend) -> HashMap<String, ArrayD<f32>> {
+        backend
+            .tensors()
+            .into_iter()
+            .map(|(key, tensor)| (key, tensor.data.to_owned()))
+            .collect()
+    }
+
+    fn entries(json: &str) -> Vec<LayerEntry> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn replacing_the_head_keeps_the_backbone() {
+        let mut model = backend();
+        let inputs = ArrayD::from_shape_fn(vec![4, 3], |x| (x[0] * 3 + x[1]) as f32 * 0.1);
+        let backbone = |model: &Backend| {
+            let layers = Some(vec![0, 1, 2]);
+            model.predict(inputs.clone(), PostProcessor::None, layers)
+        };
+        let features = backbone(&model).unwrap();
+        let before = weights(&model);
+
+        model.truncate(3).unwrap();
+        assert_eq!(model.layers.len(), 3);
+        model
+            .append(entries(
+                r#"[
+                    { "type": "dense", "config": { "size": [5] }, "name": "head" },
+                    { "type": "softmax", "config": {} }
+                ]"#,
+            ))
+            .unwrap();
+        assert_eq!(model.size, [4, 5]);
+        assert_eq!(backbone(&model).unwrap(), features);
+        assert!(!model.config.layers[0].trainable());
+
+        let after = weights(&model);
+        for (key, tensor) in &before {
+            match after.get(key) {
+                Some(kept) => assert_eq!(kept, tensor, "{}", key),
+                None => assert!(key.starts_with("dense1."), "{}", key),
+            }
+        }
+        assert_eq!(after["head.weight"].shape(), [6, 5]);
+        let output = model.predict(inputs, PostProcessor::None, None).unwrap();
+        assert_eq!(output.shape(), [4, 5]);
+    }
+
+    #[test]
+    fn rejected_edits_leave_the_model_unchanged() {
+        let mut model = backend();
+        let before = weights(&model);
+        assert!(matches!(
+            model.truncate(6),
+            Err(NetsaurError::UnknownLayer(6))
+        ));
+        let conv = r#"[{ "type": "conv2d", "config": { "kernelSize": [2, 1, 3, 3] } }]"#;
+        assert!(model.append(entries(conv)).is_err());
+        let duplicate = r#"[{ "type": "dense", "config": { "size": [2] }, "name": "dense0" }]"#;
+        assert!(model.append(entries(duplicate)).is_err());
+
+        assert_eq!(model.layers.len(), 5);
+        assert_eq!(model.size, [4, 3]);
+        assert_eq!(weights(&model), before);
+    }
+}
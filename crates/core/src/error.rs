@@ -0,0 +1,93 @@
+use std::cell::RefCell;
+
+/// Errors that can surface at the FFI and WASM boundary.
+#[derive(thiserror::Error, Debug)]
+pub enum NetsaurError {
+    #[error("invalid config: {0}")]
+    Config(#[from] serde_json::Error),
+
+    #[error("invalid option: {0}")]
+    InvalidOption(String),
+
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("invalid utf-8 in config: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
+
+    #[error("shape error: {0}")]
+    Shape(#[from] ndarray::ShapeError),
+
+    #[error("{msg}, expected: {expected:?}, got: {got:?}")]
+    ShapeMismatch {
+        msg: &'static str,
+        expected: Vec<usize>,
+        got: Vec<usize>,
+    },
+
+    #[error("layer #{index} ({layer}): {msg}, expected: {expected}, got: {got:?}")]
+    InvalidLayer {
+        index: usize,
+        layer: &'static str,
+        msg: &'static str,
+        expected: String,
+        got: Vec<usize>,
+    },
+
+    #[error("unknown backend id {0}")]
+    UnknownBackend(usize),
+
+    #[error("layer #{0} does not exist")]
+    UnknownLayer(usize),
+
+    #[error("invalid safetensors file: {0}")]
+    SafeTensors(#[from] safetensors::SafeTensorError),
+
+    #[error("invalid model file: {0}")]
+    Corrupt(String),
+
+    #[error("invalid onnx file: {0}")]
+    OnnxDecode(#[from] prost::DecodeError),
+
+    #[error("onnx: {0}")]
+    Onnx(String),
+
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl NetsaurError {
+    /// Status code returned by the FFI functions, `0` is reserved for success.
+    pub fn code(&self) -> i32 {
+        match self {
+            NetsaurError::Config(_)
+            | NetsaurError::InvalidOption(_)
+            | NetsaurError::InvalidConfig(_)
+            | NetsaurError::Utf8(_) => 1,
+            NetsaurError::Shape(_)
+            | NetsaurError::ShapeMismatch { .. }
+            | NetsaurError::InvalidLayer { .. } => 2,
+            NetsaurError::UnknownBackend(_) | NetsaurError::UnknownLayer(_) => 3,
+            NetsaurError::SafeTensors(_)
+            | NetsaurError::Corrupt(_)
+            | NetsaurError::OnnxDecode(_)
+            | NetsaurError::Onnx(_) => 4,
+            NetsaurError::Internal(_) => 5,
+        }
+    }
+}
+
+pub type NetsaurResult<T> = Result<T, NetsaurError>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Stores the message of the last error so the host can retrieve it.
+pub fn set_last_error(error: &NetsaurError) {
+    LAST_ERROR.with(|cell| *cell.borrow_mut() = Some(error.to_string()));
+}
+
+pub fn take_last_error() -> Option<String> {
+    LAST_ERROR.with(|cell| cell.borrow_mut().take())
+}
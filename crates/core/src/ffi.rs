@@ -1,9 +1,12 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::slice::{from_raw_parts, from_raw_parts_mut};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
-    decode_array, decode_json, length, Backend, Dataset, Logger, PredictOptions, Timer,
-    TrainOptions, RESOURCES,
+    decode_array, decode_json, insert_backend, length, remove_backend, set_last_error,
+    take_last_error, validate, with_backend, with_backend_ref, Backend, BackendConfig, Dataset,
+    EvaluateOptions, LayerEntry, Logger, NetsaurError, NetsaurResult, PredictOptions,
+    TensorMapping, Timer, TrainOptions,
 };
 
 type AllocBufferFn = extern "C" fn(usize) -> *mut u8;
@@ -19,12 +22,30 @@ fn now() -> u128 {
         .as_millis()
 }
 
-#[no_mangle]
-pub extern "C" fn ffi_backend_create(ptr: *const u8, len: usize, alloc: AllocBufferFn) -> usize {
-    let config = decode_json(ptr, len);
-    let net_backend = Backend::new(config, Logger { log }, Timer { now }, None);
-    let buf: Vec<u8> = net_backend
-        .size
+/// Runs `f` and turns its result into a status code, `0` meaning success.
+/// The message of a failed call can be retrieved with `ffi_backend_error`.
+fn status(f: impl FnOnce() -> NetsaurResult<()>) -> i32 {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let msg = if let Some(msg) = panic.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = panic.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "unknown panic".to_string()
+        };
+        Err(NetsaurError::Internal(msg))
+    });
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            set_last_error(&error);
+            error.code()
+        }
+    }
+}
+
+fn write_shape(size: &[usize], alloc: AllocBufferFn) {
+    let buf: Vec<u8> = size
         .iter()
         .map(|x| *x as u32)
         .flat_map(|x| x.to_le_bytes().to_vec())
@@ -32,14 +53,41 @@ pub extern "C" fn ffi_backend_create(ptr: *const u8, len: usize, alloc: AllocBuf
     let size_ptr = alloc(buf.len());
     let output_shape = unsafe { from_raw_parts_mut(size_ptr, buf.len()) };
     output_shape.copy_from_slice(buf.as_slice());
+}
 
-    let mut len = 0;
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        len = backend.len();
-        backend.push(net_backend);
-    });
-    len
+#[no_mangle]
+pub extern "C" fn ffi_backend_error(alloc: AllocBufferFn) {
+    let msg = take_last_error().unwrap_or_default();
+    let buf = alloc(msg.len());
+    let buf = unsafe { from_raw_parts_mut(buf, msg.len()) };
+    buf.copy_from_slice(msg.as_bytes());
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_create(
+    ptr: *const u8,
+    len: usize,
+    alloc: AllocBufferFn,
+    id_ptr: *mut usize,
+) -> i32 {
+    status(|| {
+        let config = decode_json(ptr, len)?;
+        validate(&config)?;
+        let net_backend = Backend::new(config, Logger { log }, Timer { now }, None);
+        write_shape(&net_backend.size, alloc);
+        unsafe { *id_ptr = insert_backend(net_backend) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_validate(ptr: *const u8, len: usize, alloc: AllocBufferFn) -> i32 {
+    status(|| {
+        let config: BackendConfig = decode_json(ptr, len)?;
+        let shapes = validate(&config)?;
+        write_shape(shapes.last().unwrap_or(&config.size), alloc);
+        Ok(())
+    })
 }
 
 #[no_mangle]
@@ -49,24 +97,84 @@ pub extern "C" fn ffi_backend_train(
     buffer_len: usize,
     options_ptr: *const u8,
     options_len: usize,
-) {
-    let buffer = unsafe { from_raw_parts(buffer_ptr, buffer_len) };
-    let options: TrainOptions = decode_json(options_ptr, options_len);
-
-    let mut datasets = Vec::new();
-    for i in 0..options.datasets {
-        let input = buffer[i * 2];
-        let output = buffer[i * 2 + 1];
-        datasets.push(Dataset {
-            inputs: decode_array(input as *const f32, options.input_shape.clone()),
-            outputs: decode_array(output as *const f32, options.output_shape.clone()),
-        });
-    }
+) -> i32 {
+    status(|| {
+        let buffer = unsafe { from_raw_parts(buffer_ptr, buffer_len) };
+        let options: TrainOptions = decode_json(options_ptr, options_len)?;
+        let validation_datasets = options.validation_datasets.unwrap_or(0);
+        if buffer.len() < (options.datasets + validation_datasets) * 2 {
+            return Err(NetsaurError::ShapeMismatch {
+                msg: "missing dataset buffers",
+                expected: vec![(options.datasets + validation_datasets) * 2],
+                got: vec![buffer.len()],
+            });
+        }
 
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        backend[id].train(datasets, options.epochs, options.batches, options.rate)
-    });
+        let decode = |i: usize, input_shape: &Vec<usize>, output_shape: &Vec<usize>| {
+            Ok(Dataset {
+                inputs: decode_array(buffer[i * 2] as *const f32, input_shape.clone())?,
+                outputs: decode_array(buffer[i * 2 + 1] as *const f32, output_shape.clone())?,
+            })
+        };
+        let datasets = (0..options.datasets)
+            .map(|i| decode(i, &options.input_shape, &options.output_shape))
+            .collect::<NetsaurResult<Vec<_>>>()?;
+        let validation_input_shape = options
+            .validation_input_shape
+            .as_ref()
+            .unwrap_or(&options.input_shape);
+        let validation_output_shape = options
+            .validation_output_shape
+            .as_ref()
+            .unwrap_or(&options.output_shape);
+        let validation = (options.datasets..options.datasets + validation_datasets)
+            .map(|i| decode(i, validation_input_shape, validation_output_shape))
+            .collect::<NetsaurResult<Vec<_>>>()?;
+
+        with_backend(id, |backend| backend.train(datasets, validation, &options))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_evaluate(
+    id: usize,
+    buffer_ptr: *const u64,
+    buffer_len: usize,
+    options_ptr: *const u8,
+    options_len: usize,
+    alloc: AllocBufferFn,
+) -> i32 {
+    status(|| {
+        let buffer = unsafe { from_raw_parts(buffer_ptr, buffer_len) };
+        let options: EvaluateOptions = decode_json(options_ptr, options_len)?;
+        if buffer.len() < options.datasets * 2 {
+            return Err(NetsaurError::ShapeMismatch {
+                msg: "missing dataset buffers",
+                expected: vec![options.datasets * 2],
+                got: vec![buffer.len()],
+            });
+        }
+        let datasets = (0..options.datasets)
+            .map(|i| {
+                Ok(Dataset {
+                    inputs: decode_array(buffer[i * 2] as *const f32, options.input_shape.clone())?,
+                    outputs: decode_array(
+                        buffer[i * 2 + 1] as *const f32,
+                        options.output_shape.clone(),
+                    )?,
+                })
+            })
+            .collect::<NetsaurResult<Vec<_>>>()?;
+
+        with_backend(id, |backend| {
+            let report = backend.evaluate(&datasets, &options.metrics)?;
+            let report = serde_json::to_string(&report)?;
+            let buf_ptr = alloc(report.len());
+            let buf = unsafe { from_raw_parts_mut(buf_ptr, report.len()) };
+            buf.copy_from_slice(report.as_bytes());
+            Ok(())
+        })
+    })
 }
 
 #[no_mangle]
@@ -76,27 +184,124 @@ pub extern "C" fn ffi_backend_predict(
     options_ptr: *const u8,
     options_len: usize,
     output_ptr: *mut f32,
-) {
-    let options: PredictOptions = decode_json(options_ptr, options_len);
-    let inputs = decode_array(buffer_ptr, options.input_shape);
-    let outputs = unsafe { from_raw_parts_mut(output_ptr, length(options.output_shape)) };
-
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        let res = backend[id].predict(inputs, options.post_process, options.layers);
-        outputs.copy_from_slice(res.as_slice().unwrap());
-    });
+) -> i32 {
+    status(|| {
+        let options: PredictOptions = decode_json(options_ptr, options_len)?;
+        let inputs = decode_array(buffer_ptr, options.input_shape)?;
+        let output_shape = options.output_shape.clone();
+        let outputs = unsafe { from_raw_parts_mut(output_ptr, length(options.output_shape)) };
+
+        with_backend_ref(id, |backend| {
+            let res = backend.predict(inputs, options.post_process, options.layers)?;
+            if res.len() != outputs.len() {
+                return Err(NetsaurError::ShapeMismatch {
+                    msg: "invalid output shape",
+                    expected: output_shape,
+                    got: res.shape().to_vec(),
+                });
+            }
+            outputs
+                .iter_mut()
+                .zip(res.iter())
+                .for_each(|(output, x)| *output = *x);
+            Ok(())
+        })
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn ffi_backend_save(id: usize, alloc: AllocBufferFn) {
-    RESOURCES.with(|cell| {
-        let backend = cell.backend.borrow_mut();
-        let data = backend[id].save();
-        let file_ptr = alloc(data.len());
-        let file = unsafe { from_raw_parts_mut(file_ptr, data.len()) };
-        file.copy_from_slice(data.as_slice());
-    });
+pub extern "C" fn ffi_backend_save(id: usize, alloc: AllocBufferFn) -> i32 {
+    status(|| {
+        with_backend_ref(id, |backend| {
+            let data = backend.save();
+            let file_ptr = alloc(data.len());
+            let file = unsafe { from_raw_parts_mut(file_ptr, data.len()) };
+            file.copy_from_slice(data.as_slice());
+            Ok(())
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_export_onnx(id: usize, alloc: AllocBufferFn) -> i32 {
+    status(|| {
+        with_backend_ref(id, |backend| {
+            let data = backend.export_onnx()?;
+            let file_ptr = alloc(data.len());
+            let file = unsafe { from_raw_parts_mut(file_ptr, data.len()) };
+            file.copy_from_slice(data.as_slice());
+            Ok(())
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_checkpoint(id: usize, alloc: AllocBufferFn) -> i32 {
+    status(|| {
+        with_backend_ref(id, |backend| {
+            let data = backend.checkpoint();
+            let file_ptr = alloc(data.len());
+            let file = unsafe { from_raw_parts_mut(file_ptr, data.len()) };
+            file.copy_from_slice(data.as_slice());
+            Ok(())
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_free(id: usize) -> i32 {
+    status(|| remove_backend(id))
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_freeze(id: usize, start: usize, end: usize) -> i32 {
+    status(|| with_backend(id, |backend| backend.freeze(start..end)))
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_unfreeze(id: usize, start: usize, end: usize) -> i32 {
+    status(|| with_backend(id, |backend| backend.unfreeze(start..end)))
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_truncate(id: usize, len: usize, alloc: AllocBufferFn) -> i32 {
+    status(|| {
+        with_backend(id, |backend| {
+            backend.truncate(len)?;
+            write_shape(&backend.size, alloc);
+            Ok(())
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_append(
+    id: usize,
+    ptr: *const u8,
+    len: usize,
+    alloc: AllocBufferFn,
+) -> i32 {
+    status(|| {
+        let layers: Vec<LayerEntry> = decode_json(ptr, len)?;
+        with_backend(id, |backend| {
+            backend.append(layers)?;
+            write_shape(&backend.size, alloc);
+            Ok(())
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_summary(id: usize, alloc: AllocBufferFn) -> i32 {
+    status(|| {
+        with_backend_ref(id, |backend| {
+            let summary = serde_json::to_string(&backend.summary()?)?;
+            let buf_ptr = alloc(summary.len());
+            let buf = unsafe { from_raw_parts_mut(buf_ptr, summary.len()) };
+            buf.copy_from_slice(summary.as_bytes());
+            Ok(())
+        })
+    })
 }
 
 #[no_mangle]
@@ -104,19 +309,73 @@ pub extern "C" fn ffi_backend_load(
     file_ptr: *const u8,
     file_len: usize,
     alloc: AllocBufferFn,
-) -> usize {
-    let buffer = unsafe { from_raw_parts(file_ptr, file_len) };
-    let net_backend = Backend::load(buffer, Logger { log }, Timer { now });
-    let buf: Vec<u8> = net_backend.size.iter().map(|x| *x as u8).collect();
-    let size_ptr = alloc(buf.len());
-    let output_shape = unsafe { from_raw_parts_mut(size_ptr, buf.len()) };
-    output_shape.copy_from_slice(buf.as_slice());
+    id_ptr: *mut usize,
+) -> i32 {
+    status(|| {
+        let buffer = unsafe { from_raw_parts(file_ptr, file_len) };
+        let net_backend = Backend::load(buffer, Logger { log }, Timer { now })?;
+        write_shape(&net_backend.size, alloc);
+        unsafe { *id_ptr = insert_backend(net_backend) };
+        Ok(())
+    })
+}
 
-    let mut len = 0;
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        len = backend.len();
-        backend.push(net_backend);
-    });
-    len
+#[no_mangle]
+pub extern "C" fn ffi_backend_resume(
+    file_ptr: *const u8,
+    file_len: usize,
+    alloc: AllocBufferFn,
+    id_ptr: *mut usize,
+) -> i32 {
+    status(|| {
+        let buffer = unsafe { from_raw_parts(file_ptr, file_len) };
+        let net_backend = Backend::resume(buffer, Logger { log }, Timer { now })?;
+        write_shape(&net_backend.size, alloc);
+        unsafe { *id_ptr = insert_backend(net_backend) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_load_onnx(
+    file_ptr: *const u8,
+    file_len: usize,
+    alloc: AllocBufferFn,
+    id_ptr: *mut usize,
+) -> i32 {
+    status(|| {
+        let buffer = unsafe { from_raw_parts(file_ptr, file_len) };
+        let net_backend = Backend::load_onnx(buffer, Logger { log }, Timer { now })?;
+        write_shape(&net_backend.size, alloc);
+        unsafe { *id_ptr = insert_backend(net_backend) };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_backend_load_pretrained(
+    config_ptr: *const u8,
+    config_len: usize,
+    file_ptr: *const u8,
+    file_len: usize,
+    mappings_ptr: *const u8,
+    mappings_len: usize,
+    alloc: AllocBufferFn,
+    report_alloc: AllocBufferFn,
+    id_ptr: *mut usize,
+) -> i32 {
+    status(|| {
+        let config = decode_json(config_ptr, config_len)?;
+        let mappings: Vec<TensorMapping> = decode_json(mappings_ptr, mappings_len)?;
+        let buffer = unsafe { from_raw_parts(file_ptr, file_len) };
+        let (net_backend, report) =
+            Backend::load_pretrained(config, buffer, &mappings, Logger { log }, Timer { now })?;
+        let report = serde_json::to_string(&report)?;
+        let report_ptr = report_alloc(report.len());
+        let buf = unsafe { from_raw_parts_mut(report_ptr, report.len()) };
+        buf.copy_from_slice(report.as_bytes());
+        write_shape(&net_backend.size, alloc);
+        unsafe { *id_ptr = insert_backend(net_backend) };
+        Ok(())
+    })
 }
@@ -1,38 +1,151 @@
 mod cpu;
+mod error;
 #[cfg(not(target_arch = "wasm32"))]
 mod ffi;
+mod onnx;
 mod tensor;
 mod types;
 mod util;
+mod validate;
 #[cfg(target_arch = "wasm32")]
 mod wasm;
 
 pub use cpu::*;
-
-
+pub use error::*;
 #[cfg(not(target_arch = "wasm32"))]
 pub use ffi::*;
+pub use netsaur_registry::Registry;
+pub use onnx::*;
 pub use tensor::*;
 pub use types::*;
 pub use util::*;
+pub use validate::*;
 
 #[cfg(target_arch = "wasm32")]
 pub use wasm::*;
 
-use std::cell::RefCell;
+use std::sync::{Arc, PoisonError, RwLock};
 
+/// Backends shared by every thread of the process. Each backend sits behind
+/// its own lock, so training one model does not block predictions or
+/// registrations on another.
 pub struct Resources {
-    pub backend: RefCell<Vec<Backend>>,
+    pub backend: RwLock<Registry<Arc<RwLock<Backend>>>>,
 }
 
 impl Resources {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
-            backend: RefCell::new(Vec::new()),
+            backend: RwLock::new(Registry::new()),
         }
     }
 }
 
-thread_local! {
-    pub static RESOURCES: Resources = Resources::new();
+pub static RESOURCES: Resources = Resources::new();
+
+/// Registers a backend and returns its id.
+pub fn insert_backend(net_backend: Backend) -> usize {
+    let mut registry = RESOURCES
+        .backend
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    registry.insert(Arc::new(RwLock::new(net_backend)))
+}
+
+fn get_backend(id: usize) -> NetsaurResult<Arc<RwLock<Backend>>> {
+    let registry = RESOURCES
+        .backend
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    registry.get(id).cloned().ok_or(NetsaurError::UnknownBackend(id))
+}
+
+/// Runs `f` with exclusive access to the backend registered under `id`.
+pub fn with_backend<T>(
+    id: usize,
+    f: impl FnOnce(&mut Backend) -> NetsaurResult<T>,
+) -> NetsaurResult<T> {
+    let backend = get_backend(id)?;
+    let mut backend = backend.write().unwrap_or_else(PoisonError::into_inner);
+    f(&mut backend)
+}
+
+/// Runs `f` with shared access to the backend registered under `id`, any
+/// number of readers may hold the same backend concurrently.
+pub fn with_backend_ref<T>(
+    id: usize,
+    f: impl FnOnce(&Backend) -> NetsaurResult<T>,
+) -> NetsaurResult<T> {
+    let backend = get_backend(id)?;
+    let backend = backend.read().unwrap_or_else(PoisonError::into_inner);
+    f(&backend)
+}
+
+/// Drops the backend registered under `id`, invalidating the id. Calls that
+/// are still running against it finish before it is freed.
+pub fn remove_backend(id: usize) -> NetsaurResult<()> {
+    let mut registry = RESOURCES
+        .backend
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    registry
+        .remove(id)
+        .map(drop)
+        .ok_or(NetsaurError::UnknownBackend(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use ndarray::ArrayD;
+
+    use super::*;
+
+    fn backend() -> Backend {
+        let config = r#"{
+            "size": [1, 2],
+            "layers": [{ "type": "dense", "config": { "size": [1] } }],
+            "cost": "mse",
+            "optimizer": { "type": "sgd" },
+            "scheduler": { "type": "none" }
+        }"#;
+        Backend::new(
+            serde_json::from_str(config).unwrap(),
+            Logger { log: |_| {} },
+            Timer { now: || 0 },
+            None,
+        )
+    }
+
+    fn predict(id: usize) -> NetsaurResult<ArrayD<f32>> {
+        with_backend_ref(id, |backend| {
+            backend.predict(ArrayD::ones(vec![1, 2]), PostProcessor::None, None)
+        })
+    }
+
+    #[test]
+    fn backends_are_shared_across_threads() {
+        let shared = insert_backend(backend());
+        let expected = predict(shared).unwrap();
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                thread::spawn(move || {
+                    let own = insert_backend(backend());
+                    predict(own).unwrap();
+                    remove_backend(own).unwrap();
+                    assert!(matches!(predict(own), Err(NetsaurError::UnknownBackend(_))));
+                    predict(shared).unwrap()
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), expected);
+        }
+        remove_backend(shared).unwrap();
+        assert!(matches!(
+            remove_backend(shared),
+            Err(NetsaurError::UnknownBackend(_))
+        ));
+    }
 }
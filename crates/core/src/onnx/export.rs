@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+
+use ndarray::{ArrayViewD, Axis};
+use prost::Message;
+
+use super::proto::{
+    attribute_type, data_type, tensor_shape_proto::dimension, tensor_shape_proto::Dimension,
+    type_proto, AttributeProto, GraphProto, ModelProto, NodeProto, OperatorSetIdProto, TensorProto,
+    TensorShapeProto, TypeProto, ValueInfoProto, IR_VERSION, OPSET_VERSION,
+};
+use crate::{validate, Activation, Backend, Layer, LayerParam, NetsaurError, NetsaurResult};
+
+/// Order of the LSTM gates in ONNX (input, output, forget, cell) as indices
+/// into netsaur's (input, forget, output, cell) order.
+pub(crate) const LSTM_GATES: [usize; 4] = [0, 2, 1, 3];
+
+/// ONNX orders the GRU gates (update, reset, candidate) like netsaur.
+pub(crate) const GRU_GATES: [usize; 3] = [0, 1, 2];
+
+fn int(name: &str, value: i64) -> AttributeProto {
+    AttributeProto {
+        name: name.to_string(),
+        r#type: attribute_type::INT,
+        i: value,
+        ..Default::default()
+    }
+}
+
+fn ints(name: &str, values: &[usize]) -> AttributeProto {
+    AttributeProto {
+        name: name.to_string(),
+        r#type: attribute_type::INTS,
+        ints: values.iter().map(|x| *x as i64).collect(),
+        ..Default::default()
+    }
+}
+
+fn float(name: &str, value: f32) -> AttributeProto {
+    AttributeProto {
+        name: name.to_string(),
+        r#type: attribute_type::FLOAT,
+        f: value,
+        ..Default::default()
+    }
+}
+
+fn strings(name: &str, values: &[&str]) -> AttributeProto {
+    AttributeProto {
+        name: name.to_string(),
+        r#type: attribute_type::STRINGS,
+        strings: values.iter().map(|x| x.as_bytes().to_vec()).collect(),
+        ..Default::default()
+    }
+}
+
+fn value_info(name: &str, shape: &[usize]) -> ValueInfoProto {
+    let dim = shape
+        .iter()
+        .enumerate()
+        .map(|(i, size)| Dimension {
+            value: Some(if i == 0 {
+                dimension::Value::DimParam("batch".to_string())
+            } else {
+                dimension::Value::DimValue(*size as i64)
+            }),
+        })
+        .collect();
+    ValueInfoProto {
+        name: name.to_string(),
+        r#type: Some(TypeProto {
+            value: Some(type_proto::Value::TensorType(type_proto::Tensor {
+                elem_type: data_type::FLOAT,
+                shape: Some(TensorShapeProto { dim }),
+            })),
+        }),
+    }
+}
+
+/// Name of an activation inside an ONNX recurrent node.
+fn recurrent_activation(activation: &Activation) -> NetsaurResult<&'static str> {
+    match activation {
+        Activation::Sigmoid => Ok("Sigmoid"),
+        Activation::Tanh => Ok("Tanh"),
+        Activation::Relu => Ok("Relu"),
+        Activation::Elu => Ok("Elu"),
+        Activation::LeakyRelu => Ok("LeakyRelu"),
+        activation => Err(NetsaurError::Onnx(format!(
+            "{:?} is not available as a recurrent activation",
+            activation
+        ))),
+    }
+}
+
+/// Collects the nodes and initializers of the graph as the layers are
+/// visited.
+struct Graph<'a> {
+    tensors: HashMap<String, ArrayViewD<'a, f32>>,
+    nodes: Vec<NodeProto>,
+    initializers: Vec<TensorProto>,
+}
+
+impl<'a> Graph<'a> {
+    fn tensor(&self, key: &str) -> NetsaurResult<ArrayViewD<'a, f32>> {
+        self.tensors
+            .get(key)
+            .cloned()
+            .ok_or(NetsaurError::Internal(format!("missing tensor {}", key)))
+    }
+
+    fn initializer(&mut self, name: String, shape: &[usize], data: Vec<f32>) -> String {
+        self.initializers.push(TensorProto {
+            dims: shape.iter().map(|x| *x as i64).collect(),
+            data_type: data_type::FLOAT,
+            float_data: data,
+            name: name.clone(),
+            ..Default::default()
+        });
+        name
+    }
+
+    /// Adds a saved tensor under its save key.
+    fn weight(&mut self, key: &str) -> NetsaurResult<String> {
+        let tensor = self.tensor(key)?;
+        let (shape, data) = (tensor.shape().to_vec(), tensor.iter().cloned().collect());
+        Ok(self.initializer(key.to_string(), &shape, data))
+    }
+
+    /// Adds a saved tensor flattened to one dimension.
+    fn vector(&mut self, key: &str) -> NetsaurResult<String> {
+        let data: Vec<f32> = self.tensor(key)?.iter().cloned().collect();
+        Ok(self.initializer(key.to_string(), &[data.len()], data))
+    }
+
+    fn scalar(&mut self, name: String, value: f32) -> String {
+        self.initializer(name, &[], vec![value])
+    }
+
+    fn int64s(&mut self, name: String, values: &[i64]) -> String {
+        self.initializers.push(TensorProto {
+            dims: vec![values.len() as i64],
+            data_type: data_type::INT64,
+            int64_data: values.to_vec(),
+            name: name.clone(),
+            ..Default::default()
+        });
+        name
+    }
+
+    fn node(
+        &mut self,
+        op_type: &str,
+        inputs: Vec<String>,
+        outputs: Vec<String>,
+        attribute: Vec<AttributeProto>,
+    ) {
+        self.nodes.push(NodeProto {
+            input: inputs,
+            name: outputs
+                .iter()
+                .find(|x| !x.is_empty())
+                .cloned()
+                .unwrap_or_default(),
+            output: outputs,
+            op_type: op_type.to_string(),
+            attribute,
+            ..Default::default()
+        });
+    }
+
+    /// Adds an `LSTM` or `GRU` node. The recurrence bias of the ONNX op
+    /// stays zero since netsaur keeps one bias per gate.
+    fn recurrent(
+        &mut self,
+        op: &str,
+        activations: &[&str],
+        name: &str,
+        x: String,
+        y: &str,
+        return_sequences: bool,
+    ) -> NetsaurResult<()> {
+        let w_ih = self.tensor(&LayerParam::WIh.key(name))?;
+        let w_hh = self.tensor(&LayerParam::WHh.key(name))?;
+        let biases = self.tensor(&LayerParam::Bias.key(name))?;
+        let gates: &[usize] = match op {
+            "LSTM" => &LSTM_GATES,
+            _ => &GRU_GATES,
+        };
+        let (input_size, hidden) = (w_ih.shape()[1], w_ih.shape()[2]);
+        let stacked = |weights: &ArrayViewD<f32>| -> Vec<f32> {
+            gates
+                .iter()
+                .flat_map(|gate| weights.index_axis(Axis(0), *gate).t().to_owned())
+                .collect()
+        };
+        let (w_data, r_data) = (stacked(&w_ih), stacked(&w_hh));
+        let mut b_data: Vec<f32> = gates
+            .iter()
+            .flat_map(|gate| biases.index_axis(Axis(0), *gate).to_owned())
+            .collect();
+        let size = gates.len() * hidden;
+        b_data.resize(2 * size, 0.0);
+        let w = self.initializer(LayerParam::WIh.key(name), &[1, size, input_size], w_data);
+        let r = self.initializer(LayerParam::WHh.key(name), &[1, size, hidden], r_data);
+        let b = self.initializer(LayerParam::Bias.key(name), &[1, 2 * size], b_data);
+
+        let attributes = vec![
+            int("hidden_size", hidden as i64),
+            int("layout", 1),
+            strings("activations", activations),
+        ];
+        // layout 1 keeps the batch first, with a direction axis to
+        // squeeze out: Y is [batch, seq, 1, hidden], Y_h [batch, 1, hidden]
+        let (outputs, axis) = if return_sequences {
+            (vec![format!("{}/Y", y)], 2)
+        } else {
+            (vec![String::new(), format!("{}/Y_h", y)], 1)
+        };
+        let state = outputs[outputs.len() - 1].clone();
+        self.node(op, vec![x, w, r, b], outputs, attributes);
+        let axes = self.int64s(format!("{}/axes", y), &[axis]);
+        self.node("Squeeze", vec![state, axes], vec![y.to_string()], vec![]);
+        Ok(())
+    }
+
+    fn activation(&mut self, activation: &Activation, x: String, y: String) {
+        match activation {
+            Activation::Elu => self.node("Elu", vec![x], vec![y], vec![float("alpha", 1.0)]),
+            Activation::LeakyRelu => {
+                self.node("LeakyRelu", vec![x], vec![y], vec![float("alpha", 0.01)])
+            }
+            Activation::Linear => self.node("Identity", vec![x], vec![y], vec![]),
+            Activation::Relu => self.node("Relu", vec![x], vec![y], vec![]),
+            Activation::Relu6 => {
+                let min = self.scalar(format!("{}/min", y), 0.0);
+                let max = self.scalar(format!("{}/max", y), 6.0);
+                self.node("Clip", vec![x, min, max], vec![y], vec![])
+            }
+            // netsaur's selu only scales the negative side
+            Activation::Selu => self.node("Elu", vec![x], vec![y], vec![float("alpha", 1.0507)]),
+            Activation::Gelu => {
+                // 0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))
+                let half = self.scalar(format!("{}/half", y), 0.5);
+                let one = self.scalar(format!("{}/one", y), 1.0);
+                let cube = self.scalar(format!("{}/cube", y), 0.044715);
+                let root = self.scalar(format!("{}/root", y), 0.797_884_6);
+                let names = [
+                    "x2", "x3", "cubed", "inner", "scaled", "tanh", "shifted", "half_x",
+                ]
+                .map(|name| format!("{}/{}", y, name));
+                let [x2, x3, cubed, inner, scaled, tanh, shifted, half_x] = names;
+                self.node("Mul", vec![x.clone(), x.clone()], vec![x2.clone()], vec![]);
+                self.node("Mul", vec![x2, x.clone()], vec![x3.clone()], vec![]);
+                self.node("Mul", vec![x3, cube], vec![cubed.clone()], vec![]);
+                self.node("Add", vec![x.clone(), cubed], vec![inner.clone()], vec![]);
+                self.node("Mul", vec![inner, root], vec![scaled.clone()], vec![]);
+                self.node("Tanh", vec![scaled], vec![tanh.clone()], vec![]);
+                self.node("Add", vec![tanh, one], vec![shifted.clone()], vec![]);
+                self.node("Mul", vec![x, half], vec![half_x.clone()], vec![]);
+                self.node("Mul", vec![half_x, shifted], vec![y], vec![]);
+            }
+            Activation::Sigmoid => self.node("Sigmoid", vec![x], vec![y], vec![]),
+            Activation::Tanh => self.node("Tanh", vec![x], vec![y], vec![]),
+        }
+    }
+}
+
+impl Backend {
+    /// Exports the model as an ONNX graph (opset 17) that runs the same
+    /// inference as `Backend::predict`. The weights are the tensors written
+    /// by `Backend::save`, stored under the same keys.
+    pub fn export_onnx(&self) -> NetsaurResult<Vec<u8>> {
+        let shapes = validate(&self.config)?;
+        let tensors = self.tensors();
+        let mut graph = Graph {
+            tensors: tensors
+                .iter()
+                .map(|(key, tensor)| (key.clone(), tensor.data.view()))
+                .collect(),
+            nodes: Vec::new(),
+            initializers: Vec::new(),
+        };
+
+        let mut x = "input".to_string();
+        let mut input_shape = self.config.size.clone();
+        let names = self.config.layer_names();
+        let layers = self.config.layers.iter().map(|entry| &entry.layer);
+        for (i, (layer, output_shape)) in layers.zip(&shapes).enumerate() {
+            let name = &names[i];
+            let y = if i + 1 == shapes.len() {
+                "output".to_string()
+            } else {
+                format!("layer{}", i)
+            };
+            match layer {
+                Layer::Activation(config) => graph.activation(&config.activation, x, y.clone()),
+                Layer::Dense(_) => {
+                    let w = graph.weight(&LayerParam::Weight.key(name))?;
+                    let b = graph.weight(&LayerParam::Bias.key(name))?;
+                    graph.node("Gemm", vec![x, w, b], vec![y.clone()], vec![]);
+                }
+                Layer::Conv2D(config) => {
+                    let w = graph.weight(&LayerParam::Weight.key(name))?;
+                    let b = graph.weight(&LayerParam::Bias.key(name))?;
+                    let padding = config.padding.clone().unwrap_or(vec![0, 0]);
+                    let attributes = vec![
+                        ints("kernel_shape", &config.kernel_size[2..]),
+                        ints("pads", &[padding[0], padding[1], padding[0], padding[1]]),
+                        ints("strides", &config.strides.clone().unwrap_or(vec![1, 1])),
+                    ];
+                    graph.node("Conv", vec![x, w, b], vec![y.clone()], attributes);
+                }
+                Layer::ConvTranspose2D(config) => {
+                    // ONNX keeps the input channels first
+                    let key = LayerParam::Weight.key(name);
+                    let weights = graph.tensor(&key)?.permuted_axes(vec![1, 0, 2, 3]);
+                    let shape = weights.shape().to_vec();
+                    let data = weights.iter().cloned().collect();
+                    let w = graph.initializer(key, &shape, data);
+                    let b = graph.weight(&LayerParam::Bias.key(name))?;
+                    let padding = config.padding.clone().unwrap_or(vec![0, 0]);
+                    let strides = config.strides.clone().unwrap_or(vec![1, 1]);
+                    // netsaur pads the input and drops kernel - 1 from each
+                    // side of the output, ONNX only crops the output
+                    let pads = (0..2)
+                        .map(|i| config.kernel_size[2 + i].checked_sub(padding[i] * strides[i] + 1))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(NetsaurError::Onnx(format!(
+                            "layer {} pads its input by more than its kernel overlaps",
+                            name
+                        )))?;
+                    let attributes = vec![
+                        ints("kernel_shape", &config.kernel_size[2..]),
+                        ints("pads", &[pads[0], pads[1], pads[0], pads[1]]),
+                        ints("strides", &strides),
+                    ];
+                    graph.node("ConvTranspose", vec![x, w, b], vec![y.clone()], attributes);
+                }
+                Layer::BatchNorm1D(config) | Layer::BatchNorm2D(config) => {
+                    let inputs = layer
+                        .params()
+                        .iter()
+                        .map(|param| graph.vector(&param.key(name)))
+                        .collect::<NetsaurResult<Vec<_>>>()?;
+                    let attributes = vec![
+                        float("epsilon", config.epsilon),
+                        float("momentum", config.momentum),
+                    ];
+                    let inputs = [vec![x], inputs].concat();
+                    graph.node("BatchNormalization", inputs, vec![y.clone()], attributes);
+                }
+                Layer::Pool2D(config) => {
+                    let strides = config.strides.clone().unwrap_or(vec![1, 1]);
+                    let op = if config.mode == 1 {
+                        "MaxPool"
+                    } else {
+                        "AveragePool"
+                    };
+                    let attributes =
+                        vec![ints("kernel_shape", &strides), ints("strides", &strides)];
+                    graph.node(op, vec![x], vec![y.clone()], attributes);
+                }
+                Layer::Embedding(_) => {
+                    let indices = format!("{}/indices", y);
+                    let cast = vec![int("to", data_type::INT64 as i64)];
+                    graph.node("Cast", vec![x], vec![indices.clone()], cast);
+                    let e = graph.weight(&LayerParam::Embeddings.key(name))?;
+                    let attributes = vec![int("axis", 0)];
+                    graph.node("Gather", vec![e, indices], vec![y.clone()], attributes);
+                }
+                Layer::Flatten => {
+                    graph.node("Flatten", vec![x], vec![y.clone()], vec![int("axis", 1)])
+                }
+                Layer::LSTM(config) => {
+                    let gate = recurrent_activation(
+                        config
+                            .recurrent_activation
+                            .as_ref()
+                            .unwrap_or(&Activation::Sigmoid),
+                    )?;
+                    let cell = recurrent_activation(
+                        config.activation.as_ref().unwrap_or(&Activation::Tanh),
+                    )?;
+                    let sequences = config.return_sequences.unwrap_or(false);
+                    graph.recurrent("LSTM", &[gate, cell, cell], name, x, &y, sequences)?;
+                }
+                Layer::GRU(config) => {
+                    let gate = recurrent_activation(
+                        config
+                            .recurrent_activation
+                            .as_ref()
+                            .unwrap_or(&Activation::Sigmoid),
+                    )?;
+                    let candidate = recurrent_activation(
+                        config.activation.as_ref().unwrap_or(&Activation::Tanh),
+                    )?;
+                    let sequences = config.return_sequences.unwrap_or(false);
+                    graph.recurrent("GRU", &[gate, candidate], name, x, &y, sequences)?;
+                }
+                Layer::Dropout1D(config) | Layer::Dropout2D(config) => {
+                    let ratio = graph.scalar(format!("{}/ratio", y), config.probability);
+                    graph.node("Dropout", vec![x, ratio], vec![y.clone()], vec![]);
+                }
+                Layer::Softmax(config) => {
+                    let temperature = config.temperature.unwrap_or(1.0);
+                    if temperature != 1.0 {
+                        let scaled = format!("{}/scaled", y);
+                        let t = graph.scalar(format!("{}/temperature", y), temperature);
+                        graph.node("Div", vec![x, t], vec![scaled.clone()], vec![]);
+                        x = scaled;
+                    }
+                    graph.node("Softmax", vec![x], vec![y.clone()], vec![int("axis", -1)]);
+                }
+            }
+            x = y;
+            input_shape = output_shape.clone();
+        }
+        if shapes.is_empty() {
+            graph.node("Identity", vec![x], vec!["output".to_string()], vec![]);
+        }
+
+        let model = ModelProto {
+            ir_version: IR_VERSION,
+            opset_import: vec![OperatorSetIdProto {
+                domain: String::new(),
+                version: OPSET_VERSION,
+            }],
+            producer_name: "netsaur".to_string(),
+            producer_version: env!("CARGO_PKG_VERSION").to_string(),
+            graph: Some(GraphProto {
+                node: graph.nodes,
+                name: "netsaur".to_string(),
+                initializer: graph.initializers,
+                input: vec![value_info("input", &self.config.size)],
+                output: vec![value_info("output", &input_shape)],
+            }),
+        };
+        Ok(model.encode_to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array, ArrayD, IxDyn};
+
+    use crate::{Backend, Logger, PostProcessor, Timer};
+
+    const CONVOLUTIONAL: &str = r#"{
+        "size": [2, 2, 6, 6],
+        "seed": 3,
+        "layers": [
+            { "type": "conv2d", "config": { "kernelSize": [3, 2, 3, 3], "padding": [1, 1] } },
+            { "type": "batchnorm2d", "config": { "momentum": 0.9, "epsilon": 0.001 } },
+            { "type": "activation", "config": { "activation": "relu" } },
+            {
+                "type": "convtranspose2d",
+                "config": { "kernelSize": [2, 3, 3, 3], "strides": [2, 2], "padding": [1, 1] }
+            },
+            { "type": "conv2d", "config": { "kernelSize": [2, 2, 3, 3], "strides": [2, 2] } },
+            { "type": "pool2d", "config": { "mode": 1, "strides": [2, 2] } },
+            { "type": "flatten" },
+            { "type": "dense", "config": { "size": [3] } },
+            { "type": "softmax", "config": { "temperature": 2.0 } }
+        ],
+        "cost": "mse",
+        "optimizer": { "type": "sgd" },
+        "scheduler": { "type": "none" }
+    }"#;
+
+    const RECURRENT: &str = r#"{
+        "size": [2, 3],
+        "seed": 4,
+        "layers": [
+            { "type": "embedding", "config": { "vocabSize": 10, "embeddingSize": 4 } },
+            { "type": "gru", "config": { "size": 5, "returnSequences": true } },
+            { "type": "lstm", "config": { "size": 3, "activation": "relu" } },
+            { "type": "dense", "config": { "size": [2] } }
+        ],
+        "cost": "mse",
+        "optimizer": { "type": "sgd" },
+        "scheduler": { "type": "none" }
+    }"#;
+
+    fn logger() -> Logger {
+        Logger { log: |_| {} }
+    }
+
+    fn timer() -> Timer {
+        Timer { now: || 0 }
+    }
+
+    fn roundtrip(config: &str, inputs: ArrayD<f32>) {
+        let backend = Backend::new(
+            serde_json::from_str(config).unwrap(),
+            logger(),
+            timer(),
+            None,
+        );
+        let bytes = backend.export_onnx().unwrap();
+        let imported = Backend::load_onnx(&bytes, logger(), timer()).unwrap();
+        assert_eq!(imported.config.layers.len(), backend.config.layers.len());
+
+        let expected = backend.predict(inputs.clone(), PostProcessor::None, None);
+        let outputs = imported.predict(inputs, PostProcessor::None, None);
+        let error = (&outputs.unwrap() - &expected.unwrap())
+            .iter()
+            .fold(0f32, |max, x| max.max(x.abs()));
+        assert!(error < 1e-5, "{}", error);
+    }
+
+    #[test]
+    fn roundtrips_convolutional_layers() {
+        let inputs = Array::range(0.0, 144.0, 1.0)
+            .mapv(|x: f32| (x * 0.7).sin())
+            .into_shape_with_order(IxDyn(&[2, 2, 6, 6]))
+            .unwrap();
+        roundtrip(CONVOLUTIONAL, inputs);
+    }
+
+    #[test]
+    fn roundtrips_recurrent_layers() {
+        let inputs = Array::range(0.0, 6.0, 1.0)
+            .mapv(|x: f32| (x * 3.0) % 10.0)
+            .into_shape_with_order(IxDyn(&[2, 3]))
+            .unwrap();
+        roundtrip(RECURRENT, inputs);
+    }
+}
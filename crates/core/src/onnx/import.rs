@@ -0,0 +1,909 @@
+use std::collections::{HashMap, HashSet};
+
+use ndarray::{s, Array1, Array3, ArrayD, Axis, Ix2, Ix4};
+use prost::Message;
+
+use super::export::{GRU_GATES, LSTM_GATES};
+use super::proto::{
+    data_type, tensor_shape_proto::dimension, type_proto, AttributeProto, ModelProto, NodeProto,
+    TensorProto,
+};
+use crate::{
+    validate, validate_tensors, Activation, ActivationLayer, Backend, BackendConfig,
+    BatchNormLayer, BatchNormTensors, Conv2DLayer, ConvTensors, ConvTranspose2DLayer, Cost,
+    DenseLayer, DenseTensors, DropoutLayer, EmbeddingLayer, EmbeddingTensors, GRULayer, LSTMLayer,
+    Layer, LayerEntry, Logger, NetsaurError, NetsaurResult, Optimizer, Pool2DLayer,
+    RecurrentTensors, Scheduler, SoftmaxLayer, Tensors, Timer,
+};
+
+fn attribute<'a>(node: &'a NodeProto, name: &str) -> Option<&'a AttributeProto> {
+    node.attribute
+        .iter()
+        .find(|attribute| attribute.name == name)
+}
+
+fn int(node: &NodeProto, name: &str, default: i64) -> i64 {
+    attribute(node, name).map_or(default, |attribute| attribute.i)
+}
+
+fn ints(node: &NodeProto, name: &str) -> Option<Vec<i64>> {
+    attribute(node, name).map(|attribute| attribute.ints.clone())
+}
+
+fn float(node: &NodeProto, name: &str, default: f32) -> f32 {
+    attribute(node, name).map_or(default, |attribute| attribute.f)
+}
+
+fn string(node: &NodeProto, name: &str) -> Option<String> {
+    attribute(node, name).map(|attribute| String::from_utf8_lossy(&attribute.s).to_string())
+}
+
+fn unsupported(node: &NodeProto, reason: &str) -> NetsaurError {
+    NetsaurError::Onnx(format!(
+        "unsupported {} node {:?}: {}",
+        node.op_type, node.name, reason
+    ))
+}
+
+/// Reads a tensor as f32, converting from f64 and integer types.
+fn floats(tensor: &TensorProto) -> NetsaurResult<ArrayD<f32>> {
+    let shape: Vec<usize> = tensor.dims.iter().map(|x| *x as usize).collect();
+    let raw = &tensor.raw_data;
+    let data: Vec<f32> = match tensor.data_type {
+        data_type::FLOAT if !raw.is_empty() => raw
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+            .collect(),
+        data_type::FLOAT => tensor.float_data.clone(),
+        data_type::DOUBLE if !raw.is_empty() => raw
+            .chunks_exact(8)
+            .map(|x| f64::from_le_bytes(x.try_into().unwrap()) as f32)
+            .collect(),
+        data_type::DOUBLE => tensor.double_data.iter().map(|x| *x as f32).collect(),
+        _ => integers(tensor)?.iter().map(|x| *x as f32).collect(),
+    };
+    Ok(ArrayD::from_shape_vec(shape, data)?)
+}
+
+fn integers(tensor: &TensorProto) -> NetsaurResult<Vec<i64>> {
+    let raw = &tensor.raw_data;
+    match tensor.data_type {
+        data_type::INT64 if !raw.is_empty() => Ok(raw
+            .chunks_exact(8)
+            .map(|x| i64::from_le_bytes(x.try_into().unwrap()))
+            .collect()),
+        data_type::INT64 => Ok(tensor.int64_data.clone()),
+        data_type::INT32 if !raw.is_empty() => Ok(raw
+            .chunks_exact(4)
+            .map(|x| i32::from_le_bytes(x.try_into().unwrap()) as i64)
+            .collect()),
+        data_type::INT32 => Ok(tensor.int32_data.iter().map(|x| *x as i64).collect()),
+        other => Err(NetsaurError::Onnx(format!(
+            "tensor {:?} has unsupported data type {}",
+            tensor.name, other
+        ))),
+    }
+}
+
+/// Activation named inside an ONNX recurrent node.
+fn recurrent_activation(node: &NodeProto, name: &[u8]) -> NetsaurResult<Activation> {
+    match name {
+        b"Sigmoid" => Ok(Activation::Sigmoid),
+        b"Tanh" => Ok(Activation::Tanh),
+        b"Relu" => Ok(Activation::Relu),
+        b"Elu" => Ok(Activation::Elu),
+        b"LeakyRelu" => Ok(Activation::LeakyRelu),
+        name => Err(unsupported(
+            node,
+            &format!("{} activation", String::from_utf8_lossy(name)),
+        )),
+    }
+}
+
+/// Checks that begin and end paddings match and returns them per axis.
+fn symmetric_pads(node: &NodeProto) -> NetsaurResult<Vec<usize>> {
+    if !matches!(string(node, "auto_pad").as_deref(), None | Some("NOTSET")) {
+        return Err(unsupported(node, "auto_pad"));
+    }
+    let pads = ints(node, "pads").unwrap_or(vec![0; 4]);
+    if pads.len() != 4 || pads[0] != pads[2] || pads[1] != pads[3] {
+        return Err(unsupported(node, "asymmetric padding"));
+    }
+    Ok(vec![pads[0] as usize, pads[1] as usize])
+}
+
+fn strides(node: &NodeProto) -> Vec<usize> {
+    ints(node, "strides")
+        .unwrap_or(vec![1, 1])
+        .iter()
+        .map(|x| *x as usize)
+        .collect()
+}
+
+fn check_unit(node: &NodeProto, name: &str) -> NetsaurResult<()> {
+    match ints(node, name) {
+        Some(values) if values.iter().any(|x| *x != 1) => Err(unsupported(node, name)),
+        _ => Ok(()),
+    }
+}
+
+/// Turns a chain of ONNX nodes into netsaur layers, one node at a time.
+struct Importer {
+    constants: HashMap<String, TensorProto>,
+    consumed: HashSet<String>,
+    size: Vec<usize>,
+    layers: Vec<Layer>,
+    tensors: Vec<Tensors>,
+    /// Name of the value the next layer reads.
+    current: String,
+    /// Divisor of a `Div` waiting for the `Softmax` it scales.
+    temperature: Option<f32>,
+    /// Direction axis left on an `LSTM` or `GRU` output, removed by a `Squeeze`.
+    squeeze: Option<i64>,
+}
+
+impl Importer {
+    fn constant(&self, node: &NodeProto, index: usize) -> NetsaurResult<ArrayD<f32>> {
+        let tensor = node
+            .input
+            .get(index)
+            .and_then(|name| self.constants.get(name))
+            .ok_or(unsupported(
+                node,
+                &format!("input {} is not a constant", index),
+            ))?;
+        floats(tensor)
+    }
+
+    fn optional_constant(
+        &self,
+        node: &NodeProto,
+        index: usize,
+    ) -> NetsaurResult<Option<ArrayD<f32>>> {
+        match node.input.get(index) {
+            Some(name) if !name.is_empty() => self.constant(node, index).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn config(&self) -> BackendConfig {
+        BackendConfig {
+            silent: None,
+            size: self.size.clone(),
+            layers: self.layers.iter().cloned().map(LayerEntry::from).collect(),
+            cost: Cost::MSE,
+            optimizer: Optimizer::SGD(None),
+            scheduler: Scheduler::None,
+            tolerance: None,
+            patience: None,
+            monitor: None,
+            clipping: None,
+            seed: None,
+        }
+    }
+
+    /// Output shape of the layers imported so far.
+    fn shape(&self) -> NetsaurResult<Vec<usize>> {
+        Ok(validate(&self.config())?
+            .pop()
+            .unwrap_or_else(|| self.size.clone()))
+    }
+
+    fn push(&mut self, layer: Layer, tensors: Option<Tensors>) {
+        self.layers.push(layer);
+        self.tensors.extend(tensors);
+    }
+
+    fn activation(&mut self, activation: Activation) {
+        self.push(Layer::Activation(ActivationLayer { activation }), None);
+    }
+
+    fn node(&mut self, node: &NodeProto) -> NetsaurResult<()> {
+        if self.temperature.is_some() && node.op_type != "Softmax" {
+            return Err(unsupported(node, "Div is only supported before a Softmax"));
+        }
+        if self.squeeze.is_some() && node.op_type != "Squeeze" {
+            return Err(unsupported(
+                node,
+                "recurrent outputs must be squeezed first",
+            ));
+        }
+        let input = if node.op_type == "Gather" { 1 } else { 0 };
+        if node.input.get(input) != Some(&self.current) {
+            return Err(unsupported(node, "not part of the chain of layers"));
+        }
+        if node.output.is_empty() {
+            return Err(NetsaurError::Corrupt(format!(
+                "{} node {:?} has no outputs",
+                node.op_type, node.name
+            )));
+        }
+
+        match node.op_type.as_str() {
+            "Identity" | "Cast" => {}
+            "Relu" => self.activation(Activation::Relu),
+            "Sigmoid" => self.activation(Activation::Sigmoid),
+            "Tanh" => self.activation(Activation::Tanh),
+            "LeakyRelu" => match float(node, "alpha", 0.01) {
+                alpha if (alpha - 0.01).abs() < 1e-6 => self.activation(Activation::LeakyRelu),
+                _ => return Err(unsupported(node, "alpha other than 0.01")),
+            },
+            "Elu" => match float(node, "alpha", 1.0) {
+                alpha if (alpha - 1.0).abs() < 1e-6 => self.activation(Activation::Elu),
+                // netsaur's selu only scales the negative side
+                alpha if (alpha - 1.0507).abs() < 1e-6 => self.activation(Activation::Selu),
+                _ => return Err(unsupported(node, "alpha other than 1")),
+            },
+            "Clip" => {
+                let min = match self.optional_constant(node, 1)? {
+                    Some(min) => min.sum(),
+                    None => float(node, "min", f32::MIN),
+                };
+                let max = match self.optional_constant(node, 2)? {
+                    Some(max) => max.sum(),
+                    None => float(node, "max", f32::MAX),
+                };
+                if min != 0.0 || max != 6.0 {
+                    return Err(unsupported(node, "bounds other than [0, 6]"));
+                }
+                self.activation(Activation::Relu6);
+            }
+            "Gelu" => match string(node, "approximate").as_deref() {
+                Some("tanh") => self.activation(Activation::Gelu),
+                _ => {
+                    return Err(unsupported(
+                        node,
+                        "only the tanh approximation is supported",
+                    ))
+                }
+            },
+            "Div" => {
+                let divisor = self.constant(node, 1)?;
+                if divisor.len() != 1 {
+                    return Err(unsupported(node, "divisor is not a scalar"));
+                }
+                self.temperature = Some(divisor.sum());
+            }
+            "Softmax" => {
+                let rank = self.shape()?.len();
+                if rank != 2 || !matches!(int(node, "axis", -1), -1 | 1) {
+                    return Err(unsupported(node, "softmax over anything but the last axis"));
+                }
+                let temperature = self.temperature.take();
+                self.push(Layer::Softmax(SoftmaxLayer { temperature }), None);
+            }
+            "Dropout" => {
+                let probability = match self.optional_constant(node, 1)? {
+                    Some(ratio) => ratio.sum(),
+                    None => float(node, "ratio", 0.5),
+                };
+                let config = DropoutLayer {
+                    probability,
+                    inplace: None,
+                };
+                if self.shape()?.len() == 4 {
+                    self.push(Layer::Dropout2D(config), None);
+                } else {
+                    self.push(Layer::Dropout1D(config), None);
+                }
+            }
+            "Gemm" | "MatMul" => {
+                if int(node, "transA", 0) != 0 {
+                    return Err(unsupported(node, "transposed inputs"));
+                }
+                let weights = self.constant(node, 1)?.into_dimensionality::<Ix2>()?;
+                let weights = if int(node, "transB", 0) != 0 {
+                    weights.reversed_axes()
+                } else {
+                    weights
+                };
+                let weights = weights * float(node, "alpha", 1.0);
+                let size = weights.dim().1;
+                let mut biases = Array1::zeros(size);
+                if let Some(c) = self.optional_constant(node, 2)? {
+                    let len = c.len();
+                    let c = c.into_shape_with_order(len)?;
+                    let c = c.broadcast(size).ok_or(unsupported(node, "bias shape"))?;
+                    biases += &(&c * float(node, "beta", 1.0));
+                }
+                let config = DenseLayer {
+                    size: vec![size],
+                    init: None,
+                    c: None,
+                    l1_ratio: None,
+                };
+                let tensors = DenseTensors {
+                    weights: weights.as_standard_layout().into_owned().into_dyn(),
+                    biases: biases.into_dyn(),
+                };
+                self.push(Layer::Dense(config), Some(Tensors::Dense(tensors)));
+            }
+            "Add" => {
+                // the bias of a preceding MatMul
+                let bias = self.constant(node, 1)?;
+                match (self.layers.last(), self.tensors.last_mut()) {
+                    (Some(Layer::Dense(_)), Some(Tensors::Dense(tensors)))
+                        if bias.len() == tensors.biases.len() =>
+                    {
+                        tensors.biases += &bias.into_shape_with_order(tensors.biases.raw_dim())?;
+                    }
+                    _ => return Err(unsupported(node, "only supported as a dense bias")),
+                }
+            }
+            "Conv" | "ConvTranspose" => {
+                if int(node, "group", 1) != 1 {
+                    return Err(unsupported(node, "grouped convolution"));
+                }
+                check_unit(node, "dilations")?;
+                let padding = symmetric_pads(node)?;
+                let strides = strides(node);
+                let weights = self.constant(node, 1)?.into_dimensionality::<Ix4>()?;
+                // ONNX stores transposed kernels as [in, out, ...]
+                let filters = weights.shape()[(node.op_type != "Conv") as usize];
+                let biases = match self.optional_constant(node, 2)? {
+                    Some(biases) => biases.into_shape_with_order(vec![filters])?,
+                    None => ArrayD::zeros(vec![filters]),
+                };
+                if node.op_type == "Conv" {
+                    let config = Conv2DLayer {
+                        init: None,
+                        kernel: None,
+                        kernel_size: weights.shape().to_vec(),
+                        padding: Some(padding),
+                        strides: Some(strides),
+                        c: None,
+                        l1_ratio: None,
+                    };
+                    let tensors = ConvTensors {
+                        weights: weights.into_dyn(),
+                        biases,
+                    };
+                    self.push(Layer::Conv2D(config), Some(Tensors::Conv(tensors)));
+                } else {
+                    if ints(node, "output_padding").is_some_and(|x| x.iter().any(|x| *x != 0))
+                        || attribute(node, "output_shape").is_some()
+                    {
+                        return Err(unsupported(node, "output padding"));
+                    }
+                    // netsaur keeps the output channels first
+                    let weights = weights.permuted_axes([1, 0, 2, 3]);
+                    let weights = weights.as_standard_layout().into_owned();
+                    // netsaur pads the input and drops kernel - 1 from each
+                    // side of the output, ONNX only crops the output
+                    let padding = (0..2)
+                        .map(|i| {
+                            let overlap = weights.shape()[2 + i].checked_sub(padding[i] + 1)?;
+                            (overlap.checked_rem(strides[i]) == Some(0))
+                                .then(|| overlap / strides[i])
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(unsupported(node, "padding that netsaur cannot express"))?;
+                    let config = ConvTranspose2DLayer {
+                        init: None,
+                        kernel: None,
+                        kernel_size: weights.shape().to_vec(),
+                        padding: Some(padding),
+                        strides: Some(strides),
+                        c: None,
+                        l1_ratio: None,
+                    };
+                    let tensors = ConvTensors {
+                        weights: weights.into_dyn(),
+                        biases,
+                    };
+                    self.push(Layer::ConvTranspose2D(config), Some(Tensors::Conv(tensors)));
+                }
+            }
+            "BatchNormalization" => {
+                if int(node, "training_mode", 0) != 0 {
+                    return Err(unsupported(node, "training mode"));
+                }
+                let config = BatchNormLayer {
+                    momentum: float(node, "momentum", 0.9),
+                    epsilon: float(node, "epsilon", 1e-5),
+                };
+                let rank = self.shape()?.len();
+                let shape = match rank {
+                    2 => vec![1, self.shape()?[1]],
+                    4 => vec![1, self.shape()?[1], 1, 1],
+                    _ => return Err(unsupported(node, "input rank other than 2 or 4")),
+                };
+                let [gamma, beta, running_mean, running_var] = [1, 2, 3, 4].map(|index| {
+                    self.constant(node, index)
+                        .and_then(|x| Ok(x.into_shape_with_order(shape.clone())?))
+                });
+                let tensors = BatchNormTensors {
+                    gamma: gamma?,
+                    beta: beta?,
+                    running_mean: running_mean?,
+                    running_var: running_var?,
+                };
+                let layer = if rank == 2 {
+                    Layer::BatchNorm1D(config)
+                } else {
+                    Layer::BatchNorm2D(config)
+                };
+                self.push(layer, Some(Tensors::BatchNorm(tensors)));
+            }
+            "MaxPool" | "AveragePool" => {
+                let kernel: Vec<usize> = ints(node, "kernel_shape")
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|x| *x as usize)
+                    .collect();
+                check_unit(node, "dilations")?;
+                if symmetric_pads(node)? != [0, 0] || int(node, "ceil_mode", 0) != 0 {
+                    return Err(unsupported(node, "padding"));
+                }
+                // netsaur pools over windows that do not overlap
+                if kernel != strides(node) {
+                    return Err(unsupported(node, "kernel size different from the strides"));
+                }
+                let config = Pool2DLayer {
+                    mode: (node.op_type == "MaxPool") as usize,
+                    strides: Some(kernel),
+                };
+                self.push(Layer::Pool2D(config), None);
+            }
+            "GlobalAveragePool" | "GlobalMaxPool" => {
+                let shape = self.shape()?;
+                let config = Pool2DLayer {
+                    mode: (node.op_type == "GlobalMaxPool") as usize,
+                    strides: Some(shape[2..].to_vec()),
+                };
+                self.push(Layer::Pool2D(config), None);
+            }
+            "Flatten" => {
+                if int(node, "axis", 1) != 1 {
+                    return Err(unsupported(node, "axis other than 1"));
+                }
+                self.push(Layer::Flatten, None);
+            }
+            "Reshape" => {
+                let shape = self.shape()?;
+                let target = integers(
+                    node.input
+                        .get(1)
+                        .and_then(|name| self.constants.get(name))
+                        .ok_or(unsupported(node, "shape is not a constant"))?,
+                )?;
+                let features: usize = shape[1..].iter().product();
+                let flattens = target.len() == 2
+                    && (matches!(target[0], -1 | 0) || target[0] as usize == shape[0])
+                    && (target[1] == -1 || target[1] as usize == features);
+                if !flattens {
+                    return Err(unsupported(node, "only flattening reshapes"));
+                }
+                self.push(Layer::Flatten, None);
+            }
+            "Gather" => {
+                if int(node, "axis", 0) != 0 {
+                    return Err(unsupported(node, "axis other than 0"));
+                }
+                let embeddings = self.constant(node, 0)?.into_dimensionality::<Ix2>()?;
+                let (vocab_size, embedding_size) = embeddings.dim();
+                let config = EmbeddingLayer {
+                    vocab_size,
+                    embedding_size,
+                    c: None,
+                    l1_ratio: None,
+                };
+                let tensors = EmbeddingTensors {
+                    embeddings: embeddings.into_dyn(),
+                };
+                self.push(Layer::Embedding(config), Some(Tensors::Embedding(tensors)));
+            }
+            // picks the output the chain continues from itself
+            "LSTM" | "GRU" => return self.recurrent(node),
+            "Squeeze" => {
+                let axes = match node.input.get(1) {
+                    Some(name) if !name.is_empty() => integers(
+                        self.constants
+                            .get(name)
+                            .ok_or(unsupported(node, "axes are not a constant"))?,
+                    )?,
+                    _ => ints(node, "axes").unwrap_or_default(),
+                };
+                match self.squeeze.take() {
+                    Some(axis) if axes == [axis] => {}
+                    _ => return Err(unsupported(node, "only supported after an LSTM or GRU")),
+                }
+            }
+            _ => return Err(unsupported(node, "op is not supported")),
+        }
+        self.current = node.output[0].clone();
+        Ok(())
+    }
+
+    fn recurrent(&mut self, node: &NodeProto) -> NetsaurResult<()> {
+        let lstm = node.op_type == "LSTM";
+        if int(node, "layout", 0) != 1 {
+            return Err(unsupported(node, "layout other than batch first"));
+        }
+        if !matches!(string(node, "direction").as_deref(), None | Some("forward")) {
+            return Err(unsupported(node, "direction other than forward"));
+        }
+        if node.input.iter().skip(4).any(|x| !x.is_empty())
+            || attribute(node, "clip").is_some()
+            || int(node, "input_forget", 0) != 0
+        {
+            return Err(unsupported(node, "initial states, peepholes or clipping"));
+        }
+        if int(node, "linear_before_reset", 0) != 0 {
+            return Err(unsupported(node, "linear_before_reset"));
+        }
+        let gates: &[usize] = if lstm { &LSTM_GATES } else { &GRU_GATES };
+        let size = gates.len();
+        let w = self.constant(node, 1)?;
+        let r = self.constant(node, 2)?;
+        if w.ndim() != 3 || r.ndim() != 3 {
+            return Err(NetsaurError::Corrupt(format!(
+                "{} node {:?} has weights of rank {} and {} instead of 3",
+                node.op_type,
+                node.name,
+                w.ndim(),
+                r.ndim()
+            )));
+        }
+        let (hidden, input_size) = (r.shape()[2], w.shape()[2]);
+        let b = self
+            .optional_constant(node, 3)?
+            .unwrap_or(ArrayD::zeros(vec![1, 2 * size * hidden]));
+        if w.shape() != [1, size * hidden, input_size] || b.shape() != [1, 2 * size * hidden] {
+            return Err(unsupported(node, "weight shapes"));
+        }
+        let activations = match attribute(node, "activations") {
+            Some(attribute) => attribute.strings.clone(),
+            None if lstm => vec![b"Sigmoid".to_vec(), b"Tanh".to_vec(), b"Tanh".to_vec()],
+            None => vec![b"Sigmoid".to_vec(), b"Tanh".to_vec()],
+        };
+        if activations.len() != size - 1 || activations[1..].iter().any(|x| *x != activations[1]) {
+            return Err(unsupported(node, "different cell and hidden activations"));
+        }
+
+        let mut w_ih = Array3::zeros((size, input_size, hidden));
+        let mut w_hh = Array3::zeros((size, hidden, hidden));
+        let mut biases = ArrayD::zeros(vec![size, hidden]);
+        for (gate, j) in gates.iter().enumerate() {
+            let rows = j * hidden..(j + 1) * hidden;
+            w_ih.index_axis_mut(Axis(0), gate)
+                .assign(&w.slice(s![0, rows.clone(), ..]).t());
+            w_hh.index_axis_mut(Axis(0), gate)
+                .assign(&r.slice(s![0, rows.clone(), ..]).t());
+            let recurrent = size * hidden + rows.start..size * hidden + rows.end;
+            let bias = &b.slice(s![0, rows.clone()]) + &b.slice(s![0, recurrent]);
+            biases.index_axis_mut(Axis(0), gate).assign(&bias);
+        }
+
+        let sequences = node
+            .output
+            .first()
+            .is_some_and(|y| self.consumed.contains(y));
+        let gate_activation = Some(recurrent_activation(node, &activations[0])?);
+        let activation = Some(recurrent_activation(node, &activations[1])?);
+        let layer = if lstm {
+            Layer::LSTM(LSTMLayer {
+                size: hidden,
+                init: None,
+                c: None,
+                l1_ratio: None,
+                return_sequences: Some(sequences),
+                recurrent_activation: gate_activation,
+                activation,
+            })
+        } else {
+            Layer::GRU(GRULayer {
+                size: hidden,
+                init: None,
+                c: None,
+                l1_ratio: None,
+                return_sequences: Some(sequences),
+                recurrent_activation: gate_activation,
+                activation,
+            })
+        };
+        let tensors = RecurrentTensors {
+            w_ih: w_ih.into_dyn(),
+            w_hh: w_hh.into_dyn(),
+            biases,
+        };
+        self.push(layer, Some(Tensors::Recurrent(tensors)));
+        // Y is [batch, seq, 1, hidden] and Y_h is [batch, 1, hidden]
+        let (output, axis) = if sequences { (0, 2) } else { (1, 1) };
+        self.squeeze = Some(axis);
+        self.current = node
+            .output
+            .get(output)
+            .cloned()
+            .ok_or(unsupported(node, "missing output"))?;
+        Ok(())
+    }
+}
+
+/// Builds a config and its weights from an ONNX model whose nodes form a
+/// single chain of supported layers. Training options are left at their
+/// defaults.
+pub fn import_onnx(buffer: &[u8]) -> NetsaurResult<(BackendConfig, Vec<Tensors>)> {
+    let model = ModelProto::decode(buffer)?;
+    let graph = model
+        .graph
+        .ok_or(NetsaurError::Onnx("model has no graph".to_string()))?;
+
+    let mut constants: HashMap<String, TensorProto> = graph
+        .initializer
+        .into_iter()
+        .map(|tensor| (tensor.name.clone(), tensor))
+        .collect();
+    let mut nodes = Vec::new();
+    for node in graph.node {
+        if node.op_type == "Constant" {
+            let value = attribute(&node, "value")
+                .and_then(|attribute| attribute.t.clone())
+                .ok_or(unsupported(&node, "only tensor values are supported"))?;
+            let name = node.output.first().ok_or(NetsaurError::Corrupt(format!(
+                "Constant node {:?} has no outputs",
+                node.name
+            )))?;
+            constants.insert(name.clone(), value);
+        } else {
+            nodes.push(node);
+        }
+    }
+
+    let inputs: Vec<_> = graph
+        .input
+        .iter()
+        .filter(|input| !constants.contains_key(&input.name))
+        .collect();
+    let (input, output) = match (inputs.as_slice(), graph.output.as_slice()) {
+        ([input], [output]) => (input, output),
+        _ => {
+            return Err(NetsaurError::Onnx(
+                "model must have exactly one input and one output".to_string(),
+            ))
+        }
+    };
+    let dims = match input.r#type.as_ref().and_then(|x| x.value.as_ref()) {
+        Some(type_proto::Value::TensorType(tensor)) => tensor
+            .shape
+            .as_ref()
+            .map(|shape| shape.dim.clone())
+            .unwrap_or_default(),
+        None => vec![],
+    };
+    let mut size = Vec::new();
+    for (i, dim) in dims.iter().enumerate() {
+        match dim.value {
+            Some(dimension::Value::DimValue(value)) if value > 0 => size.push(value as usize),
+            // the batch axis is dynamic in netsaur
+            _ if i == 0 => size.push(1),
+            _ => {
+                return Err(NetsaurError::Onnx(format!(
+                    "dimension {} of input {:?} is not fixed",
+                    i, input.name
+                )))
+            }
+        }
+    }
+
+    let mut importer = Importer {
+        consumed: nodes
+            .iter()
+            .flat_map(|node| node.input.iter().cloned())
+            .chain([output.name.clone()])
+            .collect(),
+        constants,
+        size,
+        layers: Vec::new(),
+        tensors: Vec::new(),
+        current: input.name.clone(),
+        temperature: None,
+        squeeze: None,
+    };
+    for node in &nodes {
+        importer.node(node)?;
+    }
+    if importer.temperature.is_some() || importer.squeeze.is_some() {
+        return Err(NetsaurError::Onnx("graph ends inside a layer".to_string()));
+    }
+    if importer.current != output.name {
+        return Err(NetsaurError::Onnx(format!(
+            "output {:?} is not produced by the chain of layers",
+            output.name
+        )));
+    }
+    let config = importer.config();
+    validate_tensors(&config, &importer.tensors)?;
+    Ok((config, importer.tensors))
+}
+
+impl Backend {
+    /// Builds a backend from an ONNX model, see `import_onnx`.
+    pub fn load_onnx(buffer: &[u8], logger: Logger, timer: Timer) -> NetsaurResult<Self> {
+        let (config, tensors) = import_onnx(buffer)?;
+        Ok(Backend::new(config, logger, timer, Some(tensors)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr1, arr2};
+    use prost::Message;
+
+    use super::*;
+    use crate::onnx::proto::{
+        attribute_type, tensor_shape_proto::Dimension, type_proto, GraphProto, TensorShapeProto,
+        TypeProto, ValueInfoProto,
+    };
+    use crate::PostProcessor;
+
+    const RECURRENT: &str = r#"{
+        "size": [2, 3],
+        "layers": [
+            { "type": "embedding", "config": { "vocabSize": 10, "embeddingSize": 4 } },
+            { "type": "lstm", "config": { "size": 5 } },
+            { "type": "dense", "config": { "size": [1] } }
+        ],
+        "cost": "mse",
+        "optimizer": { "type": "sgd" },
+        "scheduler": { "type": "none" }
+    }"#;
+
+    fn exported() -> ModelProto {
+        let backend = Backend::new(
+            serde_json::from_str(RECURRENT).unwrap(),
+            Logger { log: |_| {} },
+            Timer { now: || 0 },
+            None,
+        );
+        ModelProto::decode(&backend.export_onnx().unwrap()[..]).unwrap()
+    }
+
+    fn node<'a>(model: &'a mut ModelProto, op_type: &str) -> &'a mut NodeProto {
+        let graph = model.graph.as_mut().unwrap();
+        graph
+            .node
+            .iter_mut()
+            .find(|node| node.op_type == op_type)
+            .unwrap()
+    }
+
+    #[test]
+    fn imports_an_exported_model() {
+        let (config, tensors) = import_onnx(&exported().encode_to_vec()).unwrap();
+        assert_eq!(config.layer_names(), ["embedding0", "lstm0", "dense0"]);
+        assert_eq!(tensors.len(), 3);
+    }
+
+    #[test]
+    fn rejects_recurrent_weights_of_the_wrong_rank() {
+        let mut model = exported();
+        let weights = node(&mut model, "LSTM").input[1].clone();
+        let graph = model.graph.as_mut().unwrap();
+        let tensor = graph
+            .initializer
+            .iter_mut()
+            .find(|tensor| tensor.name == weights)
+            .unwrap();
+        tensor.dims = vec![tensor.dims.iter().product()];
+
+        let error = import_onnx(&model.encode_to_vec()).unwrap_err();
+        assert!(matches!(error, NetsaurError::Corrupt(_)), "{}", error);
+    }
+
+    #[test]
+    fn rejects_nodes_without_outputs() {
+        let mut model = exported();
+        node(&mut model, "LSTM").output.clear();
+
+        let error = import_onnx(&model.encode_to_vec()).unwrap_err();
+        assert!(matches!(error, NetsaurError::Corrupt(_)), "{}", error);
+    }
+
+    fn value(name: &str, dims: &[i64]) -> ValueInfoProto {
+        let dim = dims
+            .iter()
+            .map(|x| Dimension {
+                value: Some(dimension::Value::DimValue(*x)),
+            })
+            .collect();
+        ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(TypeProto {
+                value: Some(type_proto::Value::TensorType(type_proto::Tensor {
+                    elem_type: data_type::FLOAT,
+                    shape: Some(TensorShapeProto { dim }),
+                })),
+            }),
+        }
+    }
+
+    fn initializer(name: &str, dims: &[i64], data: &[f32]) -> TensorProto {
+        TensorProto {
+            name: name.to_string(),
+            dims: dims.to_vec(),
+            data_type: data_type::FLOAT,
+            raw_data: data.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds a model of a single node reading `x` of shape `[1, 3]` and
+    /// writing `y` of shape `[1, 2]`.
+    fn single_node(node: NodeProto, initializer: Vec<TensorProto>) -> Vec<u8> {
+        ModelProto {
+            graph: Some(GraphProto {
+                node: vec![node],
+                initializer,
+                input: vec![value("x", &[1, 3])],
+                output: vec![value("y", &[1, 2])],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn imports_gemm_with_transposed_weights() {
+        // Gemm with transB = 1 stores the weights as [outputs, inputs].
+        let weights = [0.5, -1.0, 2.0, 1.5, 0.25, -0.75];
+        let biases = [0.1, -0.2];
+        let node = NodeProto {
+            input: vec!["x".to_string(), "w".to_string(), "b".to_string()],
+            output: vec!["y".to_string()],
+            op_type: "Gemm".to_string(),
+            attribute: vec![AttributeProto {
+                name: "transB".to_string(),
+                r#type: attribute_type::INT,
+                i: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let model = single_node(
+            node,
+            vec![
+                initializer("w", &[2, 3], &weights),
+                initializer("b", &[2], &biases),
+            ],
+        );
+        let backend =
+            Backend::load_onnx(&model, Logger { log: |_| {} }, Timer { now: || 0 }).unwrap();
+
+        let inputs = arr2(&[[1.0, 2.0, 3.0], [-1.0, 0.5, 0.0]]);
+        let outputs = backend
+            .predict(inputs.clone().into_dyn(), PostProcessor::None, None)
+            .unwrap();
+        let weights = arr2(&[[0.5, -1.0, 2.0], [1.5, 0.25, -0.75]]);
+        let expected = inputs.dot(&weights.t()) + arr1(&biases);
+        assert_eq!(outputs.shape(), [2, 2]);
+        for (output, expected) in outputs.iter().zip(expected.iter()) {
+            assert!(
+                (output - expected).abs() < 1e-6,
+                "{} != {}",
+                output,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn names_unsupported_operators() {
+        let node = NodeProto {
+            input: vec!["x".to_string(), "x".to_string()],
+            output: vec!["y".to_string()],
+            op_type: "Concat".to_string(),
+            attribute: vec![AttributeProto {
+                name: "axis".to_string(),
+                r#type: attribute_type::INT,
+                i: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let error = import_onnx(&single_node(node, vec![])).unwrap_err();
+        assert!(matches!(error, NetsaurError::Onnx(_)), "{}", error);
+        assert!(error.to_string().contains("Concat"), "{}", error);
+    }
+}
@@ -0,0 +1,5 @@
+mod export;
+mod import;
+pub(crate) mod proto;
+
+pub use import::*;
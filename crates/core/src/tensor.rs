@@ -54,22 +54,57 @@ pub struct BatchNormTensors {
     pub running_var: ArrayD<f32>,
 }
 
+#[derive(Debug)]
+pub struct EmbeddingTensors {
+    pub embeddings: ArrayD<f32>,
+}
+
+#[derive(Debug)]
+pub struct RecurrentTensors {
+    pub w_ih: ArrayD<f32>,
+    pub w_hh: ArrayD<f32>,
+    pub biases: ArrayD<f32>,
+}
+
 #[derive(Debug)]
 pub enum Tensors {
     Dense(DenseTensors),
     Conv(ConvTensors),
     BatchNorm(BatchNormTensors),
+    Embedding(EmbeddingTensors),
+    /// Weights of the recurrent layers, LSTM and GRU.
+    Recurrent(RecurrentTensors),
+}
+
+impl Tensors {
+    /// The tensors in the order of `Layer::params`.
+    pub fn arrays(&self) -> Vec<&ArrayD<f32>> {
+        match self {
+            Tensors::Dense(tensors) => vec![&tensors.weights, &tensors.biases],
+            Tensors::Conv(tensors) => vec![&tensors.weights, &tensors.biases],
+            Tensors::BatchNorm(tensors) => vec![
+                &tensors.gamma,
+                &tensors.beta,
+                &tensors.running_mean,
+                &tensors.running_var,
+            ],
+            Tensors::Embedding(tensors) => vec![&tensors.embeddings],
+            Tensors::Recurrent(tensors) => vec![&tensors.w_ih, &tensors.w_hh, &tensors.biases],
+        }
+    }
 }
 
 pub trait GetTensor {
     fn get(&mut self) -> Option<Tensors>;
 }
 
+/// Hands out the tensors in layer order. Once they run out, the remaining
+/// layers get `None` and start from fresh parameters.
 impl GetTensor for Option<Vec<Tensors>> {
     fn get(&mut self) -> Option<Tensors> {
-        if let Some(tensors) = self {
-            return Some(tensors.remove(0));
+        match self {
+            Some(tensors) if !tensors.is_empty() => Some(tensors.remove(0)),
+            _ => None,
         }
-        None
     }
 }
@@ -5,12 +5,56 @@ use serde::{Deserialize, Serialize};
 pub struct BackendConfig {
     pub silent: Option<bool>,
     pub size: Vec<usize>,
-    pub layers: Vec<Layer>,
+    pub layers: Vec<LayerEntry>,
     pub cost: Cost,
     pub optimizer: Optimizer,
     pub scheduler: Scheduler,
     pub tolerance: Option<f32>,
     pub patience: Option<usize>,
+    pub monitor: Option<Monitor>,
+    pub clipping: Option<GradientClipping>,
+    pub seed: Option<u64>,
+}
+
+/// Gradient clipping applied before every optimizer step. The global norm is
+/// computed across all trainable parameters, the norm per parameter tensor.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GradientClipping {
+    pub value: Option<f32>,
+    pub norm: Option<f32>,
+    pub global_norm: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Monitor {
+    Loss,
+    ValLoss,
+}
+
+/// Training progress stored alongside the weights in a checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainingState {
+    pub epoch: usize,
+    pub step: usize,
+    pub best_cost: f32,
+    pub disappointments: usize,
+    #[serde(default)]
+    pub plateau: Option<PlateauState>,
+}
+
+impl Default for TrainingState {
+    fn default() -> Self {
+        Self {
+            epoch: 0,
+            step: 0,
+            best_cost: -1.0,
+            disappointments: 0,
+            plateau: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -33,11 +77,99 @@ pub enum Layer {
     Embedding(EmbeddingLayer),
     Flatten,
     LSTM(LSTMLayer),
+    GRU(GRULayer),
     Dropout1D(DropoutLayer),
     Dropout2D(DropoutLayer),
     Softmax(SoftmaxLayer),
 }
 
+impl Layer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layer::Activation(_) => "activation",
+            Layer::Dense(_) => "dense",
+            Layer::BatchNorm1D(_) => "batchnorm1d",
+            Layer::BatchNorm2D(_) => "batchnorm2d",
+            Layer::Conv2D(_) => "conv2d",
+            Layer::ConvTranspose2D(_) => "convtranspose2d",
+            Layer::Pool2D(_) => "pool2d",
+            Layer::Embedding(_) => "embedding",
+            Layer::Flatten => "flatten",
+            Layer::LSTM(_) => "lstm",
+            Layer::GRU(_) => "gru",
+            Layer::Dropout1D(_) => "dropout1d",
+            Layer::Dropout2D(_) => "dropout2d",
+            Layer::Softmax(_) => "softmax",
+        }
+    }
+
+    /// Parameters the layer saves, in the order `Backend::new` reads them.
+    pub fn params(&self) -> &'static [LayerParam] {
+        match self {
+            Layer::Dense(_) | Layer::Conv2D(_) | Layer::ConvTranspose2D(_) => {
+                &[LayerParam::Weight, LayerParam::Bias]
+            }
+            Layer::BatchNorm1D(_) | Layer::BatchNorm2D(_) => &[
+                LayerParam::Gamma,
+                LayerParam::Beta,
+                LayerParam::RunningMean,
+                LayerParam::RunningVar,
+            ],
+            Layer::Embedding(_) => &[LayerParam::Embeddings],
+            Layer::LSTM(_) | Layer::GRU(_) => &[LayerParam::WIh, LayerParam::WHh, LayerParam::Bias],
+            _ => &[],
+        }
+    }
+}
+
+/// A layer of the config. The name keys the layer's saved tensors, so
+/// files keep loading when layers are inserted before it. A layer with
+/// `trainable: false` keeps its parameters fixed during training.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LayerEntry {
+    #[serde(flatten)]
+    pub layer: Layer,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trainable: Option<bool>,
+}
+
+impl LayerEntry {
+    pub fn trainable(&self) -> bool {
+        self.trainable.unwrap_or(true)
+    }
+}
+
+impl From<Layer> for LayerEntry {
+    fn from(layer: Layer) -> Self {
+        LayerEntry {
+            layer,
+            name: None,
+            trainable: None,
+        }
+    }
+}
+
+impl BackendConfig {
+    /// Name of every layer. Unnamed layers are numbered per type, e.g.
+    /// `dense0`, `dense1`, skipping named ones.
+    pub fn layer_names(&self) -> Vec<String> {
+        let mut counts = std::collections::HashMap::new();
+        self.layers
+            .iter()
+            .map(|entry| match &entry.name {
+                Some(name) => name.clone(),
+                None => {
+                    let count = counts.entry(entry.layer.name()).or_insert(0);
+                    *count += 1;
+                    format!("{}{}", entry.layer.name(), *count - 1)
+                }
+            })
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Activation {
@@ -118,6 +250,18 @@ pub struct LSTMLayer {
     pub activation: Option<Activation>
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GRULayer {
+    pub size: usize,
+    pub init: Option<Init>,
+    pub c: Option<f32>,
+    pub l1_ratio: Option<f32>,
+    pub return_sequences: Option<bool>,
+    pub recurrent_activation: Option<Activation>,
+    pub activation: Option<Activation>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DropoutLayer {
     pub probability: f32,
@@ -185,14 +329,61 @@ pub struct RMSPropOptimizer {
     pub epsilon: f32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SGDOptimizer {
+    pub momentum: f32,
+    pub nesterov: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdamWOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+    pub weight_decay: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdagradOptimizer {
+    pub epsilon: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdadeltaOptimizer {
+    pub rho: f32,
+    pub epsilon: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdaBeliefOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LionOptimizer {
+    pub beta1: f32,
+    pub beta2: f32,
+    pub weight_decay: Option<f32>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "config")]
 #[serde(rename_all = "lowercase")]
 pub enum Optimizer {
-    SGD,
+    SGD(Option<SGDOptimizer>),
     Adam(AdamOptimizer),
     Nadam(NadamOptimizer),
     RMSProp(RMSPropOptimizer),
+    AdamW(AdamWOptimizer),
+    Adagrad(AdagradOptimizer),
+    Adadelta(AdadeltaOptimizer),
+    AdaBelief(AdaBeliefOptimizer),
+    Lion(LionOptimizer),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -209,6 +400,37 @@ pub struct OneCycleScheduler {
     pub step_size: usize,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CosineAnnealingScheduler {
+    pub min_rate: f32,
+    pub step_size: usize,
+    pub multiplier: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmupScheduler {
+    pub steps: usize,
+    pub start_factor: Option<f32>,
+    pub scheduler: Box<Scheduler>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultiStepScheduler {
+    pub milestones: Vec<usize>,
+    pub gamma: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReduceOnPlateauScheduler {
+    pub factor: f32,
+    pub patience: usize,
+    pub threshold: Option<f32>,
+    pub min_rate: Option<f32>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "config")]
 #[serde(rename_all = "lowercase")]
@@ -217,6 +439,65 @@ pub enum Scheduler {
     LinearDecay(DecayScheduler),
     ExponentialDecay(DecayScheduler),
     OneCycle(OneCycleScheduler),
+    CosineAnnealing(CosineAnnealingScheduler),
+    Warmup(WarmupScheduler),
+    MultiStep(MultiStepScheduler),
+    ReduceOnPlateau(ReduceOnPlateauScheduler),
+}
+
+/// Progress of a reduce-on-plateau scheduler.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlateauState {
+    pub scale: f32,
+    pub best: Option<f32>,
+    pub wait: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TopKConfig {
+    pub k: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "config")]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    Accuracy,
+    TopKAccuracy(TopKConfig),
+    Precision,
+    Recall,
+    F1,
+    ConfusionMatrix,
+    MAE,
+    RMSE,
+    R2,
+    RocAuc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsReport {
+    pub loss: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accuracy: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k_accuracy: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recall: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f1: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confusion_matrix: Option<Vec<Vec<usize>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mae: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rmse: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r2: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roc_auc: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -243,6 +524,24 @@ pub struct TrainOptions {
     pub epochs: usize,
     pub batches: usize,
     pub rate: f32,
+    pub validation_datasets: Option<usize>,
+    pub validation_input_shape: Option<Vec<usize>>,
+    pub validation_output_shape: Option<Vec<usize>>,
+    pub validation_split: Option<f32>,
+    pub metrics: Option<Vec<Metric>>,
+    pub resume: Option<bool>,
+    pub batch_size: Option<usize>,
+    pub shuffle: Option<bool>,
+    pub seed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluateOptions {
+    pub datasets: usize,
+    pub input_shape: Vec<usize>,
+    pub output_shape: Vec<usize>,
+    pub metrics: Vec<Metric>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -254,9 +553,130 @@ pub struct PredictOptions {
     pub post_process: PostProcessor,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerSummary {
+    pub layer: String,
+    pub name: String,
+    pub input_shape: Vec<usize>,
+    pub output_shape: Vec<usize>,
+    pub trainable_params: usize,
+    pub non_trainable_params: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub layers: Vec<LayerSummary>,
+    pub trainable_params: usize,
+    pub non_trainable_params: usize,
+    pub total_params: usize,
+    pub parameter_bytes: usize,
+    pub gradient_bytes: usize,
+    pub optimizer_bytes: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RegularizeOptions {
     pub c: f32,
     pub l1_ratio: f32,
 }
+
+/// Parameter of a layer that a pretrained tensor is loaded into.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerParam {
+    Weight,
+    Bias,
+    Gamma,
+    Beta,
+    RunningMean,
+    RunningVar,
+    Embeddings,
+    WIh,
+    WHh,
+}
+
+impl LayerParam {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayerParam::Weight => "weight",
+            LayerParam::Bias => "bias",
+            LayerParam::Gamma => "gamma",
+            LayerParam::Beta => "beta",
+            LayerParam::RunningMean => "running_mean",
+            LayerParam::RunningVar => "running_var",
+            LayerParam::Embeddings => "embeddings",
+            LayerParam::WIh => "w_ih",
+            LayerParam::WHh => "w_hh",
+        }
+    }
+
+    /// Key of the parameter in saved files, e.g. `encoder.dense1.weight`.
+    pub fn key(&self, layer: &str) -> String {
+        format!("{}.{}", layer, self.name())
+    }
+
+    /// Key in files without a format version, which key tensors by layer
+    /// index, e.g. `0w`.
+    pub fn legacy_key(&self, index: usize) -> String {
+        let suffix = match self {
+            LayerParam::Weight => "w",
+            LayerParam::Bias | LayerParam::Beta => "b",
+            LayerParam::Gamma => "g",
+            LayerParam::RunningMean => "m",
+            LayerParam::RunningVar => "v",
+            LayerParam::Embeddings => "e",
+            LayerParam::WIh => "w_ih",
+            LayerParam::WHh => "w_hh",
+        };
+        format!("{}{}", index, suffix)
+    }
+}
+
+/// Maps a tensor of a foreign safetensors file onto a layer parameter.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TensorMapping {
+    /// Name of the tensor in the file, e.g. `fc1.weight`.
+    pub source: String,
+    /// Index of the layer in the config.
+    pub layer: usize,
+    pub param: LayerParam,
+    /// Reverses the axes, e.g. for PyTorch `Linear` weights stored as
+    /// `[out, in]`.
+    pub transpose: Option<bool>,
+    /// Reorders the axes, e.g. `[3, 2, 0, 1]` for Keras conv kernels.
+    pub permute: Option<Vec<usize>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingTensor {
+    pub layer: usize,
+    pub param: LayerParam,
+    /// Set when a mapping names a tensor the file does not contain.
+    pub source: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MismatchedTensor {
+    pub layer: usize,
+    pub param: LayerParam,
+    pub source: String,
+    pub expected: Vec<usize>,
+    pub got: Vec<usize>,
+}
+
+/// Outcome of `Backend::load_pretrained`. Missing and mismatched
+/// parameters keep their initial values.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PretrainedReport {
+    pub missing: Vec<MissingTensor>,
+    pub mismatched: Vec<MismatchedTensor>,
+    /// Tensors of the file that no mapping uses.
+    pub unused: Vec<String>,
+}
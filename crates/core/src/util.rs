@@ -1,9 +1,12 @@
 use std::slice::from_raw_parts;
 
+use half::{bf16, f16};
 use ndarray::ArrayD;
-use safetensors::tensor::TensorView;
+use safetensors::{tensor::TensorView, Dtype};
 use serde::Deserialize;
 
+use crate::{NetsaurError, NetsaurResult};
+
 #[derive(Clone)]
 pub struct Logger {
     pub log: fn(string: String) -> (),
@@ -18,23 +21,47 @@ pub fn length(shape: Vec<usize>) -> usize {
     return shape.iter().fold(1, |i, x| i * x);
 }
 
-pub fn decode_array(ptr: *const f32, shape: Vec<usize>) -> ArrayD<f32> {
+pub fn decode_array(ptr: *const f32, shape: Vec<usize>) -> NetsaurResult<ArrayD<f32>> {
     let buffer = unsafe { from_raw_parts(ptr, length(shape.clone())) };
     let vec = Vec::from(buffer);
-    return ArrayD::from_shape_vec(shape, vec).unwrap();
+    Ok(ArrayD::from_shape_vec(shape, vec)?)
 }
 
-pub fn decode_json<'a, T>(ptr: *const u8, len: usize) -> T
+pub fn decode_json<'a, T>(ptr: *const u8, len: usize) -> NetsaurResult<T>
 where
     T: Deserialize<'a>,
 {
     let buffer = unsafe { from_raw_parts(ptr, len) };
-    let json = std::str::from_utf8(&buffer[0..len]).unwrap();
-    return serde_json::from_str(&json).unwrap();
+    let json = std::str::from_utf8(&buffer[0..len])?;
+    Ok(serde_json::from_str(json)?)
 }
 
-pub fn to_arr(view: TensorView) -> ArrayD<f32> {
-    let slice: &[f32] =
-        unsafe { from_raw_parts(view.data().as_ptr() as *const f32, view.data().len() / 4) };
-    return ArrayD::from_shape_vec(view.shape(), slice.to_vec()).unwrap();
+/// Reads a tensor as f32, converting f16, bf16 and f64 data.
+pub fn to_arr(view: TensorView) -> NetsaurResult<ArrayD<f32>> {
+    let data = view.data();
+    let values: Vec<f32> = match view.dtype() {
+        Dtype::F32 => data
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+            .collect(),
+        Dtype::F16 => data
+            .chunks_exact(2)
+            .map(|x| f16::from_le_bytes(x.try_into().unwrap()).to_f32())
+            .collect(),
+        Dtype::BF16 => data
+            .chunks_exact(2)
+            .map(|x| bf16::from_le_bytes(x.try_into().unwrap()).to_f32())
+            .collect(),
+        Dtype::F64 => data
+            .chunks_exact(8)
+            .map(|x| f64::from_le_bytes(x.try_into().unwrap()) as f32)
+            .collect(),
+        dtype => {
+            return Err(NetsaurError::Corrupt(format!(
+                "expected a float tensor, got {:?}",
+                dtype
+            )))
+        }
+    };
+    Ok(ArrayD::from_shape_vec(view.shape(), values)?)
 }
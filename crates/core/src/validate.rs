@@ -0,0 +1,418 @@
+use crate::{BackendConfig, JSTensor, Layer, NetsaurError, NetsaurResult, Tensors};
+
+fn invalid(
+    index: usize,
+    layer: &Layer,
+    msg: &'static str,
+    expected: String,
+    got: &[usize],
+) -> NetsaurError {
+    NetsaurError::InvalidLayer {
+        index,
+        layer: layer.name(),
+        msg,
+        expected,
+        got: got.to_vec(),
+    }
+}
+
+fn check_rank(index: usize, layer: &Layer, size: &[usize], rank: usize) -> NetsaurResult<()> {
+    if size.len() != rank {
+        return Err(invalid(
+            index,
+            layer,
+            "incompatible input rank",
+            format!("rank {}", rank),
+            size,
+        ));
+    }
+    Ok(())
+}
+
+fn check_pair(
+    index: usize,
+    layer: &Layer,
+    name: &'static str,
+    pair: &Option<Vec<usize>>,
+) -> NetsaurResult<()> {
+    if let Some(pair) = pair {
+        if pair.len() != 2 {
+            return Err(invalid(index, layer, name, "2 values".to_string(), pair));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a convolution kernel reads every input channel and that an
+/// initial kernel tensor has the shape and data of the kernel size.
+fn check_kernel(
+    index: usize,
+    layer: &Layer,
+    size: &[usize],
+    kernel: &[usize],
+    tensor: &Option<JSTensor>,
+) -> NetsaurResult<()> {
+    if kernel.len() != 4 || kernel[1] != size[1] {
+        return Err(invalid(
+            index,
+            layer,
+            "kernel does not match input channels",
+            format!("[filters, {}, y, x]", size[1]),
+            kernel,
+        ));
+    }
+    if let Some(tensor) = tensor {
+        if tensor.shape != kernel || tensor.data.len() != kernel.iter().product::<usize>() {
+            return Err(invalid(
+                index,
+                layer,
+                "kernel tensor does not match kernel size",
+                format!("{:?}", kernel),
+                &tensor.shape,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Infers the output shape of every layer in the config without constructing
+/// it, failing on the first layer whose input does not fit.
+pub fn validate(config: &BackendConfig) -> NetsaurResult<Vec<Vec<usize>>> {
+    if config.size.len() < 2 || config.size.contains(&0) {
+        return Err(NetsaurError::ShapeMismatch {
+            msg: "input shape must have a batch and at least one data dimension",
+            expected: vec![],
+            got: config.size.clone(),
+        });
+    }
+    if let Some(clipping) = &config.clipping {
+        let limits = [clipping.value, clipping.norm, clipping.global_norm];
+        if limits
+            .iter()
+            .flatten()
+            .any(|limit| limit.is_nan() || *limit <= 0.0)
+        {
+            return Err(NetsaurError::InvalidOption(
+                "gradient clipping limits must be positive".to_string(),
+            ));
+        }
+    }
+    let names = config.layer_names();
+    for (index, name) in names.iter().enumerate() {
+        if name.is_empty() || names[..index].contains(name) {
+            return Err(NetsaurError::InvalidOption(format!(
+                "layer #{} needs a unique name, got {:?}",
+                index, name
+            )));
+        }
+    }
+    let mut size = config.size.clone();
+    let mut shapes = Vec::new();
+    for (index, layer) in config.layers.iter().map(|entry| &entry.layer).enumerate() {
+        size = match layer {
+            Layer::Activation(_) | Layer::Dropout1D(_) => size,
+            Layer::Softmax(_) => {
+                check_rank(index, layer, &size, 2)?;
+                size
+            }
+            Layer::BatchNorm1D(_) => {
+                check_rank(index, layer, &size, 2)?;
+                size
+            }
+            Layer::BatchNorm2D(_) | Layer::Dropout2D(_) => {
+                check_rank(index, layer, &size, 4)?;
+                size
+            }
+            Layer::Dense(config) => {
+                check_rank(index, layer, &size, 2)?;
+                if config.size.len() != 1 {
+                    return Err(invalid(
+                        index,
+                        layer,
+                        "invalid dense size",
+                        "1 value".to_string(),
+                        &config.size,
+                    ));
+                }
+                vec![size[0], config.size[0]]
+            }
+            Layer::Conv2D(config) => {
+                check_rank(index, layer, &size, 4)?;
+                check_pair(index, layer, "invalid strides", &config.strides)?;
+                check_pair(index, layer, "invalid padding", &config.padding)?;
+                let kernel = &config.kernel_size;
+                check_kernel(index, layer, &size, kernel, &config.kernel)?;
+                let strides = config.strides.clone().unwrap_or(vec![1, 1]);
+                let padding = config.padding.clone().unwrap_or(vec![0, 0]);
+                let input_y = size[2] + 2 * padding[0];
+                let input_x = size[3] + 2 * padding[1];
+                if strides.contains(&0) || input_y < kernel[2] || input_x < kernel[3] {
+                    return Err(invalid(
+                        index,
+                        layer,
+                        "kernel does not fit the padded input",
+                        format!("at least [{}, {}]", kernel[2], kernel[3]),
+                        &[input_y, input_x],
+                    ));
+                }
+                vec![
+                    size[0],
+                    kernel[0],
+                    1 + (input_y - kernel[2]) / strides[0],
+                    1 + (input_x - kernel[3]) / strides[1],
+                ]
+            }
+            Layer::ConvTranspose2D(config) => {
+                check_rank(index, layer, &size, 4)?;
+                check_pair(index, layer, "invalid strides", &config.strides)?;
+                check_pair(index, layer, "invalid padding", &config.padding)?;
+                let kernel = &config.kernel_size;
+                check_kernel(index, layer, &size, kernel, &config.kernel)?;
+                let strides = config.strides.clone().unwrap_or(vec![1, 1]);
+                let padding = config.padding.clone().unwrap_or(vec![0, 0]);
+                if strides.contains(&0) {
+                    return Err(invalid(
+                        index,
+                        layer,
+                        "invalid strides",
+                        "non-zero strides".to_string(),
+                        &strides,
+                    ));
+                }
+                let input_y = size[2] + 2 * padding[0];
+                let input_x = size[3] + 2 * padding[1];
+                let output_y = ((input_y - 1) * strides[0] + 2).checked_sub(kernel[2]);
+                let output_x = ((input_x - 1) * strides[1] + 2).checked_sub(kernel[3]);
+                match (output_y, output_x) {
+                    (Some(output_y), Some(output_x)) if output_y > 0 && output_x > 0 => {
+                        vec![size[0], kernel[0], output_y, output_x]
+                    }
+                    _ => {
+                        return Err(invalid(
+                            index,
+                            layer,
+                            "kernel is larger than the output",
+                            format!(
+                                "at most [{}, {}]",
+                                (input_y - 1) * strides[0] + 1,
+                                (input_x - 1) * strides[1] + 1
+                            ),
+                            &kernel[2..],
+                        ))
+                    }
+                }
+            }
+            Layer::Pool2D(config) => {
+                check_rank(index, layer, &size, 4)?;
+                check_pair(index, layer, "invalid strides", &config.strides)?;
+                let strides = config.strides.clone().unwrap_or(vec![1, 1]);
+                if strides.contains(&0)
+                    || !size[2].is_multiple_of(strides[0])
+                    || !size[3].is_multiple_of(strides[1])
+                {
+                    return Err(invalid(
+                        index,
+                        layer,
+                        "strides do not divide the input",
+                        format!("multiples of {:?}", strides),
+                        &size[2..],
+                    ));
+                }
+                vec![size[0], size[1], size[2] / strides[0], size[3] / strides[1]]
+            }
+            Layer::Embedding(config) => {
+                check_rank(index, layer, &size, 2)?;
+                vec![size[0], size[1], config.embedding_size]
+            }
+            Layer::Flatten => vec![size[0], size[1..].iter().product()],
+            Layer::LSTM(config) => {
+                check_rank(index, layer, &size, 3)?;
+                if config.return_sequences.unwrap_or(false) {
+                    vec![size[0], size[1], config.size]
+                } else {
+                    vec![size[0], config.size]
+                }
+            }
+            Layer::GRU(config) => {
+                check_rank(index, layer, &size, 3)?;
+                if config.return_sequences.unwrap_or(false) {
+                    vec![size[0], size[1], config.size]
+                } else {
+                    vec![size[0], config.size]
+                }
+            }
+        };
+        shapes.push(size.clone());
+    }
+    Ok(shapes)
+}
+
+/// Shapes of the parameters of a layer with the given input shape, in the
+/// order of `Layer::params`.
+fn param_shapes(layer: &Layer, size: &[usize]) -> Vec<Vec<usize>> {
+    match layer {
+        Layer::Dense(config) => vec![vec![size[1], config.size[0]], vec![config.size[0]]],
+        Layer::Conv2D(config) => vec![config.kernel_size.clone(), vec![config.kernel_size[0]]],
+        Layer::ConvTranspose2D(config) => {
+            vec![config.kernel_size.clone(), vec![config.kernel_size[0]]]
+        }
+        Layer::BatchNorm1D(_) => vec![vec![1, size[1]]; 4],
+        Layer::BatchNorm2D(_) => vec![vec![1, size[1], 1, 1]; 4],
+        Layer::Embedding(config) => vec![vec![config.vocab_size, config.embedding_size]],
+        Layer::LSTM(config) => recurrent_shapes(4, size[2], config.size),
+        Layer::GRU(config) => recurrent_shapes(3, size[2], config.size),
+        _ => vec![],
+    }
+}
+
+fn recurrent_shapes(gates: usize, inputs: usize, units: usize) -> Vec<Vec<usize>> {
+    vec![
+        vec![gates, inputs, units],
+        vec![gates, units, units],
+        vec![gates, units],
+    ]
+}
+
+/// Checks that loaded tensors fit the layers of a valid config before the
+/// layers are built from them. `tensors` holds one entry per layer with
+/// parameters, layers past its end start from fresh parameters.
+pub fn validate_tensors(config: &BackendConfig, tensors: &[Tensors]) -> NetsaurResult<()> {
+    let shapes = validate(config)?;
+    let names = config.layer_names();
+    let mut tensors = tensors.iter();
+    for (index, entry) in config.layers.iter().enumerate() {
+        let params = entry.layer.params();
+        if params.is_empty() {
+            continue;
+        }
+        let Some(arrays) = tensors.next().map(Tensors::arrays) else {
+            break;
+        };
+        let size = match index {
+            0 => &config.size,
+            _ => &shapes[index - 1],
+        };
+        if arrays.len() != params.len() {
+            return Err(NetsaurError::Corrupt(format!(
+                "tensors of layer {} do not match a {} layer",
+                names[index],
+                entry.layer.name()
+            )));
+        }
+        for ((param, array), expected) in params
+            .iter()
+            .zip(arrays)
+            .zip(param_shapes(&entry.layer, size))
+        {
+            if array.shape() != expected {
+                return Err(NetsaurError::Corrupt(format!(
+                    "tensor {} has shape {:?}, expected {:?}",
+                    param.key(&names[index]),
+                    array.shape(),
+                    expected
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(size: &str, layers: &str) -> BackendConfig {
+        serde_json::from_str(&format!(
+            r#"{{
+                "size": {},
+                "layers": {},
+                "cost": "mse",
+                "optimizer": {{ "type": "sgd" }},
+                "scheduler": {{ "type": "none" }}
+            }}"#,
+            size, layers
+        ))
+        .unwrap()
+    }
+
+    fn invalid_index(result: NetsaurResult<Vec<Vec<usize>>>) -> usize {
+        match result {
+            Err(NetsaurError::InvalidLayer { index, .. }) => index,
+            other => panic!("expected an invalid layer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn infers_the_output_shape_of_every_layer() {
+        let config = config(
+            "[2, 1, 8, 8]",
+            r#"[
+                { "type": "conv2d", "config": { "kernelSize": [3, 1, 3, 3], "padding": [1, 1] } },
+                { "type": "pool2d", "config": { "mode": 1, "strides": [2, 2] } },
+                { "type": "flatten" },
+                { "type": "dense", "config": { "size": [10] } },
+                { "type": "softmax", "config": {} }
+            ]"#,
+        );
+        assert_eq!(
+            validate(&config).unwrap(),
+            [
+                vec![2, 3, 8, 8],
+                vec![2, 3, 4, 4],
+                vec![2, 48],
+                vec![2, 10],
+                vec![2, 10]
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_the_first_layer_that_does_not_fit() {
+        let dense_on_image = config(
+            "[2, 1, 8, 8]",
+            r#"[
+                { "type": "pool2d", "config": { "mode": 1, "strides": [2, 2] } },
+                { "type": "dense", "config": { "size": [10] } }
+            ]"#,
+        );
+        assert_eq!(invalid_index(validate(&dense_on_image)), 1);
+
+        let wrong_channels = config(
+            "[2, 3, 8, 8]",
+            r#"[{ "type": "conv2d", "config": { "kernelSize": [4, 1, 3, 3] } }]"#,
+        );
+        assert_eq!(invalid_index(validate(&wrong_channels)), 0);
+
+        for layer in ["conv2d", "convtranspose2d"] {
+            let short_kernel = config(
+                "[2, 1, 8, 8]",
+                &format!(
+                    r#"[{{
+                        "type": "{}",
+                        "config": {{
+                            "kernelSize": [2, 1, 3, 3],
+                            "kernel": {{ "data": [1, 2, 3], "shape": [2, 1, 3, 3] }}
+                        }}
+                    }}]"#,
+                    layer
+                ),
+            );
+            assert_eq!(invalid_index(validate(&short_kernel)), 0, "{}", layer);
+        }
+
+        let odd_pool = config(
+            "[2, 1, 5, 5]",
+            r#"[{ "type": "pool2d", "config": { "mode": 1, "strides": [2, 2] } }]"#,
+        );
+        assert_eq!(invalid_index(validate(&odd_pool)), 0);
+    }
+
+    #[test]
+    fn rejects_an_input_without_data_dimensions() {
+        let config = config("[4]", r#"[{ "type": "dense", "config": { "size": [1] } }]"#);
+        assert!(matches!(
+            validate(&config),
+            Err(NetsaurError::ShapeMismatch { .. })
+        ));
+    }
+}
@@ -1,8 +1,12 @@
 use js_sys::{Array, Float32Array, Uint8Array};
 use ndarray::ArrayD;
-use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+use wasm_bindgen::{prelude::wasm_bindgen, JsError, JsValue};
 
-use crate::{Backend, Dataset, Logger, PredictOptions, Timer, TrainOptions, RESOURCES};
+use crate::{
+    insert_backend, remove_backend, validate, with_backend, with_backend_ref, Backend,
+    BackendConfig, Dataset, EvaluateOptions, LayerEntry, Logger, NetsaurError, NetsaurResult,
+    PredictOptions, TensorMapping, Timer, TrainOptions,
+};
 
 #[wasm_bindgen]
 extern "C" {
@@ -22,9 +26,9 @@ fn performance_now() -> u128 {
 }
 
 #[wasm_bindgen]
-pub fn wasm_backend_create(config: String, shape: Array) -> usize {
-    let config = serde_json::from_str(&config).unwrap();
-    let mut len = 0;
+pub fn wasm_backend_create(config: String, shape: Array) -> Result<usize, JsError> {
+    let config = serde_json::from_str(&config).map_err(NetsaurError::from)?;
+    validate(&config)?;
     let logger = Logger { log: console_log };
     let net_backend = Backend::new(
         config,
@@ -39,74 +43,259 @@ pub fn wasm_backend_create(config: String, shape: Array) -> usize {
         shape.set(i as u32, JsValue::from(*s))
     }
 
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        len = backend.len();
-        backend.push(net_backend);
-    });
-    len
-}
-
-#[wasm_bindgen]
-pub fn wasm_backend_train(id: usize, buffers: Vec<Float32Array>, options: String) {
-    let options: TrainOptions = serde_json::from_str(&options).unwrap();
-    let mut datasets = Vec::new();
-    for i in 0..options.datasets {
-        let input = buffers[i * 2].to_vec();
-        let output = buffers[i * 2 + 1].to_vec();
-        datasets.push(Dataset {
-            inputs: ArrayD::from_shape_vec(options.input_shape.clone(), input).unwrap(),
-            outputs: ArrayD::from_shape_vec(options.output_shape.clone(), output).unwrap(),
-        });
+    Ok(insert_backend(net_backend))
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_validate(config: String, shape: Array) -> Result<(), JsError> {
+    let config: BackendConfig = serde_json::from_str(&config).map_err(NetsaurError::from)?;
+    let shapes = validate(&config)?;
+    let size = shapes.last().unwrap_or(&config.size);
+    shape.set_length(size.len() as u32);
+    for (i, s) in size.iter().enumerate() {
+        shape.set(i as u32, JsValue::from(*s))
+    }
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_train(
+    id: usize,
+    buffers: Vec<Float32Array>,
+    options: String,
+) -> Result<(), JsError> {
+    let options: TrainOptions = serde_json::from_str(&options).map_err(NetsaurError::from)?;
+    let validation_datasets = options.validation_datasets.unwrap_or(0);
+    if buffers.len() < (options.datasets + validation_datasets) * 2 {
+        return Err(NetsaurError::ShapeMismatch {
+            msg: "missing dataset buffers",
+            expected: vec![(options.datasets + validation_datasets) * 2],
+            got: vec![buffers.len()],
+        }
+        .into());
+    }
+    let decode = |i: usize, input_shape: &Vec<usize>, output_shape: &Vec<usize>| {
+        Ok(Dataset {
+            inputs: ArrayD::from_shape_vec(input_shape.clone(), buffers[i * 2].to_vec())?,
+            outputs: ArrayD::from_shape_vec(output_shape.clone(), buffers[i * 2 + 1].to_vec())?,
+        })
+    };
+    let datasets = (0..options.datasets)
+        .map(|i| decode(i, &options.input_shape, &options.output_shape))
+        .collect::<NetsaurResult<Vec<_>>>()?;
+    let validation_input_shape = options
+        .validation_input_shape
+        .as_ref()
+        .unwrap_or(&options.input_shape);
+    let validation_output_shape = options
+        .validation_output_shape
+        .as_ref()
+        .unwrap_or(&options.output_shape);
+    let validation = (options.datasets..options.datasets + validation_datasets)
+        .map(|i| decode(i, validation_input_shape, validation_output_shape))
+        .collect::<NetsaurResult<Vec<_>>>()?;
+    with_backend(id, |backend| backend.train(datasets, validation, &options))?;
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_evaluate(
+    id: usize,
+    buffers: Vec<Float32Array>,
+    options: String,
+) -> Result<String, JsError> {
+    let options: EvaluateOptions = serde_json::from_str(&options).map_err(NetsaurError::from)?;
+    if buffers.len() < options.datasets * 2 {
+        return Err(NetsaurError::ShapeMismatch {
+            msg: "missing dataset buffers",
+            expected: vec![options.datasets * 2],
+            got: vec![buffers.len()],
+        }
+        .into());
+    }
+    let datasets = (0..options.datasets)
+        .map(|i| {
+            Ok(Dataset {
+                inputs: ArrayD::from_shape_vec(
+                    options.input_shape.clone(),
+                    buffers[i * 2].to_vec(),
+                )?,
+                outputs: ArrayD::from_shape_vec(
+                    options.output_shape.clone(),
+                    buffers[i * 2 + 1].to_vec(),
+                )?,
+            })
+        })
+        .collect::<NetsaurResult<Vec<_>>>()?;
+    let report = with_backend(id, |backend| {
+        let report = backend.evaluate(&datasets, &options.metrics)?;
+        Ok(serde_json::to_string(&report)?)
+    })?;
+    Ok(report)
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_predict(
+    id: usize,
+    buffer: Float32Array,
+    options: String,
+) -> Result<Float32Array, JsError> {
+    let options: PredictOptions = serde_json::from_str(&options).map_err(NetsaurError::from)?;
+    let inputs =
+        ArrayD::from_shape_vec(options.input_shape, buffer.to_vec()).map_err(NetsaurError::from)?;
+
+    let res = with_backend_ref(id, |backend| {
+        let res = backend.predict(inputs, options.post_process, options.layers)?;
+        Ok(ArrayD::from_shape_vec(
+            options.output_shape,
+            res.iter().copied().collect(),
+        )?)
+    })?;
+    Ok(Float32Array::from(res.as_slice().unwrap()))
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_save(id: usize) -> Result<Uint8Array, JsError> {
+    let buffer = with_backend_ref(id, |backend| Ok(backend.save()))?;
+    Ok(Uint8Array::from(buffer.as_slice()))
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_export_onnx(id: usize) -> Result<Uint8Array, JsError> {
+    let buffer = with_backend_ref(id, |backend| backend.export_onnx())?;
+    Ok(Uint8Array::from(buffer.as_slice()))
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_checkpoint(id: usize) -> Result<Uint8Array, JsError> {
+    let buffer = with_backend_ref(id, |backend| Ok(backend.checkpoint()))?;
+    Ok(Uint8Array::from(buffer.as_slice()))
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_free(id: usize) -> Result<(), JsError> {
+    remove_backend(id)?;
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_freeze(id: usize, start: usize, end: usize) -> Result<(), JsError> {
+    with_backend(id, |backend| backend.freeze(start..end))?;
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_unfreeze(id: usize, start: usize, end: usize) -> Result<(), JsError> {
+    with_backend(id, |backend| backend.unfreeze(start..end))?;
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_truncate(id: usize, len: usize, shape: Array) -> Result<(), JsError> {
+    let size = with_backend(id, |backend| {
+        backend.truncate(len)?;
+        Ok(backend.size.clone())
+    })?;
+    shape.set_length(size.len() as u32);
+    for (i, s) in size.iter().enumerate() {
+        shape.set(i as u32, JsValue::from(*s))
+    }
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_append(id: usize, layers: String, shape: Array) -> Result<(), JsError> {
+    let layers: Vec<LayerEntry> = serde_json::from_str(&layers).map_err(NetsaurError::from)?;
+    let size = with_backend(id, |backend| {
+        backend.append(layers)?;
+        Ok(backend.size.clone())
+    })?;
+    shape.set_length(size.len() as u32);
+    for (i, s) in size.iter().enumerate() {
+        shape.set(i as u32, JsValue::from(*s))
     }
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        backend[id].train(datasets, options.epochs, options.batches, options.rate)
-    });
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn wasm_backend_summary(id: usize) -> Result<String, JsError> {
+    let summary = with_backend_ref(id, |backend| {
+        Ok(serde_json::to_string(&backend.summary()?)?)
+    })?;
+    Ok(summary)
 }
 
 #[wasm_bindgen]
-pub fn wasm_backend_predict(id: usize, buffer: Float32Array, options: String) -> Float32Array {
-    let options: PredictOptions = serde_json::from_str(&options).unwrap();
-    let inputs = ArrayD::from_shape_vec(options.input_shape, buffer.to_vec()).unwrap();
+pub fn wasm_backend_load(buffer: Uint8Array, shape: Array) -> Result<usize, JsError> {
+    let logger = Logger { log: console_log };
+    let timer = Timer {
+        now: performance_now,
+    };
+    let net_backend = Backend::load(buffer.to_vec().as_slice(), logger, timer)?;
+    shape.set_length(net_backend.size.len() as u32);
+    for (i, s) in net_backend.size.iter().enumerate() {
+        shape.set(i as u32, JsValue::from(*s))
+    }
+
+    Ok(insert_backend(net_backend))
+}
 
-    let mut res = ArrayD::zeros(options.output_shape.clone());
+#[wasm_bindgen]
+pub fn wasm_backend_resume(buffer: Uint8Array, shape: Array) -> Result<usize, JsError> {
+    let logger = Logger { log: console_log };
+    let timer = Timer {
+        now: performance_now,
+    };
+    let net_backend = Backend::resume(buffer.to_vec().as_slice(), logger, timer)?;
+    shape.set_length(net_backend.size.len() as u32);
+    for (i, s) in net_backend.size.iter().enumerate() {
+        shape.set(i as u32, JsValue::from(*s))
+    }
 
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        let _res = backend[id].predict(inputs, options.post_process, options.layers);
-        res.assign(&ArrayD::from_shape_vec(options.output_shape, _res.as_slice().unwrap().to_vec()).unwrap());
-    });
-    Float32Array::from(res.as_slice().unwrap())
+    Ok(insert_backend(net_backend))
 }
 
 #[wasm_bindgen]
-pub fn wasm_backend_save(id: usize) -> Uint8Array {
-    let mut buffer = Vec::new();
-    RESOURCES.with(|cell| {
-        let backend = cell.backend.borrow_mut();
-        buffer = backend[id].save();
-    });
-    Uint8Array::from(buffer.as_slice())
+pub fn wasm_backend_load_onnx(buffer: Uint8Array, shape: Array) -> Result<usize, JsError> {
+    let logger = Logger { log: console_log };
+    let timer = Timer {
+        now: performance_now,
+    };
+    let net_backend = Backend::load_onnx(buffer.to_vec().as_slice(), logger, timer)?;
+    shape.set_length(net_backend.size.len() as u32);
+    for (i, s) in net_backend.size.iter().enumerate() {
+        shape.set(i as u32, JsValue::from(*s))
+    }
+
+    Ok(insert_backend(net_backend))
 }
 
+/// Loads foreign weights, see `Backend::load_pretrained`. The JSON report is
+/// pushed onto `report`.
 #[wasm_bindgen]
-pub fn wasm_backend_load(buffer: Uint8Array, shape: Array) -> usize {
-    let mut len = 0;
+pub fn wasm_backend_load_pretrained(
+    config: String,
+    buffer: Uint8Array,
+    mappings: String,
+    shape: Array,
+    report: Array,
+) -> Result<usize, JsError> {
+    let config = serde_json::from_str(&config).map_err(NetsaurError::from)?;
+    let mappings: Vec<TensorMapping> =
+        serde_json::from_str(&mappings).map_err(NetsaurError::from)?;
     let logger = Logger { log: console_log };
     let timer = Timer {
         now: performance_now,
     };
-    let net_backend = Backend::load(buffer.to_vec().as_slice(), logger, timer);
+    let (net_backend, loaded) =
+        Backend::load_pretrained(config, buffer.to_vec().as_slice(), &mappings, logger, timer)?;
+    report.push(&JsValue::from(
+        serde_json::to_string(&loaded).map_err(NetsaurError::from)?,
+    ));
     shape.set_length(net_backend.size.len() as u32);
     for (i, s) in net_backend.size.iter().enumerate() {
         shape.set(i as u32, JsValue::from(*s))
     }
 
-    RESOURCES.with(|cell| {
-        let mut backend = cell.backend.borrow_mut();
-        len = backend.len();
-        backend.push(net_backend);
-    });
-    len
+    Ok(insert_backend(net_backend))
 }
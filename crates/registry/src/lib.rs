@@ -0,0 +1,116 @@
+//! Generational slot map holding the backends of the netsaur bindings.
+
+/// Number of low bits of an id that hold the slot index, the remaining bits
+/// hold the generation of the slot.
+const INDEX_BITS: u32 = usize::BITS / 2;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+
+struct Slot<T> {
+    generation: usize,
+    value: Option<T>,
+}
+
+/// Slot map handing out ids that pair a slot index with a generation counter.
+/// Removing a value bumps the generation of its slot, so ids that outlive
+/// their value are rejected instead of resolving to whatever reuses the slot.
+pub struct Registry<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+}
+
+impl<T> Registry<T> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> usize {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: None,
+                });
+                self.slots.len() - 1
+            }
+        };
+        let slot = &mut self.slots[index];
+        slot.value = Some(value);
+        (slot.generation << INDEX_BITS) | index
+    }
+
+    pub fn get(&self, id: usize) -> Option<&T> {
+        let slot = self.slots.get(id & INDEX_MASK)?;
+        if slot.generation != id >> INDEX_BITS {
+            return None;
+        }
+        slot.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut T> {
+        let slot = self.slots.get_mut(id & INDEX_MASK)?;
+        if slot.generation != id >> INDEX_BITS {
+            return None;
+        }
+        slot.value.as_mut()
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<T> {
+        let index = id & INDEX_MASK;
+        let slot = self.slots.get_mut(index)?;
+        if slot.generation != id >> INDEX_BITS {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation = (slot.generation + 1) & (usize::MAX >> INDEX_BITS);
+        self.free.push(index);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_ids_of_removed_values() {
+        let mut registry = Registry::new();
+        let first = registry.insert("first");
+        assert_eq!(registry.remove(first), Some("first"));
+        let second = registry.insert("second");
+        assert_eq!(second & INDEX_MASK, first & INDEX_MASK);
+        assert_ne!(second, first);
+        assert_eq!(registry.get(first), None);
+        assert_eq!(registry.get_mut(first), None);
+        assert_eq!(registry.remove(first), None);
+        assert_eq!(registry.get(second), Some(&"second"));
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_ids() {
+        let mut registry = Registry::new();
+        let id = registry.insert(1);
+        assert_eq!(registry.get(id + 1), None);
+        assert_eq!(registry.remove(id), Some(1));
+        assert_eq!(registry.remove(id), None);
+        assert!(registry.is_empty());
+    }
+}
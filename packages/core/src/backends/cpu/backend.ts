@@ -1,3 +1,4 @@
+import type { Layer } from "../../core/api/layer.ts";
 import type { Rank, Shape } from "../../core/api/shape.ts";
 import type { Backend, DataSet, NetworkConfig } from "../../core/types.ts";
 import type { Library } from "./mod.ts";
@@ -5,9 +6,17 @@ import { Tensor } from "../../core/tensor/tensor.ts";
 import { length } from "../../core/tensor/util.ts";
 import {
   Buffer,
+  check,
   encodeDatasets,
   encodeJSON,
+  type EvaluateOptions,
+  type Metric,
+  type MetricsReport,
   type PredictOptions,
+  type PretrainedReport,
+  type Summary,
+  type TensorMapping,
+  type TrainConfig,
   type TrainOptions,
 } from "./util.ts";
 import type { PostProcessor } from "../../core/api/postprocess.ts";
@@ -33,15 +42,40 @@ export class CPUBackend implements Backend {
   static create(config: NetworkConfig, library: Library): CPUBackend {
     const buffer = encodeJSON(config);
     const shape = new Buffer();
-    const id = library.symbols.ffi_backend_create(
-      buffer,
-      BigInt(buffer.length),
-      shape.allocBuffer,
-    ) as bigint;
+    const id = new BigUint64Array(1);
+    check(
+      library,
+      library.symbols.ffi_backend_create(
+        buffer,
+        BigInt(buffer.length),
+        shape.allocBuffer,
+        id,
+      ),
+    );
     const outputShape = Array.from(
       new Uint32Array(shape.buffer.slice(4).buffer),
     ) as Shape<Rank>;
-    return new CPUBackend(library, outputShape, id);
+    return new CPUBackend(library, outputShape, id[0]);
+  }
+
+  /**
+   * Checks that the layers of a config fit together and returns the
+   * output shape of the network.
+   */
+  static validate(config: NetworkConfig, library: Library): Shape<Rank> {
+    const buffer = encodeJSON(config);
+    const shape = new Buffer();
+    check(
+      library,
+      library.symbols.ffi_backend_validate(
+        buffer,
+        BigInt(buffer.length),
+        shape.allocBuffer,
+      ),
+    );
+    return Array.from(
+      new Uint32Array(shape.buffer.slice(4).buffer),
+    ) as Shape<Rank>;
   }
 
   train(
@@ -49,8 +83,10 @@ export class CPUBackend implements Backend {
     epochs: number,
     batches: number,
     rate: number,
+    config: TrainConfig = {},
   ): void {
-    const buffer = encodeDatasets(datasets);
+    const { validationData = [], ...rest } = config;
+    const buffer = encodeDatasets([...datasets, ...validationData]);
     const options = encodeJSON({
       datasets: datasets.length,
       inputShape: datasets[0].inputs.shape,
@@ -58,15 +94,48 @@ export class CPUBackend implements Backend {
       epochs,
       batches,
       rate,
+      validationDatasets: validationData.length,
+      validationInputShape: validationData[0]?.inputs.shape,
+      validationOutputShape: validationData[0]?.outputs.shape,
+      ...rest,
     } as TrainOptions);
 
-    this.library.symbols.ffi_backend_train(
-      this.#id,
-      buffer,
-      BigInt(buffer.byteLength),
-      options,
-      BigInt(options.byteLength),
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_train(
+        this.#id,
+        buffer,
+        BigInt(buffer.byteLength),
+        options,
+        BigInt(options.byteLength),
+      ),
+    );
+  }
+
+  /**
+   * Computes the loss and the requested metrics over the datasets.
+   */
+  evaluate(datasets: DataSet[], metrics: Metric[] = []): MetricsReport {
+    const buffer = encodeDatasets(datasets);
+    const options = encodeJSON({
+      datasets: datasets.length,
+      inputShape: datasets[0].inputs.shape,
+      outputShape: datasets[0].outputs.shape,
+      metrics,
+    } as EvaluateOptions);
+    const report = new Buffer();
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_evaluate(
+        this.#id,
+        buffer,
+        BigInt(buffer.byteLength),
+        options,
+        BigInt(options.byteLength),
+        report.allocBuffer,
+      ),
     );
+    return JSON.parse(new TextDecoder().decode(report.buffer));
   }
 
   async predict(input: Tensor<Rank>, config: {postProcess: PostProcessor, outputShape?: Shape<Rank>}): Promise<Tensor<Rank>>;
@@ -90,12 +159,15 @@ export class CPUBackend implements Backend {
     const output = new Float32Array(
       input.shape[0] * length(config.outputShape ?? this.outputShape),
     );
-    this.library.symbols.ffi_backend_predict(
-      this.#id,
-      input.data as Float32Array,
-      options,
-      BigInt(options.length),
-      output,
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_predict(
+        this.#id,
+        input.data as Float32Array,
+        options,
+        BigInt(options.length),
+        output,
+      ),
     );
     return new Tensor(
       output,
@@ -108,27 +180,231 @@ export class CPUBackend implements Backend {
 
   save(): Uint8Array {
     const shape = new Buffer();
-    this.library.symbols.ffi_backend_save(this.#id, shape.allocBuffer);
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_save(this.#id, shape.allocBuffer),
+    );
     return shape.buffer;
   }
 
+  /**
+   * Saves the weights together with the optimizer state and the training
+   * progress.
+   */
+  checkpoint(): Uint8Array {
+    const buffer = new Buffer();
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_checkpoint(this.#id, buffer.allocBuffer),
+    );
+    return buffer.buffer;
+  }
+
+  /**
+   * Exports the model as an ONNX graph for runtimes without netsaur.
+   */
+  exportOnnx(): Uint8Array {
+    const buffer = new Buffer();
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_export_onnx(this.#id, buffer.allocBuffer),
+    );
+    return buffer.buffer;
+  }
+
+  summary(): Summary {
+    const buffer = new Buffer();
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_summary(this.#id, buffer.allocBuffer),
+    );
+    return JSON.parse(new TextDecoder().decode(buffer.buffer));
+  }
+
+  /**
+   * Freezes the layers from `start` up to but excluding `end`, so training
+   * keeps their weights and BatchNorm statistics fixed.
+   */
+  freeze(start: number, end: number): void {
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_freeze(
+        this.#id,
+        BigInt(start),
+        BigInt(end),
+      ),
+    );
+  }
+
+  /**
+   * Makes the layers from `start` up to but excluding `end` trainable again.
+   */
+  unfreeze(start: number, end: number): void {
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_unfreeze(
+        this.#id,
+        BigInt(start),
+        BigInt(end),
+      ),
+    );
+  }
+
+  /**
+   * Drops every layer from index `len` on, e.g. to remove the head of a
+   * pretrained classifier.
+   */
+  truncate(len: number): void {
+    const shape = new Buffer();
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_truncate(
+        this.#id,
+        BigInt(len),
+        shape.allocBuffer,
+      ),
+    );
+    this.outputShape = Array.from(
+      new Uint32Array(shape.buffer.slice(4).buffer),
+    ) as Shape<Rank>;
+  }
+
+  /**
+   * Appends freshly initialized layers. The optimizer state and training
+   * progress start over.
+   */
+  append(layers: Layer[]): void {
+    const buffer = encodeJSON(layers);
+    const shape = new Buffer();
+    check(
+      this.library,
+      this.library.symbols.ffi_backend_append(
+        this.#id,
+        buffer,
+        BigInt(buffer.length),
+        shape.allocBuffer,
+      ),
+    );
+    this.outputShape = Array.from(
+      new Uint32Array(shape.buffer.slice(4).buffer),
+    ) as Shape<Rank>;
+  }
+
+  /**
+   * Releases the native model. The backend must not be used afterwards.
+   */
+  free(): void {
+    check(this.library, this.library.symbols.ffi_backend_free(this.#id));
+  }
+
   saveFile(path: string): void {
     Deno.writeFileSync(path, this.save());
   }
 
   static load(buffer: Uint8Array, library: Library): CPUBackend {
     const shape = new Buffer();
-    const id = library.symbols.ffi_backend_load(
-      buffer,
-      BigInt(buffer.length),
-      shape.allocBuffer,
-    ) as bigint;
-    const outputShape = Array.from(shape.buffer.slice(1)) as Shape<Rank>;
+    const id = new BigUint64Array(1);
+    check(
+      library,
+      library.symbols.ffi_backend_load(
+        buffer,
+        BigInt(buffer.length),
+        shape.allocBuffer,
+        id,
+      ),
+    );
+    const outputShape = Array.from(
+      new Uint32Array(shape.buffer.slice(4).buffer),
+    ) as Shape<Rank>;
 
-    return new CPUBackend(library, outputShape, id);
+    return new CPUBackend(library, outputShape, id[0]);
   }
 
   static loadFile(path: string, library: Library): CPUBackend {
     return this.load(Deno.readFileSync(path), library);
   }
+
+  /**
+   * Loads a checkpoint so that training continues where it stopped.
+   */
+  static resume(buffer: Uint8Array, library: Library): CPUBackend {
+    const shape = new Buffer();
+    const id = new BigUint64Array(1);
+    check(
+      library,
+      library.symbols.ffi_backend_resume(
+        buffer,
+        BigInt(buffer.length),
+        shape.allocBuffer,
+        id,
+      ),
+    );
+    const outputShape = Array.from(
+      new Uint32Array(shape.buffer.slice(4).buffer),
+    ) as Shape<Rank>;
+
+    return new CPUBackend(library, outputShape, id[0]);
+  }
+
+  /**
+   * Builds a model from an ONNX file whose nodes form a single chain of
+   * layers netsaur supports. Unsupported nodes are reported by name.
+   */
+  static loadOnnx(buffer: Uint8Array, library: Library): CPUBackend {
+    const shape = new Buffer();
+    const id = new BigUint64Array(1);
+    check(
+      library,
+      library.symbols.ffi_backend_load_onnx(
+        buffer,
+        BigInt(buffer.length),
+        shape.allocBuffer,
+        id,
+      ),
+    );
+    const outputShape = Array.from(
+      new Uint32Array(shape.buffer.slice(4).buffer),
+    ) as Shape<Rank>;
+
+    return new CPUBackend(library, outputShape, id[0]);
+  }
+
+  /**
+   * Creates a model from a config and fills it with the weights of a
+   * safetensors file written by another framework.
+   */
+  static loadPretrained(
+    config: NetworkConfig,
+    buffer: Uint8Array,
+    mappings: TensorMapping[],
+    library: Library,
+  ): [CPUBackend, PretrainedReport] {
+    const configBuffer = encodeJSON(config);
+    const mappingsBuffer = encodeJSON(mappings);
+    const shape = new Buffer();
+    const report = new Buffer();
+    const id = new BigUint64Array(1);
+    check(
+      library,
+      library.symbols.ffi_backend_load_pretrained(
+        configBuffer,
+        BigInt(configBuffer.length),
+        buffer,
+        BigInt(buffer.length),
+        mappingsBuffer,
+        BigInt(mappingsBuffer.length),
+        shape.allocBuffer,
+        report.allocBuffer,
+        id,
+      ),
+    );
+    const outputShape = Array.from(
+      new Uint32Array(shape.buffer.slice(4).buffer),
+    ) as Shape<Rank>;
+
+    return [
+      new CPUBackend(library, outputShape, id[0]),
+      JSON.parse(new TextDecoder().decode(report.buffer)),
+    ];
+  }
 }
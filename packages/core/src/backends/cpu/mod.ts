@@ -23,25 +23,91 @@ const options: FetchOptions = {
 };
 
 const symbols = {
+  ffi_backend_error: {
+    parameters: ["pointer"],
+    result: "void",
+  } as const,
   ffi_backend_create: {
+    parameters: ["buffer", "usize", "pointer", "buffer"],
+    result: "i32",
+  } as const,
+  ffi_backend_validate: {
     parameters: ["buffer", "usize", "pointer"],
-    result: "usize",
+    result: "i32",
   } as const,
   ffi_backend_train: {
     parameters: ["usize", "buffer", "usize", "buffer", "usize"],
-    result: "void",
+    result: "i32",
+  } as const,
+  ffi_backend_evaluate: {
+    parameters: ["usize", "buffer", "usize", "buffer", "usize", "pointer"],
+    result: "i32",
   } as const,
   ffi_backend_predict: {
     parameters: ["usize", "buffer", "buffer", "usize", "buffer"],
-    result: "void",
+    result: "i32",
   } as const,
   ffi_backend_save: {
     parameters: ["usize", "pointer"],
-    result: "void",
+    result: "i32",
+  } as const,
+  ffi_backend_free: {
+    parameters: ["usize"],
+    result: "i32",
+  } as const,
+  ffi_backend_export_onnx: {
+    parameters: ["usize", "pointer"],
+    result: "i32",
+  } as const,
+  ffi_backend_checkpoint: {
+    parameters: ["usize", "pointer"],
+    result: "i32",
+  } as const,
+  ffi_backend_freeze: {
+    parameters: ["usize", "usize", "usize"],
+    result: "i32",
+  } as const,
+  ffi_backend_unfreeze: {
+    parameters: ["usize", "usize", "usize"],
+    result: "i32",
+  } as const,
+  ffi_backend_truncate: {
+    parameters: ["usize", "usize", "pointer"],
+    result: "i32",
+  } as const,
+  ffi_backend_append: {
+    parameters: ["usize", "buffer", "usize", "pointer"],
+    result: "i32",
+  } as const,
+  ffi_backend_summary: {
+    parameters: ["usize", "pointer"],
+    result: "i32",
   } as const,
   ffi_backend_load: {
-    parameters: ["buffer", "usize", "pointer"],
-    result: "usize",
+    parameters: ["buffer", "usize", "pointer", "buffer"],
+    result: "i32",
+  } as const,
+  ffi_backend_resume: {
+    parameters: ["buffer", "usize", "pointer", "buffer"],
+    result: "i32",
+  } as const,
+  ffi_backend_load_onnx: {
+    parameters: ["buffer", "usize", "pointer", "buffer"],
+    result: "i32",
+  } as const,
+  ffi_backend_load_pretrained: {
+    parameters: [
+      "buffer",
+      "usize",
+      "buffer",
+      "usize",
+      "buffer",
+      "usize",
+      "pointer",
+      "pointer",
+      "buffer",
+    ],
+    result: "i32",
   } as const,
 };
 
@@ -1,5 +1,7 @@
 import type { Rank, Shape } from "../../core/api/shape.ts";
 import type { DataSet } from "../../core/types.ts";
+import { BackendError } from "../../core/api/error.ts";
+import type { Library } from "./mod.ts";
 
 export class Buffer {
   buffer: Uint8Array = new Uint8Array();
@@ -15,6 +17,16 @@ export class Buffer {
   }).pointer;
 }
 
+/**
+ * Throws the last backend error if a call did not succeed.
+ */
+export function check(library: Library, status: number): void {
+  if (status === 0) return;
+  const message = new Buffer();
+  library.symbols.ffi_backend_error(message.allocBuffer);
+  throw new BackendError(status, new TextDecoder().decode(message.buffer));
+}
+
 /**
  * Train Options Interface.
  */
@@ -25,6 +37,74 @@ export type TrainOptions = {
   epochs: number;
   batches: number;
   rate: number;
+  validationDatasets?: number;
+  validationInputShape?: Shape<Rank>;
+  validationOutputShape?: Shape<Rank>;
+  validationSplit?: number;
+  metrics?: Metric[];
+  resume?: boolean;
+  batchSize?: number;
+  shuffle?: boolean;
+  seed?: number;
+};
+
+/**
+ * Optional settings of `CPUBackend.train`.
+ */
+export type TrainConfig = {
+  /** Datasets to compute the validation loss and metrics on. */
+  validationData?: DataSet[];
+  /** Fraction of the training samples to hold out for validation instead. */
+  validationSplit?: number;
+  /** Metrics to log after every epoch. */
+  metrics?: Metric[];
+  /** Continues from the epoch and optimizer state of a checkpoint. */
+  resume?: boolean;
+  /** Slices the datasets into minibatches of this many samples. */
+  batchSize?: number;
+  /** Shuffles the samples or datasets every epoch. */
+  shuffle?: boolean;
+  /** Seed of the shuffling and dropout, overriding the config seed. */
+  seed?: number;
+};
+
+/**
+ * Metric to compute when evaluating a model.
+ */
+export type Metric =
+  | { type: "accuracy" }
+  | { type: "topkaccuracy"; config: { k: number } }
+  | { type: "precision" }
+  | { type: "recall" }
+  | { type: "f1" }
+  | { type: "confusionmatrix" }
+  | { type: "mae" }
+  | { type: "rmse" }
+  | { type: "r2" }
+  | { type: "rocauc" };
+
+export type EvaluateOptions = {
+  datasets: number;
+  inputShape: Shape<Rank>;
+  outputShape: Shape<Rank>;
+  metrics: Metric[];
+};
+
+/**
+ * Metrics Report Interface.
+ */
+export type MetricsReport = {
+  loss: number;
+  accuracy?: number;
+  topKAccuracy?: number;
+  precision?: number[];
+  recall?: number[];
+  f1?: number[];
+  confusionMatrix?: number[][];
+  mae?: number;
+  rmse?: number;
+  r2?: number;
+  rocAuc?: number;
 };
 
 /**
@@ -35,6 +115,76 @@ export type PredictOptions = {
   outputShape: Shape<Rank>;
 };
 
+/**
+ * Layer Summary Interface.
+ */
+export type LayerSummary = {
+  layer: string;
+  name: string;
+  inputShape: number[];
+  outputShape: number[];
+  trainableParams: number;
+  nonTrainableParams: number;
+};
+
+/**
+ * Model Summary Interface.
+ */
+export type Summary = {
+  layers: LayerSummary[];
+  trainableParams: number;
+  nonTrainableParams: number;
+  totalParams: number;
+  parameterBytes: number;
+  gradientBytes: number;
+  optimizerBytes: number;
+};
+
+/**
+ * Layer parameter that a pretrained tensor is loaded into.
+ */
+export type LayerParam =
+  | "weight"
+  | "bias"
+  | "gamma"
+  | "beta"
+  | "running_mean"
+  | "running_var"
+  | "embeddings"
+  | "w_ih"
+  | "w_hh";
+
+/**
+ * Maps a tensor of a foreign safetensors file onto a layer parameter.
+ */
+export type TensorMapping = {
+  /** Name of the tensor in the file, e.g. `fc1.weight`. */
+  source: string;
+  /** Index of the layer in the config. */
+  layer: number;
+  param: LayerParam;
+  /** Reverses the axes, e.g. for PyTorch `Linear` weights. */
+  transpose?: boolean;
+  /** Reorders the axes, e.g. `[3, 2, 0, 1]` for Keras conv kernels. */
+  permute?: number[];
+};
+
+/**
+ * Parameters that kept their initial values and file tensors that were
+ * not used when loading pretrained weights.
+ */
+export type PretrainedReport = {
+  missing: { layer: number; param: LayerParam; source?: string }[];
+  mismatched: {
+    layer: number;
+    param: LayerParam;
+    source: string;
+    expected: number[];
+    got: number[];
+  }[];
+  unused: string[];
+};
+
 /**
  * Encode JSON data.
  */
@@ -3,6 +3,7 @@ import type { Backend, DataSet, NetworkConfig } from "../../core/types.ts";
 import type { Library } from "./mod.ts";
 import { length } from "../../core/tensor/util.ts";
 import { Tensor } from "../../core/tensor/tensor.ts";
+import { BackendError } from "../../core/api/error.ts";
 import {
   Buffer,
   encodeDatasets,
@@ -54,12 +55,14 @@ export class GPUBackend implements Backend {
       rate,
     } as TrainOptions);
 
-    this.library.symbols.ffi_backend_train(
-      this.#id,
-      buffer,
-      BigInt(buffer.byteLength),
-      options,
-      BigInt(options.byteLength),
+    this.#check(
+      this.library.symbols.ffi_backend_train(
+        this.#id,
+        buffer,
+        BigInt(buffer.byteLength),
+        options,
+        BigInt(options.byteLength),
+      ),
     );
   }
 
@@ -83,12 +86,14 @@ export class GPUBackend implements Backend {
     const output = new Float32Array(
       input.shape[0] * length(outputShape ?? this.outputShape),
     );
-    this.library.symbols.ffi_backend_predict(
-      this.#id,
-      input.data as Float32Array,
-      options,
-      BigInt(options.length),
-      output,
+    this.#check(
+      this.library.symbols.ffi_backend_predict(
+        this.#id,
+        input.data as Float32Array,
+        options,
+        BigInt(options.length),
+        output,
+      ),
     );
     return new Tensor(
       output,
@@ -101,10 +106,28 @@ export class GPUBackend implements Backend {
 
   save(): Uint8Array {
     const shape = new Buffer();
-    this.library.symbols.ffi_backend_save(this.#id, shape.allocBuffer);
+    this.#check(
+      this.library.symbols.ffi_backend_save(this.#id, shape.allocBuffer),
+    );
     return shape.buffer;
   }
 
+  /**
+   * Releases the native model. The backend must not be used afterwards.
+   */
+  free(): void {
+    this.#check(this.library.symbols.ffi_backend_free(this.#id));
+  }
+
+  /**
+   * Throws if the native model was not found, e.g. after `free`.
+   */
+  #check(found: boolean): void {
+    if (!found) {
+      throw new BackendError(3, `Backend #${this.#id} does not exist.`);
+    }
+  }
+
   saveFile(path: string): void {
     Deno.writeFileSync(path, this.save());
   }
@@ -29,15 +29,19 @@ const symbols = {
   } as const,
   ffi_backend_train: {
     parameters: ["usize", "buffer", "usize", "buffer", "usize"],
-    result: "void",
+    result: "bool",
   } as const,
   ffi_backend_predict: {
     parameters: ["usize", "buffer", "buffer", "usize", "buffer"],
-    result: "void",
+    result: "bool",
   } as const,
   ffi_backend_save: {
     parameters: ["usize", "pointer"],
-    result: "void",
+    result: "bool",
+  } as const,
+  ffi_backend_free: {
+    parameters: ["usize"],
+    result: "bool",
   } as const,
   ffi_backend_load: {
     parameters: ["buffer", "usize", "pointer"],
@@ -4,6 +4,7 @@ import type { PredictOptions, TrainOptions } from "./utils.ts";
 import { Tensor } from "../../core/tensor/tensor.ts";
 import {
   wasm_backend_create,
+  wasm_backend_free,
   wasm_backend_load,
   wasm_backend_predict,
   wasm_backend_save,
@@ -92,6 +93,14 @@ export class WASMBackend implements Backend {
     return wasm_backend_save(this.#id);
   }
 
+  /**
+   * Releases the model held by the Wasm module. The backend must not be used
+   * afterwards.
+   */
+  free(): void {
+    wasm_backend_free(this.#id);
+  }
+
   saveFile(input: string): void {
     Deno.writeFileSync(input, this.save());
   }
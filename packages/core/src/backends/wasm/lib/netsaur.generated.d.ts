@@ -8,7 +8,8 @@ export interface InstantiateResult {
     wasm_backend_train: typeof wasm_backend_train;
     wasm_backend_predict: typeof wasm_backend_predict;
     wasm_backend_save: typeof wasm_backend_save;
-    wasm_backend_load: typeof wasm_backend_load
+    wasm_backend_load: typeof wasm_backend_load,
+    wasm_backend_free: typeof wasm_backend_free
   };
 }
 
@@ -63,3 +64,7 @@ export function wasm_backend_save(id: number): Uint8Array;
 * @returns {number}
 */
 export function wasm_backend_load(buffer: Uint8Array, shape: Array<any>): number;
+/**
+* @param {number} id
+*/
+export function wasm_backend_free(id: number): void;
@@ -210,6 +210,29 @@ export function wasm_backend_load(buffer, shape) {
   return ret >>> 0;
 }
 
+function getInt32Memory0() {
+  if (cachedInt32Memory0 == null || cachedInt32Memory0.byteLength === 0) {
+    cachedInt32Memory0 = new Int32Array(wasm.memory.buffer);
+  }
+  return cachedInt32Memory0;
+}
+/**
+ * @param {number} id
+ */
+export function wasm_backend_free(id) {
+  try {
+    const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+    wasm.wasm_backend_free(retptr, id);
+    var r0 = getInt32Memory0()[retptr / 4 + 0];
+    var r1 = getInt32Memory0()[retptr / 4 + 1];
+    if (r1) {
+      throw takeObject(r0);
+    }
+  } finally {
+    wasm.__wbindgen_add_to_stack_pointer(16);
+  }
+}
+
 function handleError(f, args) {
   try {
     return f.apply(this, args);
@@ -534,6 +557,7 @@ function getWasmInstanceExports() {
     wasm_backend_predict,
     wasm_backend_save,
     wasm_backend_load,
+    wasm_backend_free,
   };
 }
 
@@ -49,3 +49,15 @@ export class ActivationError extends Error {
     super(`Unknown activation function: ${activation}.`);
   }
 }
+
+/**
+ * Backend Error is thrown when a native backend call fails.
+ */
+export class BackendError extends Error {
+  code: number;
+
+  constructor(code: number, message: string) {
+    super(message);
+    this.code = code;
+  }
+}
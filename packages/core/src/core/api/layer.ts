@@ -3,9 +3,15 @@ import type { Activation, Init, LayerType } from "../types.ts";
 import type { Rank, Shape, Shape1D, Shape2D, Shape3D, Shape4D } from "./shape.ts";
 
 /**
- * Layer is the base type for all layers.
+ * Layer is the base type for all layers. An optional `name` keys the
+ * layer's saved weights, e.g. `{ ...DenseLayer({ size: [8] }), name:
+ * "encoder.dense1" }` saves `encoder.dense1.weight`. Unnamed layers are
+ * numbered per type (`dense0`, `dense1`, ...). Layers with `trainable:
+ * false` keep their weights and BatchNorm statistics fixed during training.
  */
-export type Layer =
+export type Layer = LayerBase & { name?: string; trainable?: boolean };
+
+type LayerBase =
   | { type: LayerType.Activation; config: ActivationLayerConfig }
   | { type: LayerType.Conv1D; config: Conv1DLayerConfig }
   | { type: LayerType.Conv2D; config: Conv2DLayerConfig }
@@ -17,6 +23,7 @@ export type Layer =
   | { type: LayerType.Embedding; config: EmbeddingLayerConfig }
   | { type: LayerType.Flatten }
   | { type: LayerType.LSTM; config: LSTMLayerConfig }
+  | { type: LayerType.GRU; config: GRULayerConfig }
   | { type: LayerType.Pool2D; config: Pool2DLayerConfig }
   | { type: LayerType.BatchNorm1D; config: BatchNormLayerConfig }
   | { type: LayerType.BatchNorm2D; config: BatchNormLayerConfig }
@@ -56,6 +63,12 @@ export interface LSTMLayerConfig {
   recurrentActivation?: Activation;
 }
 
+/**
+ * The configuration for a GRU layer. It takes the same options as an LSTM
+ * layer, with `activation` applied to the candidate state.
+ */
+export type GRULayerConfig = LSTMLayerConfig;
+
   /**
  * The configuration for a dense layer.
  */
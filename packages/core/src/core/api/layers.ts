@@ -8,6 +8,7 @@ import {
   type EmbeddingLayerConfig,
   type Conv1DLayerConfig,
   type ConvTranspose1DLayerConfig,
+  type GRULayerConfig,
   type LSTMLayerConfig,
   type Layer,
   type Pool2DLayerConfig,
@@ -210,6 +211,15 @@ export function LSTMLayer(config: LSTMLayerConfig): Layer {
   return { type: LayerType.LSTM, config };
 }
 
+/**
+ * Creates a GRU layer.
+ * GRU layers are recurrent layers with an update and a reset gate, lighter
+ * than LSTM layers.
+ */
+export function GRULayer(config: GRULayerConfig): Layer {
+  return { type: LayerType.GRU, config };
+}
+
 /**
  * Creates a BatchNorm1D layer.
  * BatchNorm1D layers normalize the input.
@@ -1,10 +1,20 @@
 import { OptimizerType } from "../types.ts";
 
 export type Optimizer =
-  | { type: OptimizerType.SGD }
+  | { type: OptimizerType.SGD; config?: SGDOptimizerConfig }
   | { type: OptimizerType.Adam; config: AdamOptimizerConfig }
   | { type: OptimizerType.Nadam; config: AdamOptimizerConfig }
-  | { type: OptimizerType.RMSProp; config: RMSPropOptimizerConfig };
+  | { type: OptimizerType.RMSProp; config: RMSPropOptimizerConfig }
+  | { type: OptimizerType.AdamW; config: AdamWOptimizerConfig }
+  | { type: OptimizerType.Adagrad; config: AdagradOptimizerConfig }
+  | { type: OptimizerType.Adadelta; config: AdadeltaOptimizerConfig }
+  | { type: OptimizerType.AdaBelief; config: AdamOptimizerConfig }
+  | { type: OptimizerType.Lion; config: LionOptimizerConfig };
+
+export interface SGDOptimizerConfig {
+  momentum?: number;
+  nesterov?: boolean;
+}
 
 export interface AdamOptimizerConfig {
   beta1?: number;
@@ -17,8 +27,30 @@ export interface RMSPropOptimizerConfig {
   epsilon?: number;
 }
 
-export function SGDOptimizer(): Optimizer {
-  return { type: OptimizerType.SGD };
+export interface AdamWOptimizerConfig extends AdamOptimizerConfig {
+  weightDecay?: number;
+}
+
+export interface AdagradOptimizerConfig {
+  epsilon?: number;
+}
+
+export interface AdadeltaOptimizerConfig {
+  rho?: number;
+  epsilon?: number;
+}
+
+export interface LionOptimizerConfig {
+  beta1?: number;
+  beta2?: number;
+  weightDecay?: number;
+}
+
+export function SGDOptimizer(config?: SGDOptimizerConfig): Optimizer {
+  if (!config) return { type: OptimizerType.SGD };
+  config.momentum = config.momentum || 0;
+  config.nesterov = config.nesterov || false;
+  return { type: OptimizerType.SGD, config };
 }
 
 export function AdamOptimizer(config: AdamOptimizerConfig = {}): Optimizer {
@@ -42,3 +74,40 @@ export function RMSPropOptimizer(
   config.epsilon = config.epsilon || 1e-8;
   return { type: OptimizerType.RMSProp, config };
 }
+
+export function AdamWOptimizer(config: AdamWOptimizerConfig = {}): Optimizer {
+  config.beta1 = config.beta1 || 0.9;
+  config.beta2 = config.beta2 || 0.999;
+  config.epsilon = config.epsilon || 1e-8;
+  config.weightDecay = config.weightDecay ?? 0.01;
+  return { type: OptimizerType.AdamW, config };
+}
+
+export function AdagradOptimizer(
+  config: AdagradOptimizerConfig = {},
+): Optimizer {
+  config.epsilon = config.epsilon || 1e-10;
+  return { type: OptimizerType.Adagrad, config };
+}
+
+export function AdadeltaOptimizer(
+  config: AdadeltaOptimizerConfig = {},
+): Optimizer {
+  config.rho = config.rho || 0.9;
+  config.epsilon = config.epsilon || 1e-6;
+  return { type: OptimizerType.Adadelta, config };
+}
+
+export function AdaBeliefOptimizer(config: AdamOptimizerConfig = {}): Optimizer {
+  config.beta1 = config.beta1 || 0.9;
+  config.beta2 = config.beta2 || 0.999;
+  config.epsilon = config.epsilon || 1e-16;
+  return { type: OptimizerType.AdaBelief, config };
+}
+
+export function LionOptimizer(config: LionOptimizerConfig = {}): Optimizer {
+  config.beta1 = config.beta1 || 0.9;
+  config.beta2 = config.beta2 || 0.99;
+  config.weightDecay = config.weightDecay ?? 0;
+  return { type: OptimizerType.Lion, config };
+}
@@ -1,23 +1,66 @@
 import { SchedulerType } from "../types.ts";
 
+/**
+ * Learning rate schedule. Schedules are counted in epochs for every
+ * optimizer, however many batches an epoch has.
+ */
 export type Scheduler =
   | { type: SchedulerType.None }
   | {
     type: SchedulerType.LinearDecay | SchedulerType.ExponentialDecay;
     config: DecaySchedulerConfig;
   }
-  | { type: SchedulerType.OneCycle; config: OneCycleSchedulerConfig };
+  | { type: SchedulerType.OneCycle; config: OneCycleSchedulerConfig }
+  | {
+    type: SchedulerType.CosineAnnealing;
+    config: CosineAnnealingSchedulerConfig;
+  }
+  | { type: SchedulerType.Warmup; config: WarmupSchedulerConfig }
+  | { type: SchedulerType.MultiStep; config: MultiStepSchedulerConfig }
+  | {
+    type: SchedulerType.ReduceOnPlateau;
+    config: ReduceOnPlateauSchedulerConfig;
+  };
 
 export interface DecaySchedulerConfig {
   rate?: number;
+  /** Number of epochs between decays. */
   step_size?: number;
 }
 
 export interface OneCycleSchedulerConfig {
   max_rate?: number;
+  /** Length of each half of the cycle in epochs. */
   step_size?: number;
 }
 
+export interface CosineAnnealingSchedulerConfig {
+  minRate?: number;
+  /** Length of the first cycle in epochs. */
+  stepSize?: number;
+  multiplier?: number;
+}
+
+export interface WarmupSchedulerConfig {
+  /** Number of warmup epochs. */
+  steps?: number;
+  startFactor?: number;
+  scheduler?: Scheduler;
+}
+
+export interface MultiStepSchedulerConfig {
+  /** Epochs at which the rate is multiplied by `gamma`. */
+  milestones?: number[];
+  gamma?: number;
+}
+
+export interface ReduceOnPlateauSchedulerConfig {
+  factor?: number;
+  patience?: number;
+  threshold?: number;
+  minRate?: number;
+}
+
 export function NoScheduler(): Scheduler {
   return { type: SchedulerType.None };
 }
@@ -39,3 +82,35 @@ export function OneCycle(config: OneCycleSchedulerConfig = {}): Scheduler {
   config.step_size = config.step_size || 100;
   return { type: SchedulerType.OneCycle, config };
 }
+
+export function CosineAnnealing(
+  config: CosineAnnealingSchedulerConfig = {},
+): Scheduler {
+  config.minRate = config.minRate || 0;
+  config.stepSize = config.stepSize || 100;
+  config.multiplier = config.multiplier || 1;
+  return { type: SchedulerType.CosineAnnealing, config };
+}
+
+export function Warmup(config: WarmupSchedulerConfig = {}): Scheduler {
+  config.steps = config.steps || 100;
+  config.startFactor = config.startFactor || 0;
+  config.scheduler = config.scheduler || NoScheduler();
+  return { type: SchedulerType.Warmup, config };
+}
+
+export function MultiStep(config: MultiStepSchedulerConfig = {}): Scheduler {
+  config.milestones = config.milestones || [];
+  config.gamma = config.gamma || 0.1;
+  return { type: SchedulerType.MultiStep, config };
+}
+
+export function ReduceOnPlateau(
+  config: ReduceOnPlateauSchedulerConfig = {},
+): Scheduler {
+  config.factor = config.factor || 0.1;
+  config.patience = config.patience || 10;
+  config.threshold = config.threshold || 1e-4;
+  config.minRate = config.minRate || 0;
+  return { type: SchedulerType.ReduceOnPlateau, config };
+}
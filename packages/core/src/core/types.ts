@@ -105,6 +105,37 @@ export interface NetworkConfig {
    * Number of disappointing iterations to allow before early stopping
    */
   patience?: number;
+
+  /**
+   * Gradient clipping applied before every optimizer step.
+   */
+  clipping?: GradientClipping;
+
+  /**
+   * Seed for weight initialization, dropout masks and shuffling, making
+   * training runs reproducible.
+   */
+  seed?: number;
+}
+
+/**
+ * Limits for gradient clipping. Any combination may be set.
+ */
+export interface GradientClipping {
+  /**
+   * Clamp every gradient element to [-value, value].
+   */
+  value?: number;
+
+  /**
+   * Rescale each parameter gradient whose L2 norm exceeds this limit.
+   */
+  norm?: number;
+
+  /**
+   * Rescale all gradients when their combined L2 norm exceeds this limit.
+   */
+  globalNorm?: number;
 }
 
 /**
@@ -206,6 +237,11 @@ export enum OptimizerType {
   Adam = "adam",
   Nadam = "nadam",
   RMSProp = "rmsprop",
+  AdamW = "adamw",
+  Adagrad = "adagrad",
+  Adadelta = "adadelta",
+  AdaBelief = "adabelief",
+  Lion = "lion",
 }
 
 export enum SchedulerType {
@@ -213,6 +249,10 @@ export enum SchedulerType {
   LinearDecay = "lineardecay",
   ExponentialDecay = "exponentialdecay",
   OneCycle = "onecycle",
+  CosineAnnealing = "cosineannealing",
+  Warmup = "warmup",
+  MultiStep = "multistep",
+  ReduceOnPlateau = "reduceonplateau",
 }
 
 /**
@@ -238,6 +278,7 @@ export enum LayerType {
   Embedding = "embedding",
   Flatten = "flatten",
   LSTM = "lstm",
+  GRU = "gru",
   Softmax = "softmax",
 }
 